@@ -0,0 +1,114 @@
+//! Compares sparse set and table storage on particle-like entities: `cargo bench --bench ecs_storage`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use BnanR::ecs::component::StorageType;
+use BnanR::ecs::{Component, World};
+
+const ENTITY_COUNTS: [usize; 2] = [100_000, 250_000];
+const ITERATIONS: u32 = 50;
+
+#[derive(Clone, Copy)]
+struct Position<const TABLE: bool>([f32; 3]);
+
+#[derive(Clone, Copy)]
+struct Velocity<const TABLE: bool>([f32; 3]);
+
+#[derive(Clone, Copy)]
+struct Lifetime<const TABLE: bool>(f32);
+
+macro_rules! impl_bench_component {
+    ($($t:ident),+) => {
+        $(
+            impl Component for $t<false> {}
+
+            impl Component for $t<true> {
+                const STORAGE: StorageType = StorageType::Table;
+            }
+        )+
+    };
+}
+
+impl_bench_component!(Position, Velocity, Lifetime);
+
+fn time(iterations: u32, mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+    start.elapsed() / iterations
+}
+
+fn spawn_particles<const TABLE: bool>(world: &mut World, count: usize)
+where
+    Position<TABLE>: Component,
+    Velocity<TABLE>: Component,
+    Lifetime<TABLE>: Component,
+{
+    for i in 0..count {
+        let entity = world.spawn();
+        world.insert(entity, Position::<TABLE>([i as f32, 0.0, 0.0]));
+        world.insert(entity, Velocity::<TABLE>([0.0, 1.0, 0.0]));
+
+        // a third of the particles expire, the rest live forever
+        if i % 3 == 0 {
+            world.insert(entity, Lifetime::<TABLE>(1.0));
+        }
+    }
+}
+
+fn bench_backend<const TABLE: bool>(name: &str, count: usize)
+where
+    Position<TABLE>: Component,
+    Velocity<TABLE>: Component,
+    Lifetime<TABLE>: Component,
+{
+    let spawn = time(5, || {
+        let mut world = World::new();
+        spawn_particles::<TABLE>(&mut world, count);
+        black_box(&world);
+    });
+
+    let mut world = World::new();
+    spawn_particles::<TABLE>(&mut world, count);
+
+    let integrate = time(ITERATIONS, || {
+        for (_, (mut position, velocity)) in world.query::<(&mut Position<TABLE>, &Velocity<TABLE>)>() {
+            for axis in 0..3 {
+                position.0[axis] += velocity.0[axis] * 0.016;
+            }
+        }
+    });
+
+    let expire = time(ITERATIONS, || {
+        for (_, (position, mut lifetime)) in world.query::<(&Position<TABLE>, &mut Lifetime<TABLE>)>() {
+            lifetime.0 -= 0.016 * black_box(position.0[1]).signum();
+        }
+    });
+
+    let read = time(ITERATIONS, || {
+        let sum: f32 = world.query_ref::<(&Position<TABLE>, &Velocity<TABLE>, Option<&Lifetime<TABLE>>)>()
+            .map(|(_, (position, velocity, lifetime))| position.0[0] + velocity.0[1] + lifetime.map_or(0.0, |lifetime| lifetime.0))
+            .sum();
+        black_box(sum);
+    });
+
+    let entities: Vec<_> = world.entities().iter().step_by(10).collect();
+    let churn = time(ITERATIONS, || {
+        for &entity in &entities {
+            if let Some(lifetime) = world.remove::<Lifetime<TABLE>>(entity) {
+                world.insert(entity, lifetime);
+            }
+        }
+    });
+
+    println!("{name:>6} {count:>7} | spawn {spawn:>10.2?} | integrate {integrate:>10.2?} | expire {expire:>10.2?} | read 3 {read:>10.2?} | churn {churn:>10.2?}");
+}
+
+fn main() {
+    for count in ENTITY_COUNTS {
+        bench_backend::<false>("sparse", count);
+        bench_backend::<true>("table", count);
+    }
+}
@@ -9,7 +9,7 @@ use russimp::scene::*;
 use image::ImageReader;
 use exr::prelude::*;
 
-use BnanR::fs::bpk::{BpkArchive, BpkEntryData, BpkNode};
+use BnanR::fs::bpk::{BpkArchive, BpkAssetId, BpkEntryData, BpkNode};
 use BnanR::core::bnan_mesh::Vertex;
 
 mod meshlet_processor;
@@ -81,6 +81,11 @@ enum Commands {
         internal_path: String,
     },
 
+    Compact {
+        #[arg(help = "Path to the .bpk archive")]
+        archive_path: std::path::PathBuf,
+    },
+
     Read {
         #[arg(help = "Path to the .bpk archive")]
         archive_path: std::path::PathBuf,
@@ -119,7 +124,7 @@ fn main() -> anyhow::Result<()> {
         Commands::List { archive_path } => {
             let archive = BpkArchive::open(&archive_path)?;
             println!("Contents of {:?}:", archive_path);
-            list_recursive(&archive.root, "");
+            list_recursive(archive.root()?, "");
         }
         Commands::Add { archive_path, source_path, internal_path, overwrite } => {
             let mut archive = if archive_path.exists() {
@@ -128,14 +133,14 @@ fn main() -> anyhow::Result<()> {
                  BpkArchive::new()
             };
 
-            if !overwrite && archive.get_node(&internal_path).is_some() {
+            if !overwrite && archive.get_node(&internal_path)?.is_some() {
                  return Err(anyhow!("Path '{}' already exists in archive. Use --overwrite to replace.", internal_path));
             }
 
             let data = std::fs::read(&source_path).context(format!("Failed to read source file {:?}", source_path))?;
             
             archive.add_item(&internal_path, data)?;
-            archive.save(&archive_path)?;
+            write_archive(&mut archive, &archive_path)?;
             println!("Added '{}' to archive.", internal_path);
         }
         Commands::AddMesh { archive_path, mesh_path, internal_dir } => {
@@ -203,7 +208,7 @@ fn main() -> anyhow::Result<()> {
                 let ind_path = format!("{}/indices", internal_dir);
                 archive.add_buffer(&ind_path, 4, indices.len() as u32, indices_bytes)?;
 
-                archive.save(&archive_path)?;
+                write_archive(&mut archive, &archive_path)?;
                 println!("Imported mesh to '{}'", internal_dir);
 
             } else {
@@ -290,20 +295,27 @@ fn main() -> anyhow::Result<()> {
                 archive.add_image(&internal_path, width, height, 1, vk::Format::R8G8B8A8_SRGB, data)?;
             }
             
-            archive.save(&archive_path)?;
+            write_archive(&mut archive, &archive_path)?;
             println!("Imported image to '{}'", internal_path);
         }
         Commands::Remove { archive_path, internal_path } => {
              let mut archive = BpkArchive::open(&archive_path)?;
-             match archive.get_node(&internal_path) {
+             match archive.get_node(&internal_path)? {
                  Some(_) => {
                      archive.remove_item(&internal_path)?;
-                     archive.save(&archive_path)?;
+                     write_archive(&mut archive, &archive_path)?;
                      println!("Removed '{}' from archive.", internal_path);
                  }
                  None => println!("Path '{}' not found in archive.", internal_path),
              }
         }
+        Commands::Compact { archive_path } => {
+            let mut archive = BpkArchive::open(&archive_path)?;
+            let before = std::fs::metadata(&archive_path)?.len();
+
+            archive.compact()?;
+            println!("Compacted {:?} from {} to {} bytes", archive_path, before, std::fs::metadata(&archive_path)?.len());
+        }
         Commands::Read { archive_path, internal_path, output } => {
             let mut archive = BpkArchive::open(&archive_path)?;
             let data = archive.read_file(&internal_path)?;
@@ -382,7 +394,7 @@ fn main() -> anyhow::Result<()> {
                 // Store in archive
                 println!("Saving to archive...");
                 archive.add_meshlet_dag(&internal_dir, &dag, &raw_data)?;
-                archive.save(&archive_path)?;
+                write_archive(&mut archive, &archive_path)?;
                 
                 println!("Imported meshlet mesh to '{}'", internal_dir);
                 println!("  {} total meshlet nodes", dag.nodes.len());
@@ -397,6 +409,15 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Appends to archives already in the current format, older ones and new ones are written in full
+fn write_archive(archive: &mut BpkArchive, archive_path: &std::path::Path) -> anyhow::Result<()> {
+    if archive.can_append() {
+        archive.append()
+    } else {
+        archive.save(archive_path)
+    }
+}
+
 fn list_recursive(node: &BpkNode, parent_path: &str) {
     if let BpkNode::Directory { children } = node {
         for child in children {
@@ -411,11 +432,12 @@ fn list_recursive(node: &BpkNode, parent_path: &str) {
                     println!("DIR  {}", full_path);
                     list_recursive(&child.node, &full_path);
                 },
-                BpkNode::File { data, .. } => {
+                BpkNode::File { data, info } => {
+                    let id = BpkAssetId::from_path(&full_path);
 
                     match data {
-                        BpkEntryData::OnDisk { size, .. } => { println!("FILE {} ({} bytes)", full_path, size) }
-                        BpkEntryData::InMemory( data ) => { println!("FILE {} ({} bytes)", full_path, data.len()) }
+                        BpkEntryData::OnDisk { size, .. } => { println!("FILE {} ({} bytes, {:?}, id {})", full_path, size, info.codec, id) }
+                        BpkEntryData::InMemory( data ) => { println!("FILE {} ({} bytes, {:?}, id {})", full_path, data.len(), info.codec, id) }
                     }
                 }
             }
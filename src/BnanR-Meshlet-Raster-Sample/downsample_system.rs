@@ -49,8 +49,9 @@ impl WindowObserver<(i32, i32)> for DownsampleSystem {
         let (width, height) = data;
         
         unsafe {
+            // the images may still be in use on the async compute queue
             let device_guard = self.device.lock().unwrap();
-            device_guard.device.queue_wait_idle(device_guard.graphics_queue).unwrap();
+            device_guard.device.device_wait_idle().unwrap();
         }
         
         let extent = vk::Extent2D::default()
@@ -98,9 +99,7 @@ impl DownsampleSystem {
     ) -> Result<DownsampleSystem> {
         let swapchain_extent: vk::Extent2D;
         {
-            let rendergraph_guard = render_graph.lock().unwrap();
-            let swapchain_guard = rendergraph_guard.swapchain.lock().unwrap();
-            swapchain_extent = swapchain_guard.extent;
+            swapchain_extent = render_graph.lock().unwrap().get_extent();
         }
         
         // Create the hi-z mip chain images
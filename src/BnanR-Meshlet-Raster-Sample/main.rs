@@ -1,5 +1,7 @@
 mod meshlet_system;
 mod downsample_system;
+#[path = "../BnanR-Sample-Common/screenshot.rs"]
+mod screenshot;
 
 use ash::*;
 use cgmath::num_traits::FloatConst;
@@ -11,10 +13,11 @@ use BnanR::core::bnan_device::BnanDevice;
 use BnanR::core::bnan_swapchain::BnanSwapchain;
 use BnanR::core::bnan_window::{BnanWindow, WindowObserver};
 use BnanR::core::bnan_render_graph::graph::BnanRenderGraph;
-use BnanR::core::bnan_render_graph::pass::{RenderPassResource, RenderPass};
+use BnanR::core::bnan_render_graph::pass::{QueueType, RenderPassResource, RenderPass};
 use BnanR::core::bnan_render_graph::resource::ResourceUsage;
 use crate::downsample_system::DownsampleSystem;
 use crate::meshlet_system::MeshletSystem;
+use crate::screenshot::{save_screenshots, ScreenshotHotkey};
 
 struct Quit {
     pub quit: bool,
@@ -31,6 +34,7 @@ fn main() {
     let window = make_arcmut(BnanWindow::new(800, 600).unwrap());
 
     let quit = make_rcmut(Quit {quit: false});
+    let screenshot = make_rcmut(ScreenshotHotkey::new());
     let initial_window_extent = window.lock().unwrap().get_window_extent();
 
     let device = make_arcmut(BnanDevice::new(window.clone()).unwrap());
@@ -62,6 +66,7 @@ fn main() {
         let mut window_guard = window.lock().unwrap();
 
         window_guard.register_quit_observer(quit.clone());
+        window_guard.register_keyboard_observer(screenshot.clone());
         window_guard.register_atomic_resize_observer(swapchain.clone());
         window_guard.register_resize_observer(meshlet_system.clone());
         window_guard.register_mouse_observer(camera.clone());
@@ -97,12 +102,13 @@ fn main() {
         Box::new(move |graph, frame_info| {
             downsample_system.borrow().dispatch(graph, frame_info);
         })
-    );
+    ).on_queue(QueueType::AsyncCompute);
 
     {
         let mut render_graph_guard = render_graph.lock().unwrap();
         render_graph_guard.add_pass(main_pass);
         render_graph_guard.add_pass(downsample_pass);
+        render_graph_guard.enable_profiling(true).unwrap();
     }
 
     let mut count = 0;
@@ -110,6 +116,12 @@ fn main() {
     while !quit.borrow().quit {
         window.lock().unwrap().process_events();
 
+        let take_screenshot = screenshot.borrow_mut().take_request();
+        if take_screenshot {
+            let backbuffer = render_graph.lock().unwrap().get_backbuffer_handle();
+            render_graph.lock().unwrap().request_readback(&backbuffer, 0);
+        }
+
         match render_graph.lock().unwrap().execute() {
             Ok(_) => {},
 
@@ -122,6 +134,15 @@ fn main() {
                 println!("Render Graph Error: {:?}", e);
             }
         }
+
+        if take_screenshot && let Err(e) = save_screenshots(&mut render_graph.lock().unwrap()) {
+            eprintln!("Failed to save screenshot: {:?}", e);
+        }
+
+        count += 1;
+        if count % 120 == 0 && let Some(profiler) = &render_graph.lock().unwrap().profiler {
+            println!("{}", profiler.format_report());
+        }
     }
 
     unsafe { device.lock().unwrap().device.device_wait_idle().unwrap() };
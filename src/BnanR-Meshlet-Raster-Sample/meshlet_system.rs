@@ -89,8 +89,9 @@ impl WindowObserver<(i32, i32)> for MeshletSystem {
         let (width, height) = data;
 
         unsafe {
+            // the images may still be in use on the async compute queue
             let device_guard = self.device.lock().unwrap();
-            device_guard.device.queue_wait_idle(device_guard.graphics_queue).unwrap();
+            device_guard.device.device_wait_idle().unwrap();
         }
 
         let extent = vk::Extent2D::default()
@@ -147,9 +148,7 @@ impl MeshletSystem {
     pub fn new(device: ArcMut<BnanDevice>, render_graph: ArcMut<BnanRenderGraph>) -> Result<MeshletSystem> {
         let swapchain_extent: vk::Extent2D;
         {
-            let rendergraph_guard = render_graph.lock().unwrap();
-            let swapchain_guard = rendergraph_guard.swapchain.lock().unwrap();
-            swapchain_extent = swapchain_guard.extent;
+            swapchain_extent = render_graph.lock().unwrap().get_extent();
         }
 
         let streaming_buffer = BnanStreamingBuffer::new(
@@ -225,7 +224,7 @@ impl MeshletSystem {
     }
 
     pub fn load_meshlet_mesh(&mut self, archive_path: &str, mesh_name: &str) -> Result<()> {
-        let mut archive = BpkArchive::open(archive_path)?;
+        let archive = BpkArchive::open_mapped(archive_path)?;
         
         let dag = archive.load_meshlet_dag(mesh_name)?;
         
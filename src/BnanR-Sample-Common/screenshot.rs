@@ -0,0 +1,56 @@
+use anyhow::*;
+use sdl3_sys::everything::{SDL_GetKeyboardState, SDL_SCANCODE_F12};
+
+use BnanR::core::bnan_render_graph::graph::BnanRenderGraph;
+use BnanR::core::bnan_window::WindowObserver;
+
+/// F12 screenshot key, register it as a keyboard observer and poll `take_request` once per frame
+#[derive(Default)]
+pub struct ScreenshotHotkey {
+    requested: bool,
+    key_down: bool,
+}
+
+impl ScreenshotHotkey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether F12 was pressed since the last call
+    pub fn take_request(&mut self) -> bool {
+        std::mem::take(&mut self.requested)
+    }
+}
+
+impl WindowObserver<()> for ScreenshotHotkey {
+    fn update(&mut self, _data: ()) {
+        let key_down = unsafe { *SDL_GetKeyboardState(std::ptr::null_mut()).add(SDL_SCANCODE_F12.0 as usize) };
+
+        if key_down && !self.key_down {
+            self.requested = true;
+        }
+
+        self.key_down = key_down;
+    }
+}
+
+/// Saves every finished readback of the graph to the working directory as
+/// `screenshot_<timestamp>.png`, or `.exr` for float data, and returns the paths written
+pub fn save_screenshots(render_graph: &mut BnanRenderGraph) -> Result<Vec<String>> {
+    let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut paths = Vec::new();
+
+    for (index, (_, readback)) in render_graph.take_readbacks()?.into_iter().enumerate() {
+        let extension = if readback.is_float() { "exr" } else { "png" };
+        let path = match index {
+            0 => format!("screenshot_{}.{}", timestamp, extension),
+            _ => format!("screenshot_{}_{}.{}", timestamp, index, extension),
+        };
+
+        readback.save(&path).with_context(|| format!("failed to save screenshot {}", path))?;
+        println!("Saved screenshot to {}", path);
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
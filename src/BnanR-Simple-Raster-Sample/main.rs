@@ -1,4 +1,6 @@
 mod simple_system;
+#[path = "../BnanR-Sample-Common/screenshot.rs"]
+mod screenshot;
 
 use ash::*;
 use cgmath::num_traits::FloatConst;
@@ -11,6 +13,7 @@ use BnanR::core::bnan_window::{BnanWindow, WindowObserver};
 use BnanR::core::bnan_render_graph::graph::BnanRenderGraph;
 use BnanR::core::bnan_render_graph::pass::{RenderPass, RenderPassResource};
 use BnanR::core::bnan_render_graph::resource::ResourceUsage;
+use crate::screenshot::{save_screenshots, ScreenshotHotkey};
 use crate::simple_system::SimpleSystem;
 
 struct Quit {
@@ -28,6 +31,7 @@ fn main() {
     let window = make_arcmut(BnanWindow::new(800, 600).unwrap());
 
     let quit = make_rcmut(Quit {quit: false});
+    let screenshot = make_rcmut(ScreenshotHotkey::new());
     let initial_window_extent = window.lock().unwrap().get_window_extent();
 
     let device = make_arcmut(BnanDevice::new(window.clone()).unwrap());
@@ -47,8 +51,8 @@ fn main() {
         let mut window_guard = window.lock().unwrap();
 
         window_guard.register_quit_observer(quit.clone());
+        window_guard.register_keyboard_observer(screenshot.clone());
         window_guard.register_atomic_resize_observer(swapchain.clone());
-        window_guard.register_resize_observer(system.clone());
         window_guard.register_mouse_observer(camera.clone());
         window_guard.register_keyboard_observer(camera.clone());
         window_guard.register_resize_observer(camera.clone());
@@ -79,6 +83,12 @@ fn main() {
     while !quit.borrow().quit {
         window.lock().unwrap().process_events();
 
+        let take_screenshot = screenshot.borrow_mut().take_request();
+        if take_screenshot {
+            let backbuffer = render_graph.lock().unwrap().get_backbuffer_handle();
+            render_graph.lock().unwrap().request_readback(&backbuffer, 0);
+        }
+
         match render_graph.lock().unwrap().execute() {
             Ok(_) => {},
             Err(e) => {
@@ -90,6 +100,10 @@ fn main() {
                 println!("Render Graph Error: {:?}", e);
             }
         }
+
+        if take_screenshot && let Err(e) = save_screenshots(&mut render_graph.lock().unwrap()) {
+            eprintln!("Failed to save screenshot: {:?}", e);
+        }
     }
 
     unsafe { device.lock().unwrap().device.device_wait_idle().unwrap() };
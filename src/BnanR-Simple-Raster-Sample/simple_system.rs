@@ -4,18 +4,16 @@ use anyhow::*;
 use ash::*;
 use cgmath::*;
 
-use BnanR::core::{ArcMut, make_arcmut, RcMut};
+use BnanR::core::{ArcMut, RcMut};
 use BnanR::core::bnan_buffer::BnanBuffer;
 use BnanR::core::bnan_camera::BnanCamera;
 use BnanR::core::bnan_descriptors::*;
-use BnanR::core::bnan_device::{BnanBarrierBuilder, BnanDevice, WorkQueue};
-use BnanR::core::bnan_image::BnanImage;
+use BnanR::core::bnan_device::BnanDevice;
 use BnanR::core::bnan_mesh::BnanMesh;
 use BnanR::core::bnan_pipeline::{BnanPipeline, GraphicsPipelineConfigInfo};
 use BnanR::core::bnan_rendering::{BnanFrameInfo, FRAMES_IN_FLIGHT};
-use BnanR::core::bnan_window::WindowObserver;
 use BnanR::core::bnan_render_graph::graph::BnanRenderGraph;
-use BnanR::core::bnan_render_graph::resource::ResourceHandle;
+use BnanR::core::bnan_render_graph::resource::{ImageDescription, ResourceHandle};
 use BnanR::fs::bpk::BpkArchive;
 
 const SIMPLE_VERT_FILEPATH: &str = "./build/BnanR-Sample-Shaders/simple-raster.vert.spv";
@@ -30,75 +28,51 @@ pub struct SimpleUBO {
 
 pub struct SimpleSystem {
     pub device: ArcMut<BnanDevice>,
-    pub render_graph: ArcMut<BnanRenderGraph>,
     pub depth_handle: ResourceHandle,
     pub color_handle: ResourceHandle,
     pub descriptor_pool: BnanDescriptorPool,
     pub descriptor_set_layout: BnanDescriptorSetLayout,
     pub ubo_buffers: [BnanBuffer; FRAMES_IN_FLIGHT],
-    pub depth_images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT],
-    pub color_images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT],
     pub mesh: BnanMesh,
     pub descriptor_sets: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
     pub pipeline: BnanPipeline,
 }
 
-impl WindowObserver<(i32, i32)> for SimpleSystem {
-    fn update(&mut self, data: (i32, i32)) {
-        let (width, height) = data;
-
-        unsafe {
-            let device_guard = self.device.lock().unwrap();
-            device_guard.device.queue_wait_idle(device_guard.graphics_queue).unwrap();
-        }
-
-        let extent = vk::Extent2D::default()
-            .width(width as u32)
-            .height(height as u32);
-
-        (self.depth_images, self.color_images) = Self::create_framebuffer_images(self.device.clone(), extent).unwrap();
-
-        self.render_graph.lock().unwrap().update_render_image(&self.depth_handle, self.depth_images.clone());
-        self.render_graph.lock().unwrap().update_render_image(&self.color_handle, self.color_images.clone());
-
-    }
-}
-
 impl SimpleSystem {
     pub fn new(device: ArcMut<BnanDevice>, render_graph: ArcMut<BnanRenderGraph>) -> Result<SimpleSystem> {
 
-        let swapchain_extent: vk::Extent2D;
-
-        {
-            let rendergraph_guard = render_graph.lock().unwrap();
-            let swapchain_guard = rendergraph_guard.swapchain.lock().unwrap();
-            swapchain_extent = swapchain_guard.extent;
-        }
-
         let ubo_buffers = Self::create_uniform_buffers(device.clone())?;
-        let (depth_images, color_images) = Self::create_framebuffer_images(device.clone(), swapchain_extent)?;
 
         let mesh = Self::load_mesh(device.clone(), "./build/assets.bpk", "assets/ceramic_vase_01_4k.blend")?;
 
-        let depth_handle = render_graph.lock().unwrap().import_render_image("DepthBuffer", depth_images.clone());
-        let color_handle = render_graph.lock().unwrap().import_render_image("Color", color_images.clone());
+        // multisampled targets only live for the main pass, the graph owns them and recreates them on resize
+        let depth_format = device.lock().unwrap().find_depth_format()?;
+        let color_format = vk::Format::B8G8R8A8_SRGB;
+        let sample_count = device.lock().unwrap().msaa_samples;
+
+        let depth_handle = render_graph.lock().unwrap().create_transient_image(
+            "DepthBuffer",
+            ImageDescription::new(depth_format, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT).samples(sample_count),
+        );
+
+        let color_handle = render_graph.lock().unwrap().create_transient_image(
+            "Color",
+            ImageDescription::new(color_format, vk::ImageUsageFlags::COLOR_ATTACHMENT).samples(sample_count),
+        );
         
         let descriptor_set_layout = Self::create_descriptor_set_layout(device.clone())?;
         let descriptor_pool = Self::create_descriptor_pool(device.clone())?;
         let descriptor_sets = Self::allocate_descriptor_sets(device.clone(), &descriptor_set_layout, &descriptor_pool, &ubo_buffers)?;
         
-        let pipeline = Self::create_pipeline(device.clone(), &descriptor_set_layout, color_images[0].lock().unwrap().format, depth_images[0].lock().unwrap().format)?;
+        let pipeline = Self::create_pipeline(device.clone(), &descriptor_set_layout, color_format, depth_format)?;
 
         Ok(SimpleSystem {
             device,
-            render_graph,
             depth_handle,
             color_handle,
             descriptor_pool,
             descriptor_set_layout,
             ubo_buffers,
-            depth_images,
-            color_images,
             mesh,
             descriptor_sets,
             pipeline,
@@ -181,54 +155,6 @@ impl SimpleSystem {
         Ok(buffers)
     }
 
-    fn create_framebuffer_images(device: ArcMut<BnanDevice>, extent: vk::Extent2D) -> Result<([ArcMut<BnanImage>; FRAMES_IN_FLIGHT], [ArcMut<BnanImage>; FRAMES_IN_FLIGHT])> {
-        let depth_format = device.lock().unwrap().find_depth_format()?;
-        let color_format = vk::Format::B8G8R8A8_SRGB;
-
-        let sample_count = device.lock().unwrap().msaa_samples;
-
-        let extent = vk::Extent3D::default()
-            .width(extent.width)
-            .height(extent.height)
-            .depth(1);
-
-        let depth_vec: Result<Vec<_>> = (0..FRAMES_IN_FLIGHT).map(|_|
-            Ok(make_arcmut(BnanImage::new(device.clone(), depth_format, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, vk::MemoryPropertyFlags::DEVICE_LOCAL, extent, sample_count, None)?))
-        ).collect();
-
-        let depth_images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT] = depth_vec?.try_into().map_err(|_| anyhow!("something went wrong while creating depth images"))?;
-
-        let color_vec: Result<Vec<_>> = (0..FRAMES_IN_FLIGHT).map(|_|
-            Ok(make_arcmut(BnanImage::new(device.clone(), color_format, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::MemoryPropertyFlags::DEVICE_LOCAL, extent, sample_count, None)?))
-        ).collect();
-
-        let color_images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT] = color_vec?.try_into().map_err(|_| anyhow!("something went wrong while creating depth images"))?;
-
-        unsafe {
-            let device_guard = device.lock().unwrap();
-            let fence = device_guard.device.create_fence(&vk::FenceCreateInfo::default(), None)?;
-
-            let command_buffer = device_guard.begin_commands(WorkQueue::GRAPHICS, 1)?[0];
-
-            let mut builder = BnanBarrierBuilder::new();
-
-            for image in &depth_images {
-                builder.transition_image_layout(image.lock().unwrap().image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, None, None)?;
-            }
-
-            for image in &color_images {
-                builder.transition_image_layout(image.lock().unwrap().image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, None, None)?;
-            }
-
-            builder.record(&*device_guard, command_buffer);
-
-            device_guard.submit_commands(WorkQueue::GRAPHICS, vec![command_buffer], None, Some(fence))?;
-            device_guard.device.wait_for_fences(&[fence], true, u64::MAX)?;
-        }
-
-        Ok((depth_images, color_images))
-    }
-
     fn create_descriptor_set_layout(device: ArcMut<BnanDevice>) -> Result<BnanDescriptorSetLayout> {
         BnanDescriptorSetLayoutBuilder::new(vk::DescriptorSetLayoutCreateFlags::empty())
             .add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
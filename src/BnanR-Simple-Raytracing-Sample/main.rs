@@ -1,4 +1,6 @@
 mod simple_system;
+#[path = "../BnanR-Sample-Common/screenshot.rs"]
+mod screenshot;
 
 use ash::*;
 
@@ -10,6 +12,7 @@ use BnanR::core::bnan_render_graph::graph::BnanRenderGraph;
 use BnanR::core::bnan_render_graph::pass::{RenderPass, RenderPassResource};
 use BnanR::core::bnan_render_graph::resource::ResourceUsage;
 
+use crate::screenshot::{save_screenshots, ScreenshotHotkey};
 use crate::simple_system::SimpleSystem;
 
 struct Quit {
@@ -27,6 +30,7 @@ fn main() {
     let window = make_arcmut(BnanWindow::new(800, 600).unwrap());
     
     let quit = make_rcmut(Quit {quit: false});
+    let screenshot = make_rcmut(ScreenshotHotkey::new());
     let initial_window_extent = window.lock().unwrap().get_window_extent();
     
     let device = make_arcmut(BnanDevice::new(window.clone()).unwrap());
@@ -38,6 +42,7 @@ fn main() {
     simple_system.borrow_mut().update_storage_buffers().unwrap();
 
     window.lock().unwrap().register_quit_observer(quit.clone());
+    window.lock().unwrap().register_keyboard_observer(screenshot.clone());
     window.lock().unwrap().register_atomic_resize_observer(swapchain.clone());
     window.lock().unwrap().register_resize_observer(simple_system.clone());
     
@@ -60,6 +65,12 @@ fn main() {
     while !quit.borrow().quit {
         window.lock().unwrap().process_events();
 
+        let take_screenshot = screenshot.borrow_mut().take_request();
+        if take_screenshot {
+            let backbuffer = render_graph.get_backbuffer_handle();
+            render_graph.request_readback(&backbuffer, 0);
+        }
+
         match render_graph.execute() {
             Ok(_) => {},
             Err(e) => {
@@ -71,6 +82,10 @@ fn main() {
                  println!("Render Graph Error: {:?}", e);
             }
         }
+
+        if take_screenshot && let Err(e) = save_screenshots(&mut render_graph) {
+            eprintln!("Failed to save screenshot: {:?}", e);
+        }
     }
     
     let q = device.lock().unwrap().graphics_queue;
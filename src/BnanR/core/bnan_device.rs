@@ -1,5 +1,5 @@
 use std::collections::HashSet;
-use std::ffi::{c_char, c_void, CStr};
+use std::ffi::{c_char, c_void, CStr, CString};
 use std::ptr::slice_from_raw_parts;
 
 use anyhow::*;
@@ -38,6 +38,7 @@ pub struct BnanDevice {
     pub transfer_queue: vk::Queue,
     pub device: Device,
     pub allocator: Allocator,
+    pub debug_utils: Option<ext::debug_utils::Device>,
 }
 
 pub enum WorkQueue {
@@ -111,12 +112,14 @@ pub extern "system" fn debug_callback(debug_utils_message_severity_flags_ext: vk
 
 pub struct BnanBarrierBuilder {
     image_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+    buffer_barriers: Vec<vk::BufferMemoryBarrier2<'static>>,
 }
 
 impl BnanBarrierBuilder {
     pub fn new() -> Self {
         Self {
             image_barriers: Vec::new(),
+            buffer_barriers: Vec::new(),
         }
     }
     
@@ -174,12 +177,50 @@ impl BnanBarrierBuilder {
         Ok(self)
     }
     
+    pub fn get_image_barriers(&self) -> &[vk::ImageMemoryBarrier2<'static>] {
+        &self.image_barriers
+    }
+
+    pub fn get_buffer_barriers(&self) -> &[vk::BufferMemoryBarrier2<'static>] {
+        &self.buffer_barriers
+    }
+
     /// Push a pre-built image memory barrier directly
     pub fn push_barrier(&mut self, barrier: vk::ImageMemoryBarrier2<'static>) -> &mut Self {
         self.image_barriers.push(barrier);
         self
     }
     
+    /// Barrier over the whole buffer between two stage/access pairs
+    pub fn buffer_barrier(
+        &mut self,
+        buffer: vk::Buffer,
+        src_stage: vk::PipelineStageFlags2,
+        src_access: vk::AccessFlags2,
+        dst_stage: vk::PipelineStageFlags2,
+        dst_access: vk::AccessFlags2,
+    ) -> &mut Self {
+        let barrier = vk::BufferMemoryBarrier2::default()
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access)
+            .dst_stage_mask(dst_stage)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED);
+
+        self.buffer_barriers.push(barrier);
+        self
+    }
+
+    /// Push a pre-built buffer memory barrier directly
+    pub fn push_buffer_barrier(&mut self, barrier: vk::BufferMemoryBarrier2<'static>) -> &mut Self {
+        self.buffer_barriers.push(barrier);
+        self
+    }
+    
     pub fn flush_writes(
         &mut self,
         image: vk::Image,
@@ -221,19 +262,21 @@ impl BnanBarrierBuilder {
     }
     
     pub fn is_empty(&self) -> bool {
-        self.image_barriers.is_empty()
+        self.image_barriers.is_empty() && self.buffer_barriers.is_empty()
     }
     
     pub fn record(&mut self, device: &BnanDevice, command_buffer: vk::CommandBuffer) {
-        if self.image_barriers.is_empty() {
+        if self.is_empty() {
             return;
         }
 
         let dependency_info =  vk::DependencyInfo::default()
-            .image_memory_barriers(&self.image_barriers);
+            .image_memory_barriers(&self.image_barriers)
+            .buffer_memory_barriers(&self.buffer_barriers);
 
         unsafe { device.device.cmd_pipeline_barrier2(command_buffer, &dependency_info) };
         self.image_barriers.clear();
+        self.buffer_barriers.clear();
     }
 }
 
@@ -254,15 +297,16 @@ impl Drop for BnanDevice {
             unsafe { debug_utils_instance.destroy_debug_utils_messenger(self.debug_messenger, None); }
         }
 
-        let surface_instance = khr::surface::Instance::new(&*ENTRY, &self.instance);
-        unsafe { surface_instance.destroy_surface(self.surface, None) };
+        if !self.is_headless() {
+            let surface_instance = khr::surface::Instance::new(&ENTRY, &self.instance);
+            unsafe { surface_instance.destroy_surface(self.surface, None) };
+        }
     }
 }
 
 impl BnanDevice {
     pub fn new(window: ArcMut<BnanWindow>) -> Result<BnanDevice> {
-        let mut instance = Self::create_instance()?;
-        let thread_pool = Self::create_thread_pool()?;
+        let mut instance = Self::create_instance(false)?;
 
         let debug_messenger = match ENABLE_VALIDATION_LAYERS {
             true => Self::setup_debug_messenger(&instance)?,
@@ -270,14 +314,37 @@ impl BnanDevice {
         };
 
         let mut pwindow = window.lock().expect("failed to unlock window mutex");
-
         let surface = Self::create_surface(&mut *pwindow, &mut instance)?;
+
+        Self::create(instance, debug_messenger, surface)
+    }
+
+    /// Creates a device without a window or surface, for offscreen rendering and batch work.
+    /// No surface extensions are enabled and present support is not required; swapchain queries will fail.
+    pub fn new_headless() -> Result<BnanDevice> {
+        let instance = Self::create_instance(true)?;
+
+        let debug_messenger = match ENABLE_VALIDATION_LAYERS {
+            true => Self::setup_debug_messenger(&instance)?,
+            false => vk::DebugUtilsMessengerEXT::null()
+        };
+
+        Self::create(instance, debug_messenger, vk::SurfaceKHR::null())
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface == vk::SurfaceKHR::null()
+    }
+
+    fn create(instance: Instance, debug_messenger: vk::DebugUtilsMessengerEXT, surface: vk::SurfaceKHR) -> Result<BnanDevice> {
+        let thread_pool = Self::create_thread_pool()?;
+
         let physical_device = Self::pick_physical_device(&instance, surface)?;
 
         let indices = Self::find_queue_families(&instance, surface, physical_device)?;
         let msaa_samples = Self::get_msaa_sample_count(&instance, physical_device)?;
 
-        let device = Self::create_logical_device(&instance, &indices, physical_device)?;
+        let device = Self::create_logical_device(&instance, &indices, physical_device, surface)?;
         let allocator = Self::create_allocator(&instance, &device, physical_device)?;
 
         let graphics_queue = Self::get_graphics_queue(&device, &indices)?;
@@ -286,6 +353,11 @@ impl BnanDevice {
         
         let command_pools = Self::create_command_pools(&device, &indices)?;
 
+        let debug_utils = match ENABLE_VALIDATION_LAYERS || Self::is_debug_utils_available() {
+            true => Some(ext::debug_utils::Device::new(&instance, &device)),
+            false => None,
+        };
+
         Ok(
             BnanDevice {
                 instance,
@@ -299,11 +371,47 @@ impl BnanDevice {
                 compute_queue,
                 transfer_queue,
                 device,
-                allocator
+                allocator,
+                debug_utils,
             }
         )
     }
 
+    /// Opens a named region in the command buffer for capture tools, does nothing without VK_EXT_debug_utils
+    pub fn begin_debug_label(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(&name)
+            .color(color);
+
+        unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+    }
+
+    pub fn end_debug_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils) = &self.debug_utils {
+            unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+
+    /// Nanoseconds per timestamp tick
+    pub fn get_timestamp_period(&self) -> f32 {
+        unsafe { self.instance.get_physical_device_properties(self.physical_device).limits.timestamp_period }
+    }
+
+    /// Number of meaningful bits in timestamps written on the queue family, 0 when timestamps are unsupported
+    pub fn get_timestamp_valid_bits(&self, queue_family: u32) -> u32 {
+        let queue_families = unsafe { self.instance.get_physical_device_queue_family_properties(self.physical_device) };
+        queue_families.get(queue_family as usize).map(|props| props.timestamp_valid_bits).unwrap_or(0)
+    }
+
+    pub fn supports_pipeline_statistics(&self) -> bool {
+        unsafe { self.instance.get_physical_device_features(self.physical_device).pipeline_statistics_query == vk::TRUE }
+    }
+
     pub fn begin_commands(&self, work_queue: WorkQueue, num_command_buffers: u32) -> Result<Vec<vk::CommandBuffer>> {
         let command_pool = match work_queue {
             WorkQueue::GRAPHICS => self.command_pools[Self::GRAPHICS_COMMAND_POOL],
@@ -410,6 +518,10 @@ impl BnanDevice {
     }
 
     pub fn get_swapchain_support(&self) -> Result<SwapChainSupportDetails> {
+        if self.is_headless() {
+            bail!("headless device has no surface to query swapchain support for");
+        }
+
         Self::query_swapchain_support(&self.instance, self.physical_device, self.surface)
     }
 
@@ -417,7 +529,7 @@ impl BnanDevice {
         Self::find_queue_families(&self.instance, self.surface, self.physical_device)
     }
 
-    fn create_instance() -> Result<Instance> {
+    fn create_instance(headless: bool) -> Result<Instance> {
 
         unsafe {
 
@@ -428,7 +540,7 @@ impl BnanDevice {
                 .engine_version(vk::make_api_version(0, 1, 0, 0))
                 .api_version(vk::API_VERSION_1_3);
 
-            let extensions = Self::get_required_instance_extensions()?;
+            let extensions = Self::get_required_instance_extensions(headless)?;
 
             let mut instance_info = vk::InstanceCreateInfo::default()
                 .application_info(&appinfo)
@@ -447,15 +559,19 @@ impl BnanDevice {
         }
     }
 
-    fn get_required_instance_extensions() -> Result<Vec<*const c_char>> {
+    fn get_required_instance_extensions(headless: bool) -> Result<Vec<*const c_char>> {
         unsafe {
-            let mut count = 0u32;
-            SDL_Vulkan_GetInstanceExtensions(&mut count);
             let mut extensions = Vec::new();
-            let sdl_extensions = slice_from_raw_parts(SDL_Vulkan_GetInstanceExtensions(&mut count), count as usize).as_ref().unwrap();
-            extensions.extend_from_slice(sdl_extensions);
 
-            if ENABLE_VALIDATION_LAYERS {
+            if !headless {
+                let mut count = 0u32;
+                SDL_Vulkan_GetInstanceExtensions(&mut count);
+                let sdl_extensions = slice_from_raw_parts(SDL_Vulkan_GetInstanceExtensions(&mut count), count as usize).as_ref().unwrap();
+                extensions.extend_from_slice(sdl_extensions);
+            }
+
+            // also enabled outside validation builds so capture tools see pass labels
+            if ENABLE_VALIDATION_LAYERS || Self::is_debug_utils_available() {
                 extensions.push(c"VK_EXT_debug_utils".as_ptr());
             }
 
@@ -463,6 +579,11 @@ impl BnanDevice {
         }
     }
     
+    fn is_debug_utils_available() -> bool {
+        let extensions = unsafe { ENTRY.enumerate_instance_extension_properties(None) }.unwrap_or_default();
+        extensions.iter().any(|extension| extension.extension_name_as_c_str() == std::result::Result::Ok(ext::debug_utils::NAME))
+    }
+
     fn create_thread_pool() -> Result<ThreadPool> {
         Ok(ThreadPoolBuilder::new().build()?)
     }
@@ -499,10 +620,14 @@ impl BnanDevice {
 
         let surface_instance = khr::surface::Instance::new(&*ENTRY, instance);
 
-        // 1. Find graphics queue family (must support presentation)
+        // 1. Find graphics queue family (must support presentation unless headless)
         let graphics_candidate = queue_families.iter().enumerate().find(|(i, props)| {
-            let present_support = unsafe { surface_instance.get_physical_device_surface_support(device, *i as u32, surface) };
-            props.queue_flags.contains(vk::QueueFlags::GRAPHICS) && present_support.unwrap_or(false)
+            let present_support = match surface == vk::SurfaceKHR::null() {
+                true => true,
+                false => unsafe { surface_instance.get_physical_device_surface_support(device, *i as u32, surface).unwrap_or(false) }
+            };
+
+            props.queue_flags.contains(vk::QueueFlags::GRAPHICS) && present_support
         });
 
         if let Some((g_index, _)) = graphics_candidate {
@@ -557,7 +682,15 @@ impl BnanDevice {
         Ok(indices)
     }
 
-    fn check_device_extension_support(instance: &Instance, device: vk::PhysicalDevice) -> Result<bool> {
+    fn get_device_extensions(surface: vk::SurfaceKHR) -> Vec<&'static CStr> {
+        // swapchain is only needed when there is something to present to
+        DEVICE_EXTENSIONS.iter()
+            .filter(|extension| surface != vk::SurfaceKHR::null() || **extension != c"VK_KHR_swapchain")
+            .cloned()
+            .collect()
+    }
+
+    fn check_device_extension_support(instance: &Instance, surface: vk::SurfaceKHR, device: vk::PhysicalDevice) -> Result<bool> {
 
         let available_extensions: Vec<vk::ExtensionProperties>;
 
@@ -565,7 +698,7 @@ impl BnanDevice {
             available_extensions = instance.enumerate_device_extension_properties(device)?;
         }
 
-        let mut required_extensions_set: HashSet<&CStr> = HashSet::from_iter(DEVICE_EXTENSIONS.clone());
+        let mut required_extensions_set: HashSet<&CStr> = HashSet::from_iter(Self::get_device_extensions(surface));
 
         for extension in available_extensions.iter() {
             let extension_name = extension.extension_name_as_c_str()?;
@@ -624,17 +757,22 @@ impl BnanDevice {
             return Ok(false);
         }
 
-        if !Self::check_device_extension_support(instance, device)? {
+        if !Self::check_device_extension_support(instance, surface, device)? {
             return Ok(false);
         }
 
-        let details = Self::query_swapchain_support(instance, device, surface)?;
-        if !details.is_adequate() {
-            return Ok(false);
+        if surface != vk::SurfaceKHR::null() {
+            let details = Self::query_swapchain_support(instance, device, surface)?;
+            if !details.is_adequate() {
+                return Ok(false);
+            }
         }
 
         let mut descriptor_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
         let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut timeline_semaphore_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut host_query_reset_features = vk::PhysicalDeviceHostQueryResetFeatures::default();
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::default();
         let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default();
         let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
         let mut storage_8bit_features = vk::PhysicalDevice8BitStorageFeaturesKHR::default();
@@ -643,6 +781,9 @@ impl BnanDevice {
         let mut features2 = vk::PhysicalDeviceFeatures2::default()
             .push_next(&mut descriptor_indexing_features)
             .push_next(&mut synchronization2_features)
+            .push_next(&mut timeline_semaphore_features)
+            .push_next(&mut host_query_reset_features)
+            .push_next(&mut multiview_features)
             .push_next(&mut dynamic_rendering_features)
             .push_next(&mut mesh_shader_features)
             .push_next(&mut storage_8bit_features)
@@ -657,6 +798,9 @@ impl BnanDevice {
             descriptor_indexing_features.runtime_descriptor_array == vk::TRUE &&
             descriptor_indexing_features.descriptor_binding_variable_descriptor_count == vk::TRUE &&
             synchronization2_features.synchronization2 == vk::TRUE &&
+            timeline_semaphore_features.timeline_semaphore == vk::TRUE &&
+            host_query_reset_features.host_query_reset == vk::TRUE &&
+            multiview_features.multiview == vk::TRUE &&
             dynamic_rendering_features.dynamic_rendering == vk::TRUE &&
             mesh_shader_features.mesh_shader == vk::TRUE &&
             mesh_shader_features.task_shader == vk::TRUE &&
@@ -721,7 +865,7 @@ impl BnanDevice {
         Ok(vk::SampleCountFlags::TYPE_1)
     }
 
-    fn create_logical_device(instance: &Instance, indices: &QueueIndices, device: vk::PhysicalDevice) -> Result<Device> {
+    fn create_logical_device(instance: &Instance, indices: &QueueIndices, device: vk::PhysicalDevice, surface: vk::SurfaceKHR) -> Result<Device> {
 
         let mut family_queue_counts = std::collections::HashMap::<u32, u32>::new();
         
@@ -743,12 +887,24 @@ impl BnanDevice {
                 .queue_priorities(priorities)
         }).collect();
 
+        let supported_features = unsafe { instance.get_physical_device_features(device) };
+
         let mut device_features = vk::PhysicalDeviceFeatures2::default();
         device_features.features.sampler_anisotropy = vk::TRUE;
+        device_features.features.pipeline_statistics_query = supported_features.pipeline_statistics_query;
 
         let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::default()
             .synchronization2(true);
 
+        let mut timeline_semaphore_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default()
+            .timeline_semaphore(true);
+
+        let mut host_query_reset_features = vk::PhysicalDeviceHostQueryResetFeatures::default()
+            .host_query_reset(true);
+
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::default()
+            .multiview(true);
+
         let mut descriptor_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default()
             .descriptor_binding_partially_bound(true)
             .descriptor_binding_variable_descriptor_count(true)
@@ -767,13 +923,16 @@ impl BnanDevice {
         let mut scalar_block_layout_features = vk::PhysicalDeviceScalarBlockLayoutFeaturesEXT::default()
             .scalar_block_layout(true);
 
-        let extensions: Vec<_> = DEVICE_EXTENSIONS.iter().map(|s| s.as_ptr()).collect();
+        let extensions: Vec<_> = Self::get_device_extensions(surface).iter().map(|s| s.as_ptr()).collect();
 
         let info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
             .enabled_extension_names(&extensions)
             .push_next(&mut device_features)
             .push_next(&mut synchronization2_features)
+            .push_next(&mut timeline_semaphore_features)
+            .push_next(&mut host_query_reset_features)
+            .push_next(&mut multiview_features)
             .push_next(&mut descriptor_indexing_features)
             .push_next(&mut dynamic_rendering_features)
             .push_next(&mut mesh_shading_features)
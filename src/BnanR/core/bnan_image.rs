@@ -1,4 +1,6 @@
 
+use std::collections::HashMap;
+
 use anyhow::*;
 use ash::*;
 use vk_mem::*;
@@ -6,6 +8,42 @@ use vk_mem::*;
 use crate::core::ArcMut;
 use crate::core::bnan_device::BnanDevice;
 
+/// Array layout of an image, cube images take six layers per cube
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageLayers {
+    Single,
+    Array(u32),
+    Cube,
+    CubeArray(u32),
+}
+
+impl ImageLayers {
+    pub fn count(self) -> u32 {
+        match self {
+            ImageLayers::Single => 1,
+            ImageLayers::Array(layers) => layers.max(1),
+            ImageLayers::Cube => 6,
+            ImageLayers::CubeArray(cubes) => cubes.max(1) * 6,
+        }
+    }
+
+    pub fn view_type(self) -> vk::ImageViewType {
+        match self {
+            ImageLayers::Single => vk::ImageViewType::TYPE_2D,
+            ImageLayers::Array(_) => vk::ImageViewType::TYPE_2D_ARRAY,
+            ImageLayers::Cube => vk::ImageViewType::CUBE,
+            ImageLayers::CubeArray(_) => vk::ImageViewType::CUBE_ARRAY,
+        }
+    }
+
+    fn create_flags(self) -> vk::ImageCreateFlags {
+        match self {
+            ImageLayers::Cube | ImageLayers::CubeArray(_) => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            _ => vk::ImageCreateFlags::empty(),
+        }
+    }
+}
+
 pub struct BnanImage {
     pub device: ArcMut<BnanDevice>,
     pub image: vk::Image,
@@ -14,7 +52,9 @@ pub struct BnanImage {
     pub image_extent: vk::Extent3D,
     pub format: vk::Format,
     pub mip_levels: u32,
+    pub layers: ImageLayers,
     pub mip_views: Vec<vk::ImageView>,
+    pub subresource_views: HashMap<(u32, u32, u32), vk::ImageView>,
     pub owned: bool,
 }
 
@@ -25,7 +65,7 @@ impl Drop for BnanImage {
                 let device = self.device.lock().unwrap();
                 
                 // Destroy per-mip views
-                for view in &self.mip_views {
+                for view in self.mip_views.iter().chain(self.subresource_views.values()) {
                     device.device.destroy_image_view(*view, None);
                 }
                 
@@ -36,6 +76,9 @@ impl Drop for BnanImage {
                 if let Some(mut alloc) = self.image_allocation.take() {
                     drop(device); // Release lock before reallocating
                     self.device.lock().unwrap().allocator.destroy_image(self.image, &mut alloc);
+                } else {
+                    // aliased image, the memory is owned by whoever allocated it
+                    device.device.destroy_image(self.image, None);
                 }
             }
         }
@@ -52,18 +95,26 @@ impl BnanImage {
         sample_count: vk::SampleCountFlags,
         mip_levels: Option<u32>,
     ) -> Result<BnanImage> {
-        let mip_count = mip_levels.unwrap_or(1);
-        let (image, image_allocation) = Self::create_image(device.clone(), format, usage, properties, image_extent, sample_count, mip_count)?;
-
-        let image_aspect = match format {
-            vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
-            vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
-            vk::Format::D24_UNORM_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        Self::new_layered(device, format, usage, properties, image_extent, sample_count, mip_levels, ImageLayers::Single)
+    }
 
-            _ => vk::ImageAspectFlags::COLOR,
-        };
+    /// Creates an array or cube image, the default view covers every layer
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_layered(
+        device: ArcMut<BnanDevice>,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+        image_extent: vk::Extent3D,
+        sample_count: vk::SampleCountFlags,
+        mip_levels: Option<u32>,
+        layers: ImageLayers,
+    ) -> Result<BnanImage> {
+        let mip_count = mip_levels.unwrap_or(1);
+        let (image, image_allocation) = Self::create_image(device.clone(), format, usage, properties, image_extent, sample_count, mip_count, layers)?;
 
-        let image_view = Self::create_image_view(device.clone(), image, format, image_aspect, mip_count)?;
+        let image_aspect = Self::get_image_aspect(format);
+        let image_view = Self::create_image_view(device.clone(), image, format, image_aspect, mip_count, layers)?;
         
         Ok (BnanImage {
             device,
@@ -73,11 +124,83 @@ impl BnanImage {
             image_extent,
             format,
             mip_levels: mip_count,
+            layers,
             mip_views: Vec::new(),
+            subresource_views: HashMap::new(),
             owned: true,
         })
     }
 
+    /// Creates an image bound to memory shared with other images, see `allocate_aliasing_memory`.
+    /// The image is destroyed on drop but the memory is not freed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_aliased(
+        device: ArcMut<BnanDevice>,
+        allocation: &Allocation,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        image_extent: vk::Extent3D,
+        sample_count: vk::SampleCountFlags,
+        mip_levels: Option<u32>,
+        layers: ImageLayers,
+    ) -> Result<BnanImage> {
+        let mip_count = mip_levels.unwrap_or(1);
+        let info = Self::build_image_info(format, usage, image_extent, sample_count, mip_count, layers);
+
+        let image = unsafe { device.lock().unwrap().allocator.create_aliasing_image(allocation, &info)? };
+
+        let image_aspect = Self::get_image_aspect(format);
+        let image_view = Self::create_image_view(device.clone(), image, format, image_aspect, mip_count, layers)?;
+
+        Ok (BnanImage {
+            device,
+            image,
+            image_view,
+            image_allocation: None,
+            image_extent,
+            format,
+            mip_levels: mip_count,
+            layers,
+            mip_views: Vec::new(),
+            subresource_views: HashMap::new(),
+            owned: true,
+        })
+    }
+
+    /// Memory requirements of an image with the given description, without creating it
+    pub fn get_memory_requirements(
+        device: &BnanDevice,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        image_extent: vk::Extent3D,
+        sample_count: vk::SampleCountFlags,
+        mip_levels: Option<u32>,
+        layers: ImageLayers,
+    ) -> vk::MemoryRequirements {
+        let info = Self::build_image_info(format, usage, image_extent, sample_count, mip_levels.unwrap_or(1), layers);
+        let requirements_info = vk::DeviceImageMemoryRequirements::default().create_info(&info);
+
+        let mut requirements = vk::MemoryRequirements2::default();
+        unsafe { device.device.get_device_image_memory_requirements(&requirements_info, &mut requirements) };
+
+        requirements.memory_requirements
+    }
+
+    /// Allocates device local memory that several images can be bound to with `new_aliased`
+    pub fn allocate_aliasing_memory(device: ArcMut<BnanDevice>, requirements: &vk::MemoryRequirements) -> Result<Allocation> {
+        let allocation_info = AllocationCreateInfo {
+            required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            usage: MemoryUsage::Unknown,
+            ..Default::default()
+        };
+
+        unsafe { Ok(device.lock().unwrap().allocator.allocate_memory(requirements, &allocation_info)?) }
+    }
+
+    pub fn free_aliasing_memory(device: ArcMut<BnanDevice>, mut allocation: Allocation) {
+        unsafe { device.lock().unwrap().allocator.free_memory(&mut allocation) };
+    }
+
     pub fn from_image(device: ArcMut<BnanDevice>, image: vk::Image, image_view: vk::ImageView, format: vk::Format, image_extent: vk::Extent3D) -> BnanImage {
         BnanImage {
             device,
@@ -87,11 +210,34 @@ impl BnanImage {
             image_extent,
             format,
             mip_levels: 1,
+            layers: ImageLayers::Single,
             mip_views: Vec::new(),
+            subresource_views: HashMap::new(),
             owned: false
         }
     }
     
+    /// Size in bytes of a single texel for uncompressed color and depth formats
+    pub fn get_texel_size(format: vk::Format) -> Result<u64> {
+        match format {
+            vk::Format::R8_UNORM | vk::Format::R8_UINT | vk::Format::S8_UINT => Ok(1),
+
+            vk::Format::R8G8_UNORM | vk::Format::R16_SFLOAT | vk::Format::R16_UNORM |
+            vk::Format::R16_UINT | vk::Format::D16_UNORM => Ok(2),
+
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_UNORM |
+            vk::Format::B8G8R8A8_SRGB | vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::B10G11R11_UFLOAT_PACK32 |
+            vk::Format::R16G16_SFLOAT | vk::Format::R32_SFLOAT | vk::Format::R32_UINT |
+            vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D24_UNORM_S8_UINT => Ok(4),
+
+            vk::Format::R16G16B16A16_SFLOAT | vk::Format::R16G16B16A16_UNORM | vk::Format::R32G32_SFLOAT => Ok(8),
+            vk::Format::R32G32B32_SFLOAT => Ok(12),
+            vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT => Ok(16),
+
+            _ => bail!("unsupported format {:?}", format),
+        }
+    }
+
     /// Calculate the number of mip levels for a given extent
     pub fn calculate_mip_levels(extent: vk::Extent3D) -> u32 {
         let max_dim = extent.width.max(extent.height) as f32;
@@ -110,12 +256,7 @@ impl BnanImage {
     /// Create an image view for a specific mip level and store it internally.
     /// Returns the index of the created view in the mip_views vector.
     pub fn create_mip_view(&mut self, mip_level: u32) -> Result<usize> {
-        let image_aspect = match self.format {
-            vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
-            vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
-            vk::Format::D24_UNORM_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
-            _ => vk::ImageAspectFlags::COLOR,
-        };
+        let image_aspect = Self::get_image_aspect(self.format);
         
         let subresource_range = vk::ImageSubresourceRange::default()
             .aspect_mask(image_aspect)
@@ -137,16 +278,67 @@ impl BnanImage {
         Ok(index)
     }
 
-    fn create_image(device: ArcMut<BnanDevice>, format: vk::Format, usage: vk::ImageUsageFlags, properties: vk::MemoryPropertyFlags, extent: vk::Extent3D, sample_count: vk::SampleCountFlags, mip_levels: u32) -> Result<(vk::Image, Allocation)> {
-        let info = vk::ImageCreateInfo::default()
+    /// View of one mip level over a range of layers, for rendering into part of an array or cube.
+    /// Views are cached and destroyed with the image.
+    pub fn get_subresource_view(&mut self, mip_level: u32, base_layer: u32, layer_count: u32) -> Result<vk::ImageView> {
+        if let Some(view) = self.subresource_views.get(&(mip_level, base_layer, layer_count)) {
+            return Ok(*view);
+        }
+
+        let subresource_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(Self::get_image_aspect(self.format))
+            .base_array_layer(base_layer)
+            .layer_count(layer_count)
+            .base_mip_level(mip_level)
+            .level_count(1);
+
+        let view_type = match layer_count {
+            1 => vk::ImageViewType::TYPE_2D,
+            _ => vk::ImageViewType::TYPE_2D_ARRAY,
+        };
+
+        let info = vk::ImageViewCreateInfo::default()
+            .image(self.image)
+            .format(self.format)
+            .subresource_range(subresource_range)
+            .view_type(view_type);
+
+        let view = unsafe { self.device.lock().unwrap().device.create_image_view(&info, None)? };
+
+        self.subresource_views.insert((mip_level, base_layer, layer_count), view);
+        Ok(view)
+    }
+
+    pub fn get_layer_count(&self) -> u32 {
+        self.layers.count()
+    }
+
+    fn get_image_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+        match format {
+            vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+            vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            vk::Format::D24_UNORM_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    fn build_image_info(format: vk::Format, usage: vk::ImageUsageFlags, extent: vk::Extent3D, sample_count: vk::SampleCountFlags, mip_levels: u32, layers: ImageLayers) -> vk::ImageCreateInfo<'static> {
+        vk::ImageCreateInfo::default()
+            .flags(layers.create_flags())
             .image_type(vk::ImageType::TYPE_2D)
             .format(format)
             .extent(extent)
             .mip_levels(mip_levels)
-            .array_layers(1)
+            .array_layers(layers.count())
             .samples(sample_count)
             .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(usage);
+            .usage(usage)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_image(device: ArcMut<BnanDevice>, format: vk::Format, usage: vk::ImageUsageFlags, properties: vk::MemoryPropertyFlags, extent: vk::Extent3D, sample_count: vk::SampleCountFlags, mip_levels: u32, layers: ImageLayers) -> Result<(vk::Image, Allocation)> {
+        let info = Self::build_image_info(format, usage, extent, sample_count, mip_levels, layers);
 
         let allocation_info = AllocationCreateInfo {
             required_flags: properties,
@@ -157,12 +349,12 @@ impl BnanImage {
         unsafe { Ok(device.lock().unwrap().allocator.create_image(&info, &allocation_info)?) }
     }
 
-    fn create_image_view(device: ArcMut<BnanDevice>, image: vk::Image, format: vk::Format, aspect: vk::ImageAspectFlags, mip_levels: u32) -> Result<vk::ImageView> {
+    fn create_image_view(device: ArcMut<BnanDevice>, image: vk::Image, format: vk::Format, aspect: vk::ImageAspectFlags, mip_levels: u32, layers: ImageLayers) -> Result<vk::ImageView> {
         
         let subresource_range = vk::ImageSubresourceRange::default()
             .aspect_mask(aspect)
             .base_array_layer(0)
-            .layer_count(1)
+            .layer_count(layers.count())
             .base_mip_level(0)
             .level_count(mip_levels);
         
@@ -170,7 +362,7 @@ impl BnanImage {
             .image(image)
             .format(format)
             .subresource_range(subresource_range)
-            .view_type(vk::ImageViewType::TYPE_2D);
+            .view_type(layers.view_type());
         
         unsafe { Ok(device.lock().unwrap().device.create_image_view(&info, None)?) }
     }
@@ -0,0 +1,353 @@
+use std::path::Path;
+
+use anyhow::*;
+use ash::*;
+use half::f16;
+
+use crate::core::ArcMut;
+use crate::core::bnan_buffer::BnanBuffer;
+use crate::core::bnan_device::{BnanDevice, WorkQueue};
+use crate::core::bnan_image::BnanImage;
+use crate::core::bnan_render_graph::graph::BnanRenderGraph;
+
+/// Pixel data read back from the gpu, one entry per channel per texel.
+/// Half floats are widened to f32, BGRA formats are swizzled to RGBA.
+#[derive(Clone, Debug)]
+pub enum BnanPixelData {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    F32(Vec<f32>),
+}
+
+#[derive(Clone, Debug)]
+pub struct BnanReadback {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub channels: u32,
+    pub pixels: BnanPixelData,
+}
+
+/// Staging buffer for a single image subresource, filled by a recorded copy
+pub struct BnanReadbackBuffer {
+    pub staging_buffer: BnanBuffer,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub mip_level: u32,
+    pub layer: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ComponentType {
+    UNORM8,
+    UNORM16,
+    UINT32,
+    SFLOAT16,
+    SFLOAT32,
+}
+
+impl BnanReadbackBuffer {
+    pub fn new(device: ArcMut<BnanDevice>, image: &BnanImage, mip_level: u32, layer: u32) -> Result<BnanReadbackBuffer> {
+        if mip_level >= image.mip_levels {
+            bail!("mip level {} out of range for image with {} levels", mip_level, image.mip_levels);
+        }
+
+        if layer >= image.get_layer_count() {
+            bail!("layer {} out of range for image with {} layers", layer, image.get_layer_count());
+        }
+
+        // a buffer copy reads a single aspect, the packed depth and stencil texels have no linear layout to decode
+        let aspect_mask = BnanRenderGraph::get_aspect_mask_for_format(image.format);
+        if aspect_mask.contains(vk::ImageAspectFlags::STENCIL) {
+            bail!("readback of format {:?} with a stencil aspect is not supported", image.format);
+        }
+
+        let extent = image.mip_extent(mip_level);
+        let size = extent.width as u64 * extent.height as u64 * BnanImage::get_texel_size(image.format)?;
+
+        let staging_buffer = BnanBuffer::new(device, size, 1, vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)?;
+
+        Ok(BnanReadbackBuffer {
+            staging_buffer,
+            format: image.format,
+            extent: vk::Extent2D { width: extent.width, height: extent.height },
+            aspect_mask,
+            mip_level,
+            layer,
+        })
+    }
+
+    /// Records the image to buffer copy, the image must be in TRANSFER_SRC_OPTIMAL
+    pub fn record_copy(&self, device: &BnanDevice, command_buffer: vk::CommandBuffer, image: vk::Image) {
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: self.aspect_mask,
+                mip_level: self.mip_level,
+                base_array_layer: self.layer,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D { width: self.extent.width, height: self.extent.height, depth: 1 });
+
+        unsafe { device.device.cmd_copy_image_to_buffer(command_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.staging_buffer.buffer, &[region]); }
+    }
+
+    /// Copies the staging buffer out as raw bytes, the recorded copy must have completed
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let size = self.staging_buffer.buffer_size as usize;
+
+        self.staging_buffer.map()?;
+        self.staging_buffer.invalidate(vk::WHOLE_SIZE, 0)?;
+
+        let mut bytes = vec![0u8; size];
+        unsafe { std::ptr::copy_nonoverlapping(self.staging_buffer.mapped, bytes.as_mut_ptr(), size); }
+
+        self.staging_buffer.unmap();
+        Ok(bytes)
+    }
+
+    /// Decodes the staging buffer into typed pixels, the recorded copy must have completed
+    pub fn resolve(mut self) -> Result<BnanReadback> {
+        let bytes = self.read_bytes()?;
+        BnanReadback::from_bytes(self.format, self.extent, &bytes)
+    }
+}
+
+impl BnanReadback {
+
+    /// One shot readback outside of the render graph, transitions the image from `layout` and back again
+    pub fn capture(device: ArcMut<BnanDevice>, image: &ArcMut<BnanImage>, layout: vk::ImageLayout, mip_level: u32, layer: u32) -> Result<BnanReadback> {
+        let image_guard = image.lock().unwrap();
+        let readback_buffer = BnanReadbackBuffer::new(device.clone(), &image_guard, mip_level, layer)?;
+
+        unsafe {
+            let device_guard = device.lock().unwrap();
+            let fence = device_guard.device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+
+            let command_buffer = device_guard.begin_commands(WorkQueue::GRAPHICS, 1)?;
+
+            if layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+                let barrier = Self::build_readback_barrier(&image_guard, layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+                device_guard.device.cmd_pipeline_barrier2(command_buffer[0], &vk::DependencyInfo::default().image_memory_barriers(&[barrier]));
+            }
+
+            readback_buffer.record_copy(&device_guard, command_buffer[0], image_guard.image);
+
+            if layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL && layout != vk::ImageLayout::UNDEFINED {
+                let barrier = Self::build_readback_barrier(&image_guard, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout);
+                device_guard.device.cmd_pipeline_barrier2(command_buffer[0], &vk::DependencyInfo::default().image_memory_barriers(&[barrier]));
+            }
+
+            device_guard.submit_commands(WorkQueue::GRAPHICS, command_buffer.clone(), None, Some(fence))?;
+            device_guard.device.wait_for_fences(&[fence], true, u64::MAX)?;
+
+            device_guard.device.destroy_fence(fence, None);
+            device_guard.device.free_command_buffers(device_guard.command_pools[BnanDevice::GRAPHICS_COMMAND_POOL], &command_buffer);
+        }
+
+        readback_buffer.resolve()
+    }
+
+    /// Full barrier between any previous access and the transfer read, readback is not on a hot path
+    pub fn build_readback_barrier(image: &BnanImage, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> vk::ImageMemoryBarrier2<'static> {
+        let aspect_mask = BnanRenderGraph::get_aspect_mask_for_format(image.format);
+
+        vk::ImageMemoryBarrier2::default()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .image(image.image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: image.mip_levels,
+                base_array_layer: 0,
+                layer_count: image.get_layer_count(),
+            })
+            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+    }
+
+    pub fn from_bytes(format: vk::Format, extent: vk::Extent2D, bytes: &[u8]) -> Result<BnanReadback> {
+        let (component_type, channels, bgra) = Self::describe_format(format)?;
+
+        let mut pixels = match component_type {
+            ComponentType::UNORM8 => BnanPixelData::U8(bytes.to_vec()),
+
+            ComponentType::UNORM16 => BnanPixelData::U16(bytes.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect()),
+
+            ComponentType::UINT32 => BnanPixelData::U32(bytes.chunks_exact(4).map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect()),
+
+            ComponentType::SFLOAT16 => BnanPixelData::F32(bytes.chunks_exact(2).map(|b| f16::from_ne_bytes([b[0], b[1]]).to_f32()).collect()),
+
+            ComponentType::SFLOAT32 => BnanPixelData::F32(bytes.chunks_exact(4).map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect()),
+        };
+
+        if bgra && let BnanPixelData::U8(data) = &mut pixels {
+            data.chunks_exact_mut(4).for_each(|texel| texel.swap(0, 2));
+        }
+
+        Ok(BnanReadback {
+            format,
+            extent,
+            channels,
+            pixels,
+        })
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self.pixels, BnanPixelData::F32(_))
+    }
+
+    /// Converts to 8 bit RGBA, float data is clamped to [0, 1] without tonemapping
+    pub fn to_rgba8(&self) -> Result<Vec<u8>> {
+        let texel_count = (self.extent.width * self.extent.height) as usize;
+        let channels = self.channels as usize;
+        let mut rgba = Vec::with_capacity(texel_count * 4);
+
+        let component = |index: usize| -> Result<u8> {
+            Ok(match &self.pixels {
+                BnanPixelData::U8(data) => data[index],
+                BnanPixelData::U16(data) => (data[index] >> 8) as u8,
+                BnanPixelData::F32(data) => (data[index].clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                BnanPixelData::U32(_) => bail!("cannot convert integer format {:?} to rgba8", self.format),
+            })
+        };
+
+        for texel in 0..texel_count {
+            let base = texel * channels;
+
+            let r = component(base)?;
+            let g = if channels >= 2 { component(base + 1)? } else { r };
+            let b = if channels >= 3 { component(base + 2)? } else if channels == 1 { r } else { 0 };
+            let a = if channels >= 4 { component(base + 3)? } else { 255 };
+
+            rgba.extend_from_slice(&[r, g, b, a]);
+        }
+
+        Ok(rgba)
+    }
+
+    /// Converts to f32 RGBA, normalized formats are mapped to [0, 1]
+    pub fn to_rgba32f(&self) -> Result<Vec<[f32; 4]>> {
+        let texel_count = (self.extent.width * self.extent.height) as usize;
+        let channels = self.channels as usize;
+
+        let component = |index: usize| -> Result<f32> {
+            Ok(match &self.pixels {
+                BnanPixelData::U8(data) => data[index] as f32 / 255.0,
+                BnanPixelData::U16(data) => data[index] as f32 / 65535.0,
+                BnanPixelData::F32(data) => data[index],
+                BnanPixelData::U32(data) => data[index] as f32,
+            })
+        };
+
+        (0..texel_count).map(|texel| {
+            let base = texel * channels;
+
+            let r = component(base)?;
+            let g = if channels >= 2 { component(base + 1)? } else { r };
+            let b = if channels >= 3 { component(base + 2)? } else if channels == 1 { r } else { 0.0 };
+            let a = if channels >= 4 { component(base + 3)? } else { 1.0 };
+
+            Ok([r, g, b, a])
+        }).collect()
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let rgba = self.to_rgba8()?;
+        image::save_buffer_with_format(path, &rgba, self.extent.width, self.extent.height, image::ExtendedColorType::Rgba8, image::ImageFormat::Png)?;
+        Ok(())
+    }
+
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let rgba = self.to_rgba32f()?;
+        let width = self.extent.width as usize;
+
+        exr::prelude::write_rgba_file(path, width, self.extent.height as usize, |x, y| {
+            let texel = rgba[y * width + x];
+            (texel[0], texel[1], texel[2], texel[3])
+        })?;
+
+        Ok(())
+    }
+
+    /// Saves based on the file extension, `.exr` for float data and `.png` for everything else
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let extension = path.as_ref().extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+
+        match extension.as_str() {
+            "exr" => self.save_exr(path),
+            "png" => self.save_png(path),
+            _ => bail!("unsupported screenshot extension '{}'", extension),
+        }
+    }
+
+    fn describe_format(format: vk::Format) -> Result<(ComponentType, u32, bool)> {
+        Ok(match format {
+            vk::Format::R8_UNORM | vk::Format::R8_SRGB => (ComponentType::UNORM8, 1, false),
+            vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB => (ComponentType::UNORM8, 2, false),
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => (ComponentType::UNORM8, 4, false),
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => (ComponentType::UNORM8, 4, true),
+
+            vk::Format::R16_UNORM | vk::Format::D16_UNORM => (ComponentType::UNORM16, 1, false),
+            vk::Format::R16G16B16A16_UNORM => (ComponentType::UNORM16, 4, false),
+
+            vk::Format::R16_SFLOAT => (ComponentType::SFLOAT16, 1, false),
+            vk::Format::R16G16_SFLOAT => (ComponentType::SFLOAT16, 2, false),
+            vk::Format::R16G16B16A16_SFLOAT => (ComponentType::SFLOAT16, 4, false),
+
+            vk::Format::R32_SFLOAT | vk::Format::D32_SFLOAT => (ComponentType::SFLOAT32, 1, false),
+            vk::Format::R32G32_SFLOAT => (ComponentType::SFLOAT32, 2, false),
+            vk::Format::R32G32B32_SFLOAT => (ComponentType::SFLOAT32, 3, false),
+            vk::Format::R32G32B32A32_SFLOAT => (ComponentType::SFLOAT32, 4, false),
+
+            vk::Format::R32_UINT => (ComponentType::UINT32, 1, false),
+            vk::Format::R32G32B32A32_UINT => (ComponentType::UINT32, 4, false),
+
+            _ => bail!("readback of format {:?} is not supported", format),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: vk::Extent2D = vk::Extent2D { width: 2, height: 1 };
+
+    #[test]
+    fn bgra_is_swizzled_to_rgba() {
+        let readback = BnanReadback::from_bytes(vk::Format::B8G8R8A8_UNORM, EXTENT, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(readback.to_rgba8().unwrap(), [3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn half_floats_are_widened_and_clamped() {
+        let halves = [1.0, 0.5, -2.0, 0.25, 0.0, 3.0, 1.0, 0.0];
+        let bytes: Vec<u8> = halves.iter().flat_map(|&value| f16::from_f32(value).to_ne_bytes()).collect();
+
+        let readback = BnanReadback::from_bytes(vk::Format::R16G16B16A16_SFLOAT, EXTENT, &bytes).unwrap();
+        assert!(readback.is_float());
+        assert_eq!(readback.to_rgba8().unwrap(), [255, 128, 0, 64, 0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn single_channel_is_broadcast_to_grey() {
+        let bytes: Vec<u8> = [0u16, 0xffff].iter().flat_map(|value| value.to_ne_bytes()).collect();
+
+        let readback = BnanReadback::from_bytes(vk::Format::R16_UNORM, EXTENT, &bytes).unwrap();
+        assert_eq!(readback.to_rgba8().unwrap(), [0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn unsupported_conversions_fail() {
+        assert!(BnanReadback::from_bytes(vk::Format::BC1_RGB_UNORM_BLOCK, EXTENT, &[0; 8]).is_err());
+
+        let readback = BnanReadback::from_bytes(vk::Format::R32_UINT, EXTENT, &[0; 8]).unwrap();
+        assert!(readback.to_rgba8().is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use anyhow::*;
+
+use crate::core::bnan_render_graph::pass::{QueueType, RenderPass};
+
+/// Pass indices in execution order plus the passes that were culled
+pub struct CompiledGraph {
+    pub order: Vec<usize>,
+    pub culled: Vec<usize>,
+}
+
+/// A run of consecutive passes submitted together to one queue
+pub struct QueueBatch {
+    pub queue: QueueType,
+    pub passes: Vec<usize>,
+}
+
+/// Splits the execution order into per queue submissions. `resolve_queue` maps a requested queue to the one
+/// actually used on this device. There is always at least one graphics batch to present from.
+pub fn split_batches(passes: &[RenderPass], order: &[usize], resolve_queue: impl Fn(QueueType) -> QueueType) -> Result<Vec<QueueBatch>> {
+    let mut batches: Vec<QueueBatch> = Vec::new();
+
+    for &index in order {
+        let pass = &passes[index];
+        let queue = resolve_queue(pass.queue);
+
+        for (_, usage, _, _) in pass.resource_uses() {
+            if !queue.supports_usage(usage) {
+                bail!("pass '{}' uses a resource as {:?} which is not supported on the {:?} queue", pass.name, usage, pass.queue);
+            }
+        }
+
+        match batches.last_mut() {
+            Some(batch) if batch.queue == queue => batch.passes.push(index),
+            _ => batches.push(QueueBatch { queue, passes: vec![index] }),
+        }
+    }
+
+    if !batches.iter().any(|batch| batch.queue == QueueType::Graphics) {
+        batches.push(QueueBatch { queue: QueueType::Graphics, passes: Vec::new() });
+    }
+
+    Ok(batches)
+}
+
+/// Orders passes by their declared reads and writes. Every write starts a new version of the resource in
+/// registration order, a read waits for the nearest preceding writer and the next writer waits for those reads.
+/// `roots` are the resources whose writers must run (backbuffer and exported resources),
+/// `external` are resources filled outside the graph that may be read without a writer.
+pub fn compile_passes(
+    passes: &[RenderPass],
+    roots: &HashSet<usize>,
+    external: &HashSet<usize>,
+    resource_names: &HashMap<usize, String>,
+) -> Result<CompiledGraph> {
+
+    let resource_name = |handle: usize| resource_names.get(&handle).cloned().unwrap_or(format!("#{}", handle));
+
+    let mut pass_indices = HashMap::new();
+    for (index, pass) in passes.iter().enumerate() {
+        if pass_indices.insert(pass.name.as_str(), index).is_some() {
+            bail!("render graph has more than one pass named '{}'", pass.name);
+        }
+    }
+
+    let mut writers: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); passes.len()];
+
+    for (index, pass) in passes.iter().enumerate() {
+        for output in &pass.outputs {
+            writers.entry(output.handle.0).or_default().push(index);
+
+            if let Some(resolve_target) = &output.resolve_target {
+                writers.entry(resolve_target.0).or_default().push(index);
+            }
+        }
+
+        for dependency in &pass.dependencies {
+            match pass_indices.get(dependency.as_str()) {
+                Some(&dependency_index) => dependencies[index].push(dependency_index),
+                None => bail!("pass '{}' depends on unknown pass '{}'", pass.name, dependency),
+            }
+        }
+    }
+
+    // --- Validate Reads ---
+    for (index, pass) in passes.iter().enumerate() {
+        for input in &pass.inputs {
+            let has_writer = writers.get(&input.handle.0).is_some_and(|resource_writers| {
+                resource_writers.iter().any(|&writer| input.is_temporal || writer != index)
+            });
+
+            if !has_writer && !external.contains(&input.handle.0) {
+                bail!(
+                    "pass '{}' reads '{}' which is never written by another pass, mark it external if it is filled outside the graph",
+                    pass.name, resource_name(input.handle.0)
+                );
+            }
+        }
+    }
+
+    // --- Versions ---
+    // writers whose version each pass reads, and readers each pass must wait for before it overwrites their version
+    let mut sources: Vec<Vec<usize>> = vec![Vec::new(); passes.len()];
+    let mut overwrites: Vec<Vec<usize>> = vec![Vec::new(); passes.len()];
+
+    // per resource: writer of the current version, its readers and reads registered before any writer
+    let mut last_writers: HashMap<usize, usize> = HashMap::new();
+    let mut readers: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut early_readers: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for (index, pass) in passes.iter().enumerate() {
+        for input in pass.inputs.iter().filter(|input| !input.is_temporal) {
+            let handle = input.handle.0;
+
+            match last_writers.get(&handle) {
+                Some(&writer) => {
+                    sources[index].push(writer);
+                    readers.entry(handle).or_default().push(index);
+                }
+                None => early_readers.entry(handle).or_default().push(index),
+            }
+        }
+
+        let written = pass.outputs.iter()
+            .flat_map(|output| std::iter::once(&output.handle).chain(output.resolve_target.as_ref()))
+            .map(|handle| handle.0);
+
+        for handle in written {
+            let previous_readers = readers.remove(&handle).unwrap_or_default();
+            overwrites[index].extend(previous_readers.into_iter().filter(|&reader| reader != index));
+
+            // reads registered before the first writer see the external contents, or else that writer's version
+            if let Some(early) = early_readers.remove(&handle) {
+                let early: Vec<usize> = early.into_iter().filter(|&reader| reader != index).collect();
+
+                if external.contains(&handle) {
+                    overwrites[index].extend(early);
+                } else {
+                    for &reader in &early {
+                        sources[reader].push(index);
+                    }
+                    readers.insert(handle, early);
+                }
+            }
+
+            last_writers.insert(handle, index);
+        }
+    }
+
+    // --- Culling ---
+    let mut live = vec![false; passes.len()];
+    let mut worklist: Vec<usize> = roots.iter()
+        .filter_map(|root| writers.get(root))
+        .flatten()
+        .cloned()
+        .collect();
+
+    while let Some(index) = worklist.pop() {
+        if live[index] {
+            continue;
+        }
+
+        live[index] = true;
+
+        // temporal reads keep every writer alive, the previous frame's last one is not known here
+        for input in passes[index].inputs.iter().filter(|input| input.is_temporal) {
+            if let Some(resource_writers) = writers.get(&input.handle.0) {
+                worklist.extend(resource_writers.iter().filter(|&&writer| !live[writer]));
+            }
+        }
+
+        worklist.extend(sources[index].iter().filter(|&&source| !live[source]));
+        worklist.extend(dependencies[index].iter().filter(|&&dependency| !live[dependency]));
+    }
+
+    // --- Edges ---
+    let mut successors: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); passes.len()];
+    let mut predecessors: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); passes.len()];
+
+    for index in (0..passes.len()).filter(|&index| live[index]) {
+        let incoming = sources[index].iter().chain(&overwrites[index]).chain(&dependencies[index]);
+
+        for &from in incoming {
+            if from != index && live[from] {
+                successors[from].insert(index);
+                predecessors[index].insert(from);
+            }
+        }
+    }
+
+    // --- Topological Sort ---
+    // ties are broken by insertion order so independent passes keep their registration order
+    let mut remaining: Vec<usize> = predecessors.iter().map(|p| p.len()).collect();
+    let mut ready: BTreeSet<usize> = (0..passes.len()).filter(|&index| live[index] && remaining[index] == 0).collect();
+    let mut order = Vec::new();
+
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+
+        for &successor in &successors[index] {
+            remaining[successor] -= 1;
+            if remaining[successor] == 0 {
+                ready.insert(successor);
+            }
+        }
+    }
+
+    let live_count = live.iter().filter(|&&is_live| is_live).count();
+    if order.len() != live_count {
+        let cycle = (0..passes.len())
+            .filter(|&index| live[index] && !order.contains(&index))
+            .map(|index| format!("'{}'", passes[index].name))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        bail!("render graph has a dependency cycle between passes {}", cycle);
+    }
+
+    // --- Validate Writers ---
+    let mut ancestors: Vec<HashSet<usize>> = vec![HashSet::new(); passes.len()];
+    for &index in &order {
+        let mut pass_ancestors = HashSet::new();
+        for &predecessor in &predecessors[index] {
+            pass_ancestors.insert(predecessor);
+            pass_ancestors.extend(ancestors[predecessor].iter().cloned());
+        }
+        ancestors[index] = pass_ancestors;
+    }
+
+    for (handle, resource_writers) in &writers {
+        let live_writers: Vec<usize> = resource_writers.iter().cloned().filter(|&writer| live[writer]).collect();
+
+        for (i, &a) in live_writers.iter().enumerate() {
+            for &b in &live_writers[i + 1..] {
+                if a != b && !ancestors[a].contains(&b) && !ancestors[b].contains(&a) {
+                    bail!(
+                        "passes '{}' and '{}' both write '{}' without an explicit ordering, read it in the later pass or use depends_on",
+                        passes[a].name, passes[b].name, resource_name(*handle)
+                    );
+                }
+            }
+        }
+    }
+
+    let culled = (0..passes.len()).filter(|&index| !live[index]).collect();
+
+    Ok(CompiledGraph { order, culled })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::bnan_render_graph::graph::BnanRenderGraph;
+    use crate::core::bnan_render_graph::pass::RenderPassResource;
+    use crate::core::bnan_render_graph::resource::{ResourceHandle, ResourceUsage};
+    use crate::core::bnan_rendering::BnanFrameInfo;
+
+    const X: usize = 0;
+    const Y: usize = 1;
+    const OUT: usize = 2;
+
+    fn pass(name: &str, reads: &[usize], writes: &[usize]) -> RenderPass {
+        let resources = |handles: &[usize], usage| handles.iter()
+            .map(|&handle| RenderPassResource::new(ResourceHandle(handle), usage))
+            .collect();
+
+        RenderPass::new(
+            name.to_string(),
+            resources(reads, ResourceUsage::StorageRead),
+            resources(writes, ResourceUsage::StorageWrite),
+            Box::new(|_: &BnanRenderGraph, _: &BnanFrameInfo| {}),
+        )
+    }
+
+    fn compile(passes: &[RenderPass], roots: &[usize], external: &[usize]) -> Result<Vec<String>> {
+        let compiled = compile_passes(passes, &roots.iter().cloned().collect(), &external.iter().cloned().collect(), &HashMap::new())?;
+        Ok(compiled.order.iter().map(|&index| passes[index].name.clone()).collect())
+    }
+
+    #[test]
+    fn readers_run_after_writers_registered_later() {
+        let passes = [pass("present", &[X], &[OUT]), pass("produce", &[], &[X])];
+        assert_eq!(compile(&passes, &[OUT], &[]).unwrap(), ["produce", "present"]);
+    }
+
+    #[test]
+    fn read_modify_write_passes_chain() {
+        let passes = [
+            pass("clear", &[], &[X]),
+            pass("first", &[X], &[X]),
+            pass("second", &[X], &[X]),
+            pass("present", &[X], &[OUT]),
+        ];
+
+        assert_eq!(compile(&passes, &[OUT], &[]).unwrap(), ["clear", "first", "second", "present"]);
+    }
+
+    #[test]
+    fn overwrite_waits_for_readers_of_the_previous_version() {
+        let passes = [
+            pass("write", &[], &[X]),
+            pass("read", &[X], &[Y]),
+            pass("overwrite", &[], &[X]).depends_on("read"),
+            pass("present", &[X, Y], &[OUT]),
+        ];
+
+        assert_eq!(compile(&passes, &[OUT], &[]).unwrap(), ["write", "read", "overwrite", "present"]);
+    }
+
+    #[test]
+    fn unused_passes_are_culled() {
+        let passes = [pass("unused", &[], &[Y]), pass("present", &[], &[OUT])];
+        let compiled = compile_passes(&passes, &HashSet::from([OUT]), &HashSet::new(), &HashMap::new()).unwrap();
+
+        assert_eq!(compiled.order, [1]);
+        assert_eq!(compiled.culled, [0]);
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let passes = [pass("a", &[Y], &[X]), pass("b", &[X], &[Y, OUT])];
+        let error = compile(&passes, &[OUT], &[]).unwrap_err().to_string();
+        assert!(error.contains("cycle"), "{}", error);
+    }
+
+    #[test]
+    fn reads_need_a_writer_or_an_external_resource() {
+        let passes = [pass("present", &[X], &[OUT])];
+
+        let error = compile(&passes, &[OUT], &[]).unwrap_err().to_string();
+        assert!(error.contains("never written"), "{}", error);
+        assert_eq!(compile(&passes, &[OUT], &[X]).unwrap(), ["present"]);
+    }
+
+    #[test]
+    fn unordered_writers_are_rejected() {
+        let passes = [pass("a", &[], &[X]), pass("b", &[], &[X])];
+        let error = compile(&passes, &[X], &[]).unwrap_err().to_string();
+        assert!(error.contains("without an explicit ordering"), "{}", error);
+
+        let passes = [pass("a", &[], &[X]), pass("b", &[], &[X]).depends_on("a")];
+        assert_eq!(compile(&passes, &[X], &[]).unwrap(), ["a", "b"]);
+    }
+}
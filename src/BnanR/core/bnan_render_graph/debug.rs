@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::*;
+use ash::vk;
+use ash::vk::Handle;
+use serde::{Deserialize, Serialize};
+
+use crate::core::bnan_render_graph::pass::RenderPass;
+use crate::core::bnan_render_graph::resource::{PhysicalResource, ResourceType};
+
+/// Snapshot of the compiled graph, serializable as JSON or Graphviz DOT.
+/// Everything is ordered by handle or pass index so dumps of the same graph diff cleanly.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphDump {
+    pub frame: u64,
+    pub passes: Vec<PassDump>,
+    pub resources: Vec<ResourceDump>,
+    pub batches: Vec<BatchDump>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PassDump {
+    pub index: usize,
+    pub name: String,
+    pub queue: String,
+    pub order: Option<usize>,
+    pub culled: bool,
+    pub depends_on: Vec<String>,
+    pub inputs: Vec<ResourceUseDump>,
+    pub outputs: Vec<ResourceUseDump>,
+    pub barriers: Vec<BarrierDump>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResourceUseDump {
+    pub resource: usize,
+    pub usage: String,
+    pub temporal: bool,
+    pub resolve_target: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResourceDump {
+    pub handle: usize,
+    pub name: String,
+    pub kind: String,
+    pub transient: bool,
+    pub aliased: bool,
+    pub external: bool,
+    pub exported: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchDump {
+    pub queue: String,
+    pub passes: Vec<usize>,
+}
+
+/// A barrier recorded before a pass, only filled in for frames captured with `request_dump` or `set_dump_interval`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BarrierDump {
+    pub resource: Option<usize>,
+    pub old_layout: Option<String>,
+    pub new_layout: Option<String>,
+    pub src_stage: String,
+    pub src_access: String,
+    pub dst_stage: String,
+    pub dst_access: String,
+}
+
+impl ResourceUseDump {
+    pub fn from_pass(pass: &RenderPass) -> (Vec<ResourceUseDump>, Vec<ResourceUseDump>) {
+        let inputs = pass.inputs.iter().map(|input| ResourceUseDump {
+            resource: input.handle.0,
+            usage: format!("{:?}", input.usage),
+            temporal: input.is_temporal,
+            resolve_target: None,
+        }).collect();
+
+        let outputs = pass.outputs.iter().map(|output| ResourceUseDump {
+            resource: output.handle.0,
+            usage: format!("{:?}", output.usage),
+            temporal: output.is_temporal,
+            resolve_target: output.resolve_target.as_ref().map(|target| target.0),
+        }).collect();
+
+        (inputs, outputs)
+    }
+}
+
+impl BarrierDump {
+    /// Converts recorded barriers back to resource handles, `handles` maps raw image and buffer handles of the pass
+    pub fn from_barriers(
+        image_barriers: &[vk::ImageMemoryBarrier2],
+        buffer_barriers: &[vk::BufferMemoryBarrier2],
+        handles: &HashMap<u64, usize>,
+    ) -> Vec<BarrierDump> {
+        let images = image_barriers.iter().map(|barrier| BarrierDump {
+            resource: handles.get(&barrier.image.as_raw()).cloned(),
+            old_layout: Some(format!("{:?}", barrier.old_layout)),
+            new_layout: Some(format!("{:?}", barrier.new_layout)),
+            src_stage: format!("{:?}", barrier.src_stage_mask),
+            src_access: format!("{:?}", barrier.src_access_mask),
+            dst_stage: format!("{:?}", barrier.dst_stage_mask),
+            dst_access: format!("{:?}", barrier.dst_access_mask),
+        });
+
+        let buffers = buffer_barriers.iter().map(|barrier| BarrierDump {
+            resource: handles.get(&barrier.buffer.as_raw()).cloned(),
+            old_layout: None,
+            new_layout: None,
+            src_stage: format!("{:?}", barrier.src_stage_mask),
+            src_access: format!("{:?}", barrier.src_access_mask),
+            dst_stage: format!("{:?}", barrier.dst_stage_mask),
+            dst_access: format!("{:?}", barrier.dst_access_mask),
+        });
+
+        images.chain(buffers).collect()
+    }
+}
+
+impl ResourceDump {
+    pub fn from_physical(physical: &PhysicalResource) -> ResourceDump {
+        let kind = match physical.resource {
+            ResourceType::SwapchainImage(_) => "swapchain",
+            ResourceType::Image(_) => "image",
+            ResourceType::Buffer(_) => "buffer",
+        };
+
+        ResourceDump {
+            handle: physical.handle.0,
+            name: physical.name.clone(),
+            kind: kind.to_string(),
+            transient: false,
+            aliased: false,
+            external: false,
+            exported: false,
+        }
+    }
+}
+
+impl GraphDump {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+        writeln!(dot, "digraph RenderGraph {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [fontname=\"monospace\", fontsize=10];").unwrap();
+        writeln!(dot, "    edge [fontname=\"monospace\", fontsize=8];").unwrap();
+
+        // --- Resources ---
+        for resource in &self.resources {
+            let mut flags = Vec::new();
+            if resource.transient { flags.push("transient"); }
+            if resource.aliased { flags.push("aliased"); }
+            if resource.external { flags.push("external"); }
+            if resource.exported { flags.push("exported"); }
+
+            let style = if resource.kind == "buffer" { "shape=note" } else { "shape=ellipse" };
+            let label = match flags.is_empty() {
+                true => format!("{}\\n#{} {}", escape(&resource.name), resource.handle, resource.kind),
+                false => format!("{}\\n#{} {} ({})", escape(&resource.name), resource.handle, resource.kind, flags.join(", ")),
+            };
+
+            writeln!(dot, "    res_{} [{}, label=\"{}\"];", resource.handle, style, label).unwrap();
+        }
+
+        // --- Passes ---
+        for pass in &self.passes {
+            let order = pass.order.map(|order| order.to_string()).unwrap_or("culled".to_string());
+            let style = match pass.culled {
+                true => ", style=dashed, color=gray",
+                false => ", style=filled, fillcolor=lightgray",
+            };
+
+            writeln!(dot, "    pass_{} [shape=box{}, label=\"{}\\n{} {}\"];", pass.index, style, escape(&pass.name), order, pass.queue).unwrap();
+
+            // edges carry the layout transition the pass recorded for that resource, if any
+            let transition = |resource: usize| {
+                pass.barriers.iter()
+                    .find(|barrier| barrier.resource == Some(resource))
+                    .and_then(|barrier| Some(format!("\\n{} -> {}", barrier.old_layout.as_ref()?, barrier.new_layout.as_ref()?)))
+                    .unwrap_or_default()
+            };
+
+            for input in &pass.inputs {
+                let style = if input.temporal { ", style=dashed" } else { "" };
+                let temporal = if input.temporal { " (previous frame)" } else { "" };
+                writeln!(dot, "    res_{} -> pass_{} [label=\"{}{}{}\"{}];", input.resource, pass.index, input.usage, temporal, transition(input.resource), style).unwrap();
+            }
+
+            for output in &pass.outputs {
+                writeln!(dot, "    pass_{} -> res_{} [label=\"{}{}\"];", pass.index, output.resource, output.usage, transition(output.resource)).unwrap();
+
+                if let Some(resolve_target) = output.resolve_target {
+                    writeln!(dot, "    pass_{} -> res_{} [label=\"resolve{}\"];", pass.index, resolve_target, transition(resolve_target)).unwrap();
+                }
+            }
+
+            for dependency in &pass.depends_on {
+                if let Some(dependency) = self.passes.iter().find(|other| &other.name == dependency) {
+                    writeln!(dot, "    pass_{} -> pass_{} [style=dotted, label=\"depends_on\"];", dependency.index, pass.index).unwrap();
+                }
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// Writes `.dot` or `.json` depending on the extension
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let contents = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("dot") | Some("gv") => self.to_dot(),
+            Some("json") => self.to_json()?,
+            _ => bail!("unsupported graph dump format {:?}, expected .dot or .json", path),
+        };
+
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
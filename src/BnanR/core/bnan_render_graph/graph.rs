@@ -1,27 +1,42 @@
 use std::result::Result::Ok;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
 use std::time::Instant;
 
 use anyhow::*;
 use ash::*;
+use ash::vk::Handle;
+use vk_mem::Allocation;
 
 use crate::core::{make_arcmut, ArcMut};
+use crate::core::bnan_buffer::BnanBuffer;
 use crate::core::bnan_device::{BnanBarrierBuilder, BnanDevice};
+use crate::core::bnan_readback::{BnanReadback, BnanReadbackBuffer};
 use crate::core::bnan_swapchain::BnanSwapchain;
 use crate::core::bnan_window::BnanWindow;
 use crate::core::bnan_rendering::{BnanFrameInfo, FRAMES_IN_FLIGHT}; 
-use crate::core::bnan_render_graph::resource::{ResourceHandle, ResourceType, PhysicalResource, ResourceUsage};
-use crate::core::bnan_render_graph::pass::RenderPass;
+use crate::core::bnan_render_graph::resource::{ImageDescription, OwnershipTransfer, ResourceHandle, ResourceType, PhysicalResource, ResourceUsage, SubresourceRange, SubresourceState, TransientImage};
+use crate::core::bnan_render_graph::pass::{QueueType, RenderPass};
+use crate::core::bnan_render_graph::compile::{compile_passes, split_batches, QueueBatch};
+use crate::core::bnan_render_graph::debug::{BarrierDump, BatchDump, GraphDump, PassDump, ResourceDump, ResourceUseDump};
+use crate::core::bnan_render_graph::profiler::{GpuProfiler, PassTiming};
 use crate::core::bnan_image::BnanImage;
 use crate::core::bnan_render_graph::sync::RenderGraphSync;
 
 pub struct BnanRenderGraph {
     pub device: ArcMut<BnanDevice>,
-    pub swapchain: ArcMut<BnanSwapchain>,
-    pub window: ArcMut<BnanWindow>,
+    pub swapchain: Option<ArcMut<BnanSwapchain>>,
+    pub window: Option<ArcMut<BnanWindow>>,
+    pub offscreen_images: Option<[ArcMut<BnanImage>; FRAMES_IN_FLIGHT]>,
     pub sync: RenderGraphSync,
     
     pub passes: Vec<RenderPass>,
+    pub execution_order: Vec<usize>,
+    pub culled_passes: Vec<usize>,
+    pub queue_batches: Vec<QueueBatch>,
+    pub compiled: bool,
+    pub exported_resources: HashSet<usize>,
+    pub external_resources: HashSet<usize>,
     pub resources: HashMap<usize, PhysicalResource>,
     pub resource_counter: usize,
     
@@ -29,6 +44,31 @@ pub struct BnanRenderGraph {
     
     pub swapchain_resource_handle: Option<ResourceHandle>,
     pub frame_time_reference: Instant,
+
+    pub readback_requests: Vec<(ResourceHandle, u32)>,
+    pub pending_readbacks: [Vec<(ResourceHandle, BnanReadbackBuffer)>; FRAMES_IN_FLIGHT],
+
+    pub transient_images: HashMap<usize, TransientImage>,
+    pub aliased_resources: HashSet<usize>,
+    pub transient_allocations: Vec<Allocation>,
+    pub transient_extent: vk::Extent2D,
+
+    // --- Multi Queue ---
+    pub queues: [vk::Queue; QueueType::COUNT],
+    pub queue_families: [u32; QueueType::COUNT],
+    pub pending_ownership: HashMap<(usize, usize), OwnershipTransfer>,
+    pub resource_submissions: HashMap<usize, (QueueType, u64)>,
+
+    // --- Debug Dumps ---
+    pub frame_count: u64,
+    pub dump_requested: bool,
+    pub dump_interval: Option<(u64, PathBuf)>,
+    pub last_dump: Option<GraphDump>,
+    pub capturing_barriers: bool,
+    pub captured_barriers: HashMap<usize, Vec<BarrierDump>>,
+
+    // --- Profiling ---
+    pub profiler: Option<GpuProfiler>,
 }
 
 impl BnanRenderGraph {
@@ -39,18 +79,120 @@ impl BnanRenderGraph {
         
         Ok(Self {
             device,
-            swapchain,
-            window,
+            swapchain: Some(swapchain),
+            window: Some(window),
+            offscreen_images: None,
+            sync,
+            passes: Vec::new(),
+            execution_order: Vec::new(),
+            culled_passes: Vec::new(),
+            queue_batches: Vec::new(),
+            compiled: false,
+            exported_resources: HashSet::new(),
+            external_resources: HashSet::new(),
+            resources: HashMap::new(),
+            resource_counter: 0,
+            current_frame: 0,
+            swapchain_resource_handle: Some(ResourceHandle(0)),
+            frame_time_reference: Instant::now(),
+            readback_requests: Vec::new(),
+            pending_readbacks: std::array::from_fn(|_| Vec::new()),
+            transient_images: HashMap::new(),
+            aliased_resources: HashSet::new(),
+            transient_allocations: Vec::new(),
+            transient_extent: vk::Extent2D::default(),
+            queues: [vk::Queue::null(); QueueType::COUNT],
+            queue_families: [0; QueueType::COUNT],
+            pending_ownership: HashMap::new(),
+            resource_submissions: HashMap::new(),
+            frame_count: 0,
+            dump_requested: false,
+            dump_interval: None,
+            last_dump: None,
+            capturing_barriers: false,
+            captured_barriers: HashMap::new(),
+            profiler: None,
+        })
+    }
+
+    /// Creates a graph that renders into an owned ring of images instead of a swapchain.
+    /// The backbuffer is left in TRANSFER_SRC_OPTIMAL at the end of each frame so it can be read back.
+    pub fn new_offscreen(device: ArcMut<BnanDevice>, extent: vk::Extent2D, format: vk::Format) -> Result<Self> {
+
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        let image_extent = vk::Extent3D { width: extent.width, height: extent.height, depth: 1 };
+
+        let offscreen_images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT] = (0..FRAMES_IN_FLIGHT)
+            .map(|_| BnanImage::new(device.clone(), format, usage, vk::MemoryPropertyFlags::DEVICE_LOCAL, image_extent, vk::SampleCountFlags::TYPE_1, None).map(make_arcmut))
+            .collect::<Result<Vec<_>>>()?
+            .try_into()
+            .map_err(|_| anyhow!("failed to create offscreen images"))?;
+
+        let sync = RenderGraphSync::new(device.clone(), 0)?;
+
+        Ok(Self {
+            device,
+            swapchain: None,
+            window: None,
+            offscreen_images: Some(offscreen_images),
             sync,
             passes: Vec::new(),
+            execution_order: Vec::new(),
+            culled_passes: Vec::new(),
+            queue_batches: Vec::new(),
+            compiled: false,
+            exported_resources: HashSet::new(),
+            external_resources: HashSet::new(),
             resources: HashMap::new(),
             resource_counter: 0,
             current_frame: 0,
             swapchain_resource_handle: Some(ResourceHandle(0)),
             frame_time_reference: Instant::now(),
+            readback_requests: Vec::new(),
+            pending_readbacks: std::array::from_fn(|_| Vec::new()),
+            transient_images: HashMap::new(),
+            aliased_resources: HashSet::new(),
+            transient_allocations: Vec::new(),
+            transient_extent: vk::Extent2D::default(),
+            queues: [vk::Queue::null(); QueueType::COUNT],
+            queue_families: [0; QueueType::COUNT],
+            pending_ownership: HashMap::new(),
+            resource_submissions: HashMap::new(),
+            frame_count: 0,
+            dump_requested: false,
+            dump_interval: None,
+            last_dump: None,
+            capturing_barriers: false,
+            captured_barriers: HashMap::new(),
+            profiler: None,
         })
     }
 
+    pub fn is_offscreen(&self) -> bool {
+        self.offscreen_images.is_some()
+    }
+
+    /// Extent of the backbuffer, either the swapchain or the offscreen target
+    pub fn get_extent(&self) -> vk::Extent2D {
+        match (&self.offscreen_images, &self.swapchain) {
+            (Some(images), _) => {
+                let extent = images[0].lock().unwrap().image_extent;
+                vk::Extent2D { width: extent.width, height: extent.height }
+            }
+            (None, Some(swapchain)) => swapchain.lock().unwrap().extent,
+            (None, None) => vk::Extent2D::default(),
+        }
+    }
+
+    /// Format of the backbuffer, either the swapchain or the offscreen target
+    pub fn get_format(&self) -> vk::Format {
+        match (&self.offscreen_images, &self.swapchain) {
+            (Some(images), _) => images[0].lock().unwrap().format,
+            (None, Some(swapchain)) => swapchain.lock().unwrap().surface_format.format,
+            (None, None) => vk::Format::UNDEFINED,
+        }
+    }
+
     pub fn get_backbuffer_handle(&self) -> ResourceHandle {
         self.swapchain_resource_handle.clone().unwrap()
     }
@@ -79,13 +221,19 @@ impl BnanRenderGraph {
         self.get_image(handle, prev_frame)
     }
 
-    pub fn import_render_image(&mut self, name: &str, images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT]) -> ResourceHandle {
+    fn allocate_handle(&mut self) -> ResourceHandle {
         self.resource_counter += 1;
         if self.resource_counter <= 1 { self.resource_counter = 1; }
         
         let handle = ResourceHandle(self.resource_counter);
         self.resource_counter += 1;
         
+        handle
+    }
+
+    pub fn import_render_image(&mut self, name: &str, images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT]) -> ResourceHandle {
+        let handle = self.allocate_handle();
+        
         let physical = PhysicalResource::new_image(handle.clone(), name.to_string(), images);
         self.resources.insert(handle.0, physical);
         
@@ -96,18 +244,455 @@ impl BnanRenderGraph {
         
         if let Some(physical) = self.resources.get_mut(&handle.0) {
             physical.resource = ResourceType::Image(images);
-            physical.current_layout = vk::ImageLayout::UNDEFINED;
+            physical.set_uniform_state(vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE);
+            physical.current_queue_family = vk::QUEUE_FAMILY_IGNORED;
+        }
+
+        self.pending_ownership.retain(|(pending_handle, _), _| *pending_handle != handle.0);
+    }
+    
+    /// Declares an image owned by the graph. It is allocated when the graph is compiled, shares memory with
+    /// transients whose lifetimes do not overlap and is recreated when the backbuffer is resized.
+    /// Temporal and exported transients are never aliased.
+    pub fn create_transient_image(&mut self, name: &str, description: ImageDescription) -> ResourceHandle {
+        let handle = self.allocate_handle();
+
+        self.transient_images.insert(handle.0, TransientImage { name: name.to_string(), description });
+        self.compiled = false;
+
+        handle
+    }
+
+    pub fn get_buffer(&self, handle: &ResourceHandle, frame: usize) -> Option<ArcMut<BnanBuffer>> {
+        self.resources.get(&handle.0).and_then(|physical| {
+            match &physical.resource {
+                ResourceType::Buffer(buffers) => Some(buffers[frame].clone()),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn get_buffers(&self, handle: &ResourceHandle) -> Option<[ArcMut<BnanBuffer>; FRAMES_IN_FLIGHT]> {
+        self.resources.get(&handle.0).and_then(|physical| {
+            match &physical.resource {
+                ResourceType::Buffer(buffers) => Some(buffers.clone()),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn get_previous_frame_buffer(&self, handle: &ResourceHandle, current_frame: usize) -> Option<ArcMut<BnanBuffer>> {
+        let prev_frame = (current_frame + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT;
+        self.get_buffer(handle, prev_frame)
+    }
+
+    /// Imports one buffer per frame in flight, temporal reads see the previous frame's buffer
+    pub fn import_buffer(&mut self, name: &str, buffers: [ArcMut<BnanBuffer>; FRAMES_IN_FLIGHT]) -> ResourceHandle {
+        let handle = self.allocate_handle();
+
+        let physical = PhysicalResource::new_buffer(handle.clone(), name.to_string(), buffers);
+        self.resources.insert(handle.0, physical);
+
+        handle
+    }
+
+    /// Creates a device local buffer per frame in flight and imports it
+    pub fn create_buffer(&mut self, name: &str, instance_size: vk::DeviceSize, instance_count: u32, usage: vk::BufferUsageFlags) -> Result<ResourceHandle> {
+        let buffers: [ArcMut<BnanBuffer>; FRAMES_IN_FLIGHT] = (0..FRAMES_IN_FLIGHT)
+            .map(|_| BnanBuffer::new(self.device.clone(), instance_size, instance_count, usage, vk::MemoryPropertyFlags::DEVICE_LOCAL).map(make_arcmut))
+            .collect::<Result<Vec<_>>>()?
+            .try_into()
+            .map_err(|_| anyhow!("failed to create buffers for {}", name))?;
+
+        Ok(self.import_buffer(name, buffers))
+    }
+
+    pub fn update_buffer(&mut self, handle: &ResourceHandle, buffers: [ArcMut<BnanBuffer>; FRAMES_IN_FLIGHT]) {
+        if let Some(physical) = self.resources.get_mut(&handle.0) {
+            physical.resource = ResourceType::Buffer(buffers);
             physical.current_stage = vk::PipelineStageFlags2::NONE;
+            physical.current_access = vk::AccessFlags2::NONE;
+            physical.current_queue_family = vk::QUEUE_FAMILY_IGNORED;
         }
+
+        self.pending_ownership.retain(|(pending_handle, _), _| *pending_handle != handle.0);
     }
     
     pub fn add_pass(&mut self, pass: RenderPass) {
         self.passes.push(pass);
+        self.compiled = false;
+    }
+
+    /// Keeps the writers of this resource alive during culling even if nothing reads it
+    pub fn export_resource(&mut self, handle: &ResourceHandle) {
+        self.exported_resources.insert(handle.0);
+        self.compiled = false;
+    }
+
+    /// Marks a resource whose contents are produced outside the graph, so passes may read it without a writer
+    pub fn mark_external(&mut self, handle: &ResourceHandle) {
+        self.external_resources.insert(handle.0);
+        self.compiled = false;
+    }
+
+    /// Sorts passes by their declared inputs and outputs and culls passes that do not contribute to the frame.
+    /// Called automatically by `execute` after passes change.
+    pub fn compile(&mut self) -> Result<()> {
+        let mut roots = self.exported_resources.clone();
+        roots.insert(self.get_backbuffer_handle().0);
+
+        let mut resource_names: HashMap<usize, String> = self.resources.iter()
+            .map(|(handle, physical)| (*handle, physical.name.clone()))
+            .collect();
+        resource_names.insert(self.get_backbuffer_handle().0, "Backbuffer".to_string());
+        resource_names.extend(self.transient_images.iter().map(|(handle, transient)| (*handle, transient.name.clone())));
+
+        let compiled = compile_passes(&self.passes, &roots, &self.external_resources, &resource_names)?;
+
+        self.execution_order = compiled.order;
+        self.culled_passes = compiled.culled;
+
+        // --- Queue Batches ---
+        let queue_indices = self.device.lock().unwrap().get_queue_indices()?;
+        self.queue_families = [
+            queue_indices.graphics_family.unwrap(),
+            queue_indices.compute_family.unwrap(),
+            queue_indices.transfer_family.unwrap(),
+        ];
+
+        {
+            let device = self.device.lock().unwrap();
+            self.queues = [device.graphics_queue, device.compute_queue, device.transfer_queue];
+        }
+
+        // without a separate queue the pass simply runs on graphics
+        let queues = self.queues;
+        self.queue_batches = split_batches(&self.passes, &self.execution_order, |queue| {
+            if queues[queue as usize] == queues[QueueType::Graphics as usize] { QueueType::Graphics } else { queue }
+        })?;
+
+        // releases made for the old schedule may never be acquired, their contents are dropped
+        if !self.pending_ownership.is_empty() {
+            unsafe { self.device.lock().unwrap().device.device_wait_idle()? };
+
+            for ((handle, _), _) in self.pending_ownership.drain() {
+                if let Some(physical) = self.resources.get_mut(&handle) {
+                    physical.set_uniform_state(vk::ImageLayout::UNDEFINED, physical.current_stage, physical.current_access);
+                    physical.current_queue_family = vk::QUEUE_FAMILY_IGNORED;
+                }
+            }
+        }
+
+        self.compiled = true;
+
+        self.allocate_transients()?;
+
+        Ok(())
+    }
+
+    /// (Re)creates every transient image, grouping transients with disjoint lifetimes onto shared memory
+    fn allocate_transients(&mut self) -> Result<()> {
+        self.release_transients()?;
+
+        if self.transient_images.is_empty() {
+            return Ok(());
+        }
+
+        let backbuffer_extent = self.get_extent();
+        self.transient_extent = backbuffer_extent;
+
+        // --- Lifetimes ---
+        // first and last position in the execution order of every transient
+        let mut lifetimes: HashMap<usize, (usize, usize)> = HashMap::new();
+        let mut not_aliasable: HashSet<usize> = self.exported_resources.clone();
+
+        // batches of other queues overlap the graphics work around them, positions say nothing about their lifetimes
+        let off_graphics: HashSet<usize> = self.queue_batches.iter()
+            .filter(|batch| batch.queue != QueueType::Graphics)
+            .flat_map(|batch| batch.passes.iter().cloned())
+            .collect();
+
+        for (position, &pass_index) in self.execution_order.iter().enumerate() {
+            let pass = &self.passes[pass_index];
+            let on_graphics = !off_graphics.contains(&pass_index);
+
+            let inputs = pass.inputs.iter().map(|input| {
+                if input.is_temporal {
+                    not_aliasable.insert(input.handle.0);
+                }
+
+                input.handle.0
+            });
+
+            let outputs = pass.outputs.iter().flat_map(|output| {
+                std::iter::once(output.handle.0).chain(output.resolve_target.iter().map(|target| target.0))
+            });
+
+            for handle in inputs.collect::<Vec<_>>().into_iter().chain(outputs) {
+                if !on_graphics {
+                    not_aliasable.insert(handle);
+                }
+
+                if self.transient_images.contains_key(&handle) {
+                    let lifetime = lifetimes.entry(handle).or_insert((position, position));
+                    lifetime.1 = lifetime.1.max(position);
+                }
+            }
+        }
+
+        // --- Grouping ---
+        struct AliasGroup {
+            last_use: usize,
+            requirements: vk::MemoryRequirements,
+            members: Vec<usize>,
+        }
+
+        let mut candidates: Vec<(usize, (usize, usize))> = lifetimes.iter()
+            .filter(|(handle, _)| !not_aliasable.contains(handle))
+            .map(|(handle, lifetime)| (*handle, *lifetime))
+            .collect();
+        candidates.sort_by_key(|(handle, (first_use, _))| (*first_use, *handle));
+
+        let mut groups: Vec<AliasGroup> = Vec::new();
+
+        {
+            let device = self.device.lock().unwrap();
+
+            for (handle, (first_use, last_use)) in candidates {
+                let description = &self.transient_images[&handle].description;
+                let requirements = BnanImage::get_memory_requirements(
+                    &device,
+                    description.format,
+                    description.usage,
+                    description.resolve_extent(backbuffer_extent),
+                    description.samples,
+                    Some(description.resolve_mip_levels(backbuffer_extent)),
+                    description.layers,
+                );
+
+                let group = groups.iter_mut().find(|group| {
+                    group.last_use < first_use && group.requirements.memory_type_bits & requirements.memory_type_bits != 0
+                });
+
+                match group {
+                    Some(group) => {
+                        group.last_use = last_use;
+                        group.requirements.size = group.requirements.size.max(requirements.size);
+                        group.requirements.alignment = group.requirements.alignment.max(requirements.alignment);
+                        group.requirements.memory_type_bits &= requirements.memory_type_bits;
+                        group.members.push(handle);
+                    }
+
+                    None => groups.push(AliasGroup { last_use, requirements, members: vec![handle] }),
+                }
+            }
+        }
+
+        // groups of one gain nothing from aliasing and keep a dedicated allocation
+        groups.retain(|group| group.members.len() > 1);
+
+        // --- Allocation ---
+        let mut images: HashMap<usize, Vec<ArcMut<BnanImage>>> = HashMap::new();
+
+        for _ in 0..FRAMES_IN_FLIGHT {
+            for group in &groups {
+                let allocation = BnanImage::allocate_aliasing_memory(self.device.clone(), &group.requirements)?;
+
+                for &handle in &group.members {
+                    let description = &self.transient_images[&handle].description;
+                    let image = BnanImage::new_aliased(
+                        self.device.clone(),
+                        &allocation,
+                        description.format,
+                        description.usage,
+                        description.resolve_extent(backbuffer_extent),
+                        description.samples,
+                        Some(description.resolve_mip_levels(backbuffer_extent)),
+                        description.layers,
+                    )?;
+
+                    images.entry(handle).or_default().push(make_arcmut(image));
+                    self.aliased_resources.insert(handle);
+                }
+
+                self.transient_allocations.push(allocation);
+            }
+
+            for (handle, transient) in self.transient_images.iter().filter(|(handle, _)| !self.aliased_resources.contains(handle)) {
+                let description = &transient.description;
+                let image = BnanImage::new_layered(
+                    self.device.clone(),
+                    description.format,
+                    description.usage,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    description.resolve_extent(backbuffer_extent),
+                    description.samples,
+                    Some(description.resolve_mip_levels(backbuffer_extent)),
+                    description.layers,
+                )?;
+
+                images.entry(*handle).or_default().push(make_arcmut(image));
+            }
+        }
+
+        for (handle, frame_images) in images {
+            let name = self.transient_images[&handle].name.clone();
+            let frame_images: [ArcMut<BnanImage>; FRAMES_IN_FLIGHT] = frame_images
+                .try_into()
+                .map_err(|_| anyhow!("failed to create transient images for {}", name))?;
+
+            self.resources.insert(handle, PhysicalResource::new_image(ResourceHandle(handle), name, frame_images));
+        }
+
+        Ok(())
+    }
+
+    /// Destroys every transient image and frees the memory shared between aliased ones
+    fn release_transients(&mut self) -> Result<()> {
+        let allocated = self.transient_images.keys().any(|handle| self.resources.contains_key(handle));
+        if !allocated && self.transient_allocations.is_empty() {
+            return Ok(());
+        }
+
+        unsafe { self.device.lock().unwrap().device.device_wait_idle()? };
+
+        for handle in self.transient_images.keys() {
+            self.resources.remove(handle);
+        }
+
+        let transient_images = &self.transient_images;
+        self.pending_ownership.retain(|(handle, _), _| !transient_images.contains_key(handle));
+
+        // images must be gone before the memory they are bound to
+        for allocation in self.transient_allocations.drain(..) {
+            BnanImage::free_aliasing_memory(self.device.clone(), allocation);
+        }
+
+        self.aliased_resources.clear();
+
+        Ok(())
+    }
+
+    /// Structure of the compiled graph. Barriers are those of the last frame captured with
+    /// `request_dump` or `set_dump_interval`, and are empty before any capture.
+    pub fn dump(&self) -> GraphDump {
+        let order_positions: HashMap<usize, usize> = self.execution_order.iter().enumerate().map(|(position, &index)| (index, position)).collect();
+        let pass_queues: HashMap<usize, QueueType> = self.queue_batches.iter()
+            .flat_map(|batch| batch.passes.iter().map(move |&index| (index, batch.queue)))
+            .collect();
+
+        let passes = self.passes.iter().enumerate().map(|(index, pass)| {
+            let (inputs, outputs) = ResourceUseDump::from_pass(pass);
+
+            PassDump {
+                index,
+                name: pass.name.clone(),
+                queue: format!("{:?}", pass_queues.get(&index).unwrap_or(&pass.queue)),
+                order: order_positions.get(&index).cloned(),
+                culled: self.culled_passes.contains(&index),
+                depends_on: pass.dependencies.clone(),
+                inputs,
+                outputs,
+                barriers: self.captured_barriers.get(&index).cloned().unwrap_or_default(),
+            }
+        }).collect();
+
+        let mut resources: BTreeMap<usize, ResourceDump> = self.resources.iter()
+            .map(|(handle, physical)| (*handle, ResourceDump::from_physical(physical)))
+            .collect();
+
+        // declared but not allocated yet
+        for (handle, transient) in &self.transient_images {
+            resources.entry(*handle).or_insert(ResourceDump {
+                handle: *handle,
+                name: transient.name.clone(),
+                kind: "image".to_string(),
+                transient: false,
+                aliased: false,
+                external: false,
+                exported: false,
+            });
+        }
+
+        let backbuffer = self.get_backbuffer_handle();
+        resources.entry(backbuffer.0).or_insert(ResourceDump {
+            handle: backbuffer.0,
+            name: "Backbuffer".to_string(),
+            kind: if self.is_offscreen() { "image" } else { "swapchain" }.to_string(),
+            transient: false,
+            aliased: false,
+            external: false,
+            exported: false,
+        });
+
+        for (handle, resource) in resources.iter_mut() {
+            resource.transient = self.transient_images.contains_key(handle);
+            resource.aliased = self.aliased_resources.contains(handle);
+            resource.external = self.external_resources.contains(handle);
+            resource.exported = self.exported_resources.contains(handle);
+        }
+
+        let batches = self.queue_batches.iter().map(|batch| BatchDump {
+            queue: format!("{:?}", batch.queue),
+            passes: batch.passes.clone(),
+        }).collect();
+
+        GraphDump {
+            frame: self.frame_count,
+            passes,
+            resources: resources.into_values().collect(),
+            batches,
+        }
+    }
+
+    /// Captures the barriers of the next executed frame, the dump is collected with `take_dump`
+    pub fn request_dump(&mut self) {
+        self.dump_requested = true;
+    }
+
+    pub fn take_dump(&mut self) -> Option<GraphDump> {
+        self.last_dump.take()
+    }
+
+    /// Every `interval` frames writes `graph_<frame>.dot` and `graph_<frame>.json` into the directory, `None` stops it
+    pub fn set_dump_interval(&mut self, interval: Option<(u64, PathBuf)>) {
+        self.dump_interval = interval;
+    }
+
+    /// Starts timestamp queries around every pass, the results lag `FRAMES_IN_FLIGHT` frames behind
+    pub fn enable_profiling(&mut self, pipeline_statistics: bool) -> Result<()> {
+        let queue_indices = self.device.lock().unwrap().get_queue_indices()?;
+        let queue_families = [
+            queue_indices.graphics_family.unwrap(),
+            queue_indices.compute_family.unwrap(),
+            queue_indices.transfer_family.unwrap(),
+        ];
+
+        self.profiler = Some(GpuProfiler::new(self.device.clone(), queue_families, pipeline_statistics)?);
+        Ok(())
+    }
+
+    pub fn disable_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    /// Rolling per-pass GPU timings, empty while profiling is disabled
+    pub fn get_pass_timings(&self) -> Vec<PassTiming> {
+        self.profiler.as_ref().map(|profiler| profiler.get_pass_timings()).unwrap_or_default()
+    }
+
+    fn get_debug_label_color(queue: QueueType) -> [f32; 4] {
+        match queue {
+            QueueType::Graphics => [0.9, 0.4, 0.1, 1.0],
+            QueueType::AsyncCompute => [0.2, 0.6, 0.9, 1.0],
+            QueueType::Transfer => [0.4, 0.8, 0.3, 1.0],
+        }
     }
 
     fn get_src_access_and_stage(
         current_layout: vk::ImageLayout,
         current_stage: vk::PipelineStageFlags2,
+        current_access: vk::AccessFlags2,
         is_swapchain: bool,
     ) -> (vk::AccessFlags2, vk::PipelineStageFlags2) {
         // Swapchain from UNDEFINED syncs with acquire semaphore at COLOR_ATTACHMENT_OUTPUT
@@ -116,7 +701,8 @@ impl BnanRenderGraph {
         }
         
         match current_layout {
-            vk::ImageLayout::UNDEFINED => (vk::AccessFlags2::NONE, current_stage),
+            // contents are discarded, only writes of whatever used the memory before are waited on
+            vk::ImageLayout::UNDEFINED => (current_access, current_stage),
             vk::ImageLayout::GENERAL => (vk::AccessFlags2::SHADER_STORAGE_WRITE, vk::PipelineStageFlags2::ALL_COMMANDS),
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (vk::AccessFlags2::COLOR_ATTACHMENT_WRITE, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT),
             vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
@@ -137,7 +723,7 @@ impl BnanRenderGraph {
         }
     }
     
-    fn get_aspect_mask_for_format(format: vk::Format) -> vk::ImageAspectFlags {
+    pub(crate) fn get_aspect_mask_for_format(format: vk::Format) -> vk::ImageAspectFlags {
         match format {
             vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
                 vk::ImageAspectFlags::DEPTH
@@ -151,53 +737,48 @@ impl BnanRenderGraph {
     }
     
     pub fn execute(&mut self) -> Result<()> {
-        
-        let (swapchain_loader, swapchain_khr, swapchain_images, swapchain_views, swapchain_extent, swapchain_format) = {
-             let device = self.device.lock().unwrap();
-             let swapchain = self.swapchain.lock().unwrap();
 
-             (
-                 khr::swapchain::Device::new(&device.instance, &device.device),
-                 swapchain.swapchain,
-                 swapchain.images.clone(),
-                 swapchain.image_views.clone(),
-                 swapchain.extent,
-                 swapchain.surface_format.format
-             )
-        };
+        if !self.compiled {
+            self.compile()?;
+        } else if !self.transient_images.is_empty() && self.get_extent() != self.transient_extent {
+            self.allocate_transients()?;
+        }
 
         self.sync.wait_and_reset_in_flight(self.current_frame)?;
-        
-        let acquire_result = unsafe {
-            swapchain_loader.acquire_next_image(
-                swapchain_khr,
-                u64::MAX,
-                self.sync.image_available_semaphores[self.current_frame],
-                vk::Fence::null()
-            )
-        };
-        
-        let image_index = match acquire_result {
-            Ok((idx, _)) => idx,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                 self.recreate_swapchain()?;
-                 return Ok(());
+        self.sync.wait_timelines(self.current_frame)?;
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame(self.current_frame)?;
+        }
+
+        let dump_due = self.dump_interval.as_ref().is_some_and(|(interval, _)| *interval > 0 && self.frame_count.is_multiple_of(*interval));
+        self.capturing_barriers = self.dump_requested || dump_due;
+        if self.capturing_barriers {
+            self.captured_barriers.clear();
+        }
+
+        // aliased transients start undefined every frame and wait on whatever used their memory before,
+        // the writes of the previous alias have to be made available before the memory is reused
+        for handle in &self.aliased_resources {
+            if let Some(physical) = self.resources.get_mut(handle) {
+                physical.set_uniform_state(vk::ImageLayout::UNDEFINED, vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_WRITE);
+            }
+        }
+
+        let (backbuffer_image, backbuffer_resource, image_index) = match &self.offscreen_images {
+            Some(images) => {
+                (images[self.current_frame].clone(), ResourceType::Image(images.clone()), None)
+            }
+
+            None => {
+                let Some((image_index, image)) = self.acquire_swapchain_image()? else {
+                    return Ok(());
+                };
+
+                (image.clone(), ResourceType::SwapchainImage(image), Some(image_index))
             }
-            Err(e) => return Err(Error::new(e)),
         };
-        
-        let command_buffer = self.sync.get_command_buffer(self.current_frame);
-        let swapchain_image = swapchain_images[image_index as usize];
-        let swapchain_view = swapchain_views[image_index as usize];
-        
-        let swapchain_resource_image = make_arcmut(BnanImage::from_image(
-            self.device.clone(),
-            swapchain_image,
-            swapchain_view,
-            swapchain_format,
-            vk::Extent3D { width: swapchain_extent.width, height: swapchain_extent.height, depth: 1 }
-        ));
-        
+
         if self.swapchain_resource_handle.is_none() {
             let handle = ResourceHandle(self.resource_counter);
             self.resource_counter += 1;
@@ -206,307 +787,662 @@ impl BnanRenderGraph {
         
         let swapchain_handle = self.swapchain_resource_handle.clone().unwrap();
         
-        // Update swapchain physical resource
+        // Update backbuffer physical resource, its contents are discarded every frame
         let swapchain_physical = PhysicalResource {
             handle: swapchain_handle.clone(),
             name: "Backbuffer".to_string(),
-            resource: ResourceType::SwapchainImage(swapchain_resource_image.clone()),
+            resource: backbuffer_resource,
             current_layout: vk::ImageLayout::UNDEFINED,
-            current_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            current_stage: match image_index {
+                Some(_) => vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                None => vk::PipelineStageFlags2::NONE,
+            },
+            current_access: vk::AccessFlags2::NONE,
             current_queue_family: vk::QUEUE_FAMILY_IGNORED,
+            subresource_states: HashMap::new(),
         };
         self.resources.insert(swapchain_handle.0, swapchain_physical);
-
-        {
-            let device = self.device.lock().unwrap();
-            unsafe {
-                device.device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
-                let begin_info = vk::CommandBufferBeginInfo::default();
-                device.device.begin_command_buffer(command_buffer, &begin_info)?;
-            }
-        }
         
         let frame_time = self.frame_time_reference.elapsed().as_nanos() as f32 / 1000.0;
         self.frame_time_reference = Instant::now();
-        
-        let frame_info = BnanFrameInfo {
-            frame_index: self.current_frame,
-            frame_time, 
-            command_pool: vk::CommandPool::null(),
-            main_command_buffer: command_buffer,
-            swapchain_image: swapchain_resource_image,
-        };
 
-        for pass in &self.passes {
+        let first_graphics_batch = self.queue_batches.iter().position(|batch| batch.queue == QueueType::Graphics).unwrap();
+        let last_graphics_batch = self.queue_batches.iter().rposition(|batch| batch.queue == QueueType::Graphics).unwrap();
+        let mut queue_batch_counts = [0usize; QueueType::COUNT];
+
+        for batch_index in 0..self.queue_batches.len() {
+            let queue = self.queue_batches[batch_index].queue;
+
+            let command_buffer = self.sync.get_queue_command_buffer(self.current_frame, queue, queue_batch_counts[queue as usize])?;
+            queue_batch_counts[queue as usize] += 1;
+
             {
                 let device = self.device.lock().unwrap();
-                let mut barrier_builder = BnanBarrierBuilder::new();
-
-                let mut process_resource = |handle: &ResourceHandle, usage: ResourceUsage, use_frame: usize, builder: &mut BnanBarrierBuilder| {
-                    let (required_stage, required_access) = usage.get_stage_and_access();
-                    let required_layout = usage.get_layout();
-
-                    if let Some(physical) = self.resources.get_mut(&handle.0) {
-                        let is_image = matches!(&physical.resource, ResourceType::SwapchainImage(_) | ResourceType::Image(_));
-                        let needs_barrier = (is_image && physical.current_layout != required_layout)
-                            || physical.current_stage != required_stage;
-
-                        if needs_barrier {
-                            match &physical.resource {
-                                ResourceType::SwapchainImage(image_arc) => {
-                                    let image = image_arc.lock().unwrap();
-                                    let (src_access, src_stage) = Self::get_src_access_and_stage(
-                                        physical.current_layout, physical.current_stage, true
-                                    );
-                                    
-                                    // Use raw barrier push for flexibility or use builder's transition
-                                    // Here we use push_barrier to have full control over access flags
-                                    let barrier = vk::ImageMemoryBarrier2::default()
-                                        .old_layout(physical.current_layout)
-                                        .new_layout(required_layout)
-                                        .image(image.image)
-                                        .subresource_range(vk::ImageSubresourceRange {
-                                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                                            base_mip_level: 0,
-                                            level_count: 1,
-                                            base_array_layer: 0,
-                                            layer_count: 1,
-                                        })
-                                        .src_access_mask(src_access)
-                                        .src_stage_mask(src_stage)
-                                        .dst_access_mask(required_access)
-                                        .dst_stage_mask(required_stage);
-
-                                    builder.push_barrier(barrier);
-                                },
-
-                                ResourceType::Image(images) => {
-                                    let image = images[use_frame].lock().unwrap();
-                                    let (src_access, src_stage) = Self::get_src_access_and_stage(
-                                        physical.current_layout, physical.current_stage, false
-                                    );
-
-                                    let aspect_mask = Self::get_aspect_mask_for_format(image.format);
-
-                                    let barrier = vk::ImageMemoryBarrier2::default()
-                                        .old_layout(physical.current_layout)
-                                        .new_layout(required_layout)
-                                        .image(image.image)
-                                        .subresource_range(vk::ImageSubresourceRange {
-                                            aspect_mask,
-                                            base_mip_level: 0,
-                                            level_count: image.mip_levels,
-                                            base_array_layer: 0,
-                                            layer_count: 1,
-                                        })
-                                        .src_access_mask(src_access)
-                                        .src_stage_mask(src_stage)
-                                        .dst_access_mask(required_access)
-                                        .dst_stage_mask(required_stage);
-
-                                    builder.push_barrier(barrier);
-                                },
-
-                                ResourceType::Buffer(_) => {
-                                    // todo buffers
-                                }
-                            }
+                unsafe {
+                    device.device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+                    let begin_info = vk::CommandBufferBeginInfo::default();
+                    device.device.begin_command_buffer(command_buffer, &begin_info)?;
+                }
+            }
 
-                            physical.current_layout = required_layout;
-                            physical.current_stage = required_stage;
-                        }
-                    }
+            let frame_info = BnanFrameInfo {
+                frame_index: self.current_frame,
+                frame_time,
+                command_pool: vk::CommandPool::null(),
+                main_command_buffer: command_buffer,
+                swapchain_image: backbuffer_image.clone(),
+            };
+
+            let signal_value = self.sync.next_timeline_value(self.current_frame, queue);
+            let queue_waits = self.record_acquires(batch_index, command_buffer, signal_value);
+
+            for pass_position in 0..self.queue_batches[batch_index].passes.len() {
+                let pass_index = self.queue_batches[batch_index].passes[pass_position];
+                let pass_name = self.passes[pass_index].name.clone();
+
+                self.device.lock().unwrap().begin_debug_label(command_buffer, &pass_name, Self::get_debug_label_color(queue));
+                let profile_slot = self.profiler.as_mut().and_then(|profiler| profiler.begin_pass(command_buffer, self.current_frame, &pass_name, queue));
+
+                self.record_pass(pass_index, &frame_info)?;
+
+                if let (Some(profiler), Some(slot)) = (&mut self.profiler, profile_slot) {
+                    profiler.end_pass(command_buffer, self.current_frame, slot);
+                }
+                self.device.lock().unwrap().end_debug_label(command_buffer);
+            }
+
+            if batch_index == last_graphics_batch {
+                self.record_readbacks(command_buffer)?;
+
+                // --- Final Backbuffer Transition ---
+                let device = self.device.lock().unwrap();
+                let physical = self.resources.get(&swapchain_handle.0).unwrap();
+                let image = backbuffer_image.lock().unwrap().image;
+
+                let final_layout = match image_index {
+                    Some(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+                    None => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
                 };
 
-                for input in &pass.inputs {
-                    let use_frame = if input.is_temporal {
-                        (frame_info.frame_index + (FRAMES_IN_FLIGHT - 1)) % FRAMES_IN_FLIGHT
-                    } else {
-                        frame_info.frame_index
-                    };
-                    process_resource(&input.handle, input.usage, use_frame, &mut barrier_builder);
+                BnanBarrierBuilder::new()
+                    .transition_image_layout(image, physical.current_layout, final_layout, None, None)?
+                    .record(&device, command_buffer);
+            }
+
+            self.record_releases(batch_index, command_buffer);
+
+            {
+                let device = self.device.lock().unwrap();
+                unsafe { device.device.end_command_buffer(command_buffer)?; }
+            }
+
+            // --- Submission ---
+            let mut wait_semaphores_vec: Vec<vk::SemaphoreSubmitInfo> = queue_waits.iter()
+                .map(|&(wait_queue, value)| {
+                    vk::SemaphoreSubmitInfo::default()
+                        .semaphore(self.sync.timeline_semaphores[wait_queue as usize])
+                        .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .value(value)
+                })
+                .collect();
+
+            let mut signal_semaphores = vec![
+                vk::SemaphoreSubmitInfo::default()
+                    .semaphore(self.sync.timeline_semaphores[queue as usize])
+                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .value(signal_value)
+            ];
+
+            if let Some(image_index) = image_index {
+                if batch_index == first_graphics_batch {
+                    wait_semaphores_vec.push(
+                        vk::SemaphoreSubmitInfo::default()
+                            .semaphore(self.sync.image_available_semaphores[self.current_frame])
+                            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+                            .value(1)
+                    );
                 }
 
-                for output in &pass.outputs {
-                    process_resource(&output.handle, output.usage, frame_info.frame_index, &mut barrier_builder);
-                    if let Some(resolve_handle) = &output.resolve_target {
-                        let resolve_usage = match output.usage {
-                            ResourceUsage::DepthStencilAttachment => ResourceUsage::DepthStencilResolve,
-                            _ => ResourceUsage::ColorAttachment,
-                        };
-                        process_resource(resolve_handle, resolve_usage, frame_info.frame_index, &mut barrier_builder);
-                    }
+                if batch_index == last_graphics_batch {
+                    signal_semaphores.push(
+                        vk::SemaphoreSubmitInfo::default()
+                            .semaphore(self.sync.render_finished_semaphores[image_index as usize])
+                            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                            .value(1)
+                    );
                 }
+            }
 
-                barrier_builder.record(&device, command_buffer);
-            }
-
-            // --- Dynamic Rendering Setup ---
-            let mut is_dynamic_rendering = false;
-            let mut color_attachments = Vec::new();
-            let mut depth_attachment: Option<vk::RenderingAttachmentInfo> = None;
-            let mut render_extent: Option<vk::Extent2D> = None;
-             
-            for output in &pass.outputs {
-                if matches!(output.usage, ResourceUsage::ColorAttachment) {
-                    if let Some(physical) = self.resources.get(&output.handle.0) {
-                        let image_guard = match &physical.resource {
-                            ResourceType::SwapchainImage(image_arc) => Some(image_arc.lock().unwrap()),
-                            ResourceType::Image(images) => Some(images[frame_info.frame_index].lock().unwrap()),
-                            ResourceType::Buffer(_) => None,
-                        };
-                        
-                        if let Some(image) = image_guard {
-                            // Resolve handling
-                            let mut resolve_view = None;
-                            if let Some(resolve_handle) = &output.resolve_target {
-                                if let Some(resolve_physical) = self.resources.get(&resolve_handle.0) {
-                                    let resolve_image_guard = match &resolve_physical.resource {
-                                        ResourceType::SwapchainImage(image_arc) => Some(image_arc.lock().unwrap()),
-                                        ResourceType::Image(images) => Some(images[frame_info.frame_index].lock().unwrap()),
-                                        ResourceType::Buffer(_) => None,
-                                    };
-                                    if let Some(resolve_image) = resolve_image_guard {
-                                        resolve_view = Some(resolve_image.image_view);
-                                    }
-                                }
-                            }
+            if batch_index == 0 && self.sync.pending_transfer_signal[self.current_frame] {
+                wait_semaphores_vec.push(
+                    vk::SemaphoreSubmitInfo::default()
+                        .semaphore(self.sync.transfer_finished_semaphores[self.current_frame])
+                        .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .value(1)
+                );
+                self.sync.pending_transfer_signal[self.current_frame] = false;
+            }
 
-                            let attachment = output.create_color_attachment_info(image.image_view, resolve_view);
-                            color_attachments.push(attachment);
-                            is_dynamic_rendering = true;
-                             
-                            if render_extent.is_none() {
-                                render_extent = Some(vk::Extent2D {
-                                    width: image.image_extent.width,
-                                    height: image.image_extent.height,
-                                });
-                            }
+            let command_buffer_infos = [
+                 vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer)
+            ];
+
+            let submit_info = [vk::SubmitInfo2::default()
+                .wait_semaphore_infos(&wait_semaphores_vec)
+                .signal_semaphore_infos(&signal_semaphores)
+                .command_buffer_infos(&command_buffer_infos)
+            ];
+
+            // the fence covers the graphics queue, other queues are waited on through their timelines
+            let fence = match batch_index == last_graphics_batch {
+                true => self.sync.in_flight_fences[self.current_frame],
+                false => vk::Fence::null(),
+            };
+
+            {
+                 let device = self.device.lock().unwrap();
+                 unsafe {
+                    device.device.queue_submit2(self.queues[queue as usize], &submit_info, fence)?;
+                 }
+            }
+        }
+        
+        if let Some(image_index) = image_index {
+            self.present_swapchain_image(image_index)?;
+        }
+
+        if self.capturing_barriers {
+            self.capturing_barriers = false;
+            let dump = self.dump();
+
+            if let (true, Some((_, directory))) = (dump_due, &self.dump_interval) {
+                dump.save(directory.join(format!("graph_{}.dot", self.frame_count)))?;
+                dump.save(directory.join(format!("graph_{}.json", self.frame_count)))?;
+            }
+
+            if std::mem::take(&mut self.dump_requested) {
+                self.last_dump = Some(dump);
+            }
+        }
+
+        self.frame_count += 1;
+        self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT;
+        Ok(())
+    }
+
+    fn record_pass(&mut self, pass_index: usize, frame_info: &BnanFrameInfo) -> Result<()> {
+        let pass = &self.passes[pass_index];
+        let command_buffer = frame_info.main_command_buffer;
+        let render_extent_fallback = self.get_extent();
+
+        {
+            let device = self.device.lock().unwrap();
+            let mut barrier_builder = BnanBarrierBuilder::new();
+
+            let mut process_resource = |handle: &ResourceHandle, usage: ResourceUsage, use_frame: usize, subresource: SubresourceRange, builder: &mut BnanBarrierBuilder| {
+                let (required_stage, required_access) = usage.get_stage_and_access();
+                let required_layout = usage.get_layout();
+
+                if let Some(physical) = self.resources.get_mut(&handle.0) {
+                    if let ResourceType::Buffer(buffers) = &physical.resource {
+                        // buffers only need a barrier for read after write, write after read and write after write
+                        let previous_write = ResourceUsage::is_write_access(physical.current_access);
+                        let hazard = previous_write || (usage.is_write() && physical.current_stage != vk::PipelineStageFlags2::NONE);
+
+                        if hazard {
+                            let buffer = buffers[use_frame].lock().unwrap();
+                            builder.buffer_barrier(buffer.buffer, physical.current_stage, physical.current_access, required_stage, required_access);
+
+                            physical.current_stage = required_stage;
+                            physical.current_access = required_access;
+                        } else {
+                            // accumulate readers so a later write waits on all of them
+                            physical.current_stage |= required_stage;
+                            physical.current_access |= required_access;
                         }
+
+                        return;
                     }
-                } else if matches!(output.usage, ResourceUsage::DepthStencilAttachment) {
-                    if let Some(physical) = self.resources.get(&output.handle.0) {
-                        let image_guard = match &physical.resource {
-                            ResourceType::SwapchainImage(image_arc) => Some(image_arc.lock().unwrap()),
-                            ResourceType::Image(images) => Some(images[frame_info.frame_index].lock().unwrap()),
-                            ResourceType::Buffer(_) => None,
-                        };
-
-                        if let Some(image) = image_guard {
-                            // Resolve handling
-                            let mut resolve_view = None;
-                            if let Some(resolve_handle) = &output.resolve_target {
-                                if let Some(resolve_physical) = self.resources.get(&resolve_handle.0) {
-                                    let resolve_image_guard = match &resolve_physical.resource {
-                                        ResourceType::SwapchainImage(image_arc) => Some(image_arc.lock().unwrap()),
-                                        ResourceType::Image(images) => Some(images[frame_info.frame_index].lock().unwrap()),
-                                        ResourceType::Buffer(_) => None,
-                                    };
-                                    if let Some(resolve_image) = resolve_image_guard {
-                                        resolve_view = Some(resolve_image.image_view);
-                                    }
+
+                    match &physical.resource {
+                        ResourceType::SwapchainImage(image_arc) => {
+                            let needs_barrier = physical.current_layout != required_layout
+                                || physical.current_stage != required_stage;
+
+                            if needs_barrier {
+                                let image = image_arc.lock().unwrap();
+                                let (src_access, src_stage) = Self::get_src_access_and_stage(
+                                    physical.current_layout, physical.current_stage, physical.current_access, true
+                                );
+
+                                // Use raw barrier push for flexibility or use builder's transition
+                                // Here we use push_barrier to have full control over access flags
+                                let barrier = vk::ImageMemoryBarrier2::default()
+                                    .old_layout(physical.current_layout)
+                                    .new_layout(required_layout)
+                                    .image(image.image)
+                                    .subresource_range(vk::ImageSubresourceRange {
+                                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                                        base_mip_level: 0,
+                                        level_count: 1,
+                                        base_array_layer: 0,
+                                        layer_count: 1,
+                                    })
+                                    .src_access_mask(src_access)
+                                    .src_stage_mask(src_stage)
+                                    .dst_access_mask(required_access)
+                                    .dst_stage_mask(required_stage);
+
+                                builder.push_barrier(barrier);
+                                drop(image);
+                                physical.set_uniform_state(required_layout, required_stage, required_access);
+                            }
+                        },
+
+                        ResourceType::Image(images) => {
+                            let image = images[use_frame].lock().unwrap();
+                            let (mip_levels, layer_count) = (image.mip_levels, image.get_layer_count());
+                            let range = subresource.resolve(mip_levels, layer_count);
+                            let aspect_mask = Self::get_aspect_mask_for_format(image.format);
+
+                            // one barrier per piece of the range that is in a different state
+                            let mut transitioned = false;
+                            for (piece, state) in physical.get_subresource_transitions(range) {
+                                if state.layout == required_layout && state.stage == required_stage {
+                                    continue;
                                 }
+
+                                let (src_access, src_stage) = Self::get_src_access_and_stage(state.layout, state.stage, state.access, false);
+
+                                let barrier = vk::ImageMemoryBarrier2::default()
+                                    .old_layout(state.layout)
+                                    .new_layout(required_layout)
+                                    .image(image.image)
+                                    .subresource_range(piece.to_vk(aspect_mask))
+                                    .src_access_mask(src_access)
+                                    .src_stage_mask(src_stage)
+                                    .dst_access_mask(required_access)
+                                    .dst_stage_mask(required_stage);
+
+                                builder.push_barrier(barrier);
+                                transitioned = true;
                             }
 
-                            depth_attachment = Some(output.create_depth_attachment_info(image.image_view, resolve_view));
-                            is_dynamic_rendering = true;
-                             
-                            if render_extent.is_none() {
-                                render_extent = Some(vk::Extent2D {
-                                    width: image.image_extent.width,
-                                    height: image.image_extent.height,
-                                });
+                            if transitioned {
+                                let state = SubresourceState { layout: required_layout, stage: required_stage, access: required_access };
+                                drop(image);
+                                physical.set_subresource_state(range, mip_levels, layer_count, state);
                             }
-                        }
+                        },
+
+                        // handled above
+                        ResourceType::Buffer(_) => {}
                     }
                 }
+            };
+
+            for (handle, usage, is_temporal, subresource) in pass.resource_uses() {
+                let use_frame = Self::get_use_slot(frame_info.frame_index, is_temporal);
+                process_resource(&handle, usage, use_frame, subresource, &mut barrier_builder);
             }
-             
-            if is_dynamic_rendering {
-                let extent = render_extent.unwrap_or(swapchain_extent);
-                 
-                let mut rendering_info = vk::RenderingInfo::default()
-                    .render_area(vk::Rect2D { offset: vk::Offset2D{x:0, y:0}, extent })
-                    .layer_count(1)
-                    .color_attachments(&color_attachments);
-                     
-                if let Some(depth) = &depth_attachment {
-                    rendering_info = rendering_info.depth_attachment(depth);
+
+            if self.capturing_barriers {
+                // map raw vulkan handles back to the graph resources they belong to
+                let mut raw_handles = HashMap::new();
+                for (handle, _, is_temporal, _) in pass.resource_uses() {
+                    let slot = Self::get_use_slot(frame_info.frame_index, is_temporal);
+                    let raw = match self.resources.get(&handle.0).map(|physical| &physical.resource) {
+                        Some(ResourceType::SwapchainImage(image)) => image.lock().unwrap().image.as_raw(),
+                        Some(ResourceType::Image(images)) => images[slot].lock().unwrap().image.as_raw(),
+                        Some(ResourceType::Buffer(buffers)) => buffers[slot].lock().unwrap().buffer.as_raw(),
+                        None => continue,
+                    };
+                    raw_handles.insert(raw, handle.0);
                 }
-                     
-                let device = self.device.lock().unwrap();
-                unsafe { device.device.cmd_begin_rendering(command_buffer, &rendering_info); }
+
+                let barriers = BarrierDump::from_barriers(barrier_builder.get_image_barriers(), barrier_builder.get_buffer_barriers(), &raw_handles);
+                self.captured_barriers.insert(pass_index, barriers);
             }
 
-            match &pass.execute {
-                f => f(&self, &frame_info),
+            barrier_builder.record(&device, command_buffer);
+        }
+
+        // --- Dynamic Rendering Setup ---
+        let mut is_dynamic_rendering = false;
+        let mut color_attachments = Vec::new();
+        let mut depth_attachment: Option<vk::RenderingAttachmentInfo> = None;
+        let mut render_extent: Option<vk::Extent2D> = None;
+         
+        let mut render_layers = 1;
+
+        for output in &pass.outputs {
+            let is_color = matches!(output.usage, ResourceUsage::ColorAttachment);
+            if !is_color && !matches!(output.usage, ResourceUsage::DepthStencilAttachment) {
+                continue;
             }
-             
-            if is_dynamic_rendering {
-                let device = self.device.lock().unwrap();
-                unsafe { device.device.cmd_end_rendering(command_buffer); }
+
+            let Some(image_arc) = self.get_attachment_image(&output.handle, frame_info.frame_index) else {
+                continue;
+            };
+
+            let (view, extent, layers) = Self::get_attachment_view(&mut image_arc.lock().unwrap(), output.subresource)?;
+
+            // Resolve handling
+            let resolve_view = match output.resolve_target.as_ref().and_then(|handle| self.get_attachment_image(handle, frame_info.frame_index)) {
+                Some(resolve_arc) => Some(Self::get_attachment_view(&mut resolve_arc.lock().unwrap(), output.subresource)?.0),
+                None => None,
+            };
+
+            match is_color {
+                true => color_attachments.push(output.create_color_attachment_info(view, resolve_view)),
+                false => depth_attachment = Some(output.create_depth_attachment_info(view, resolve_view)),
+            }
+
+            is_dynamic_rendering = true;
+
+            if render_extent.is_none() {
+                render_extent = Some(extent);
+                render_layers = layers;
             }
         }
+         
+        if is_dynamic_rendering {
+            let extent = render_extent.unwrap_or(render_extent_fallback);
 
-        // --- Final Swapchain Transition ---
-        {
+            // with multiview the layer count is ignored and each view writes its own layer
+            let mut rendering_info = vk::RenderingInfo::default()
+                .render_area(vk::Rect2D { offset: vk::Offset2D{x:0, y:0}, extent })
+                .layer_count(render_layers)
+                .view_mask(pass.view_mask)
+                .color_attachments(&color_attachments);
+                 
+            if let Some(depth) = &depth_attachment {
+                rendering_info = rendering_info.depth_attachment(depth);
+            }
+                 
             let device = self.device.lock().unwrap();
-            let swapchain_handle = self.swapchain_resource_handle.clone().unwrap();
-            let physical = self.resources.get(&swapchain_handle.0).unwrap();
+            unsafe { device.device.cmd_begin_rendering(command_buffer, &rendering_info); }
+        }
+
+        (pass.execute)(self, frame_info);
+         
+        if is_dynamic_rendering {
+            let device = self.device.lock().unwrap();
+            unsafe { device.device.cmd_end_rendering(command_buffer); }
+        }
+
+        Ok(())
+    }
+
+    fn get_attachment_image(&self, handle: &ResourceHandle, frame: usize) -> Option<ArcMut<BnanImage>> {
+        match &self.resources.get(&handle.0)?.resource {
+            ResourceType::SwapchainImage(image) => Some(image.clone()),
+            ResourceType::Image(images) => Some(images[frame].clone()),
+            ResourceType::Buffer(_) => None,
+        }
+    }
+
+    /// View, extent and layer count an attachment renders to. Whole single layer images use their default view.
+    fn get_attachment_view(image: &mut BnanImage, subresource: SubresourceRange) -> Result<(vk::ImageView, vk::Extent2D, u32)> {
+        let range = subresource.resolve(image.mip_levels, image.get_layer_count());
+        let extent = image.mip_extent(range.base_mip);
+        let extent = vk::Extent2D { width: extent.width, height: extent.height };
+
+        if image.get_layer_count() == 1 && image.mip_levels == 1 {
+            return Ok((image.image_view, extent, 1));
+        }
+
+        let view = image.get_subresource_view(range.base_mip, range.base_layer, range.layer_count)?;
+        Ok((view, extent, range.layer_count))
+    }
+
+    /// Acquires resources released to this batch's queue and collects the timeline values it must wait on
+    fn record_acquires(&mut self, batch_index: usize, command_buffer: vk::CommandBuffer, signal_value: u64) -> Vec<(QueueType, u64)> {
+        let queue = self.queue_batches[batch_index].queue;
+        let family = self.queue_families[queue as usize];
 
-            let builder = BnanBarrierBuilder::new()
-                .transition_image_layout(swapchain_image, physical.current_layout, vk::ImageLayout::PRESENT_SRC_KHR, None, None)?
-                .record(&*device, command_buffer);
+        let mut wait_values = [0u64; QueueType::COUNT];
+        let mut builder = BnanBarrierBuilder::new();
 
-            unsafe {
-                device.device.end_command_buffer(command_buffer)?;
+        for &pass_index in &self.queue_batches[batch_index].passes {
+            for (handle, _, is_temporal, _) in self.passes[pass_index].resource_uses() {
+                // anything last touched on another queue is waited on, including work from the previous frame
+                if let Some((last_queue, value)) = self.resource_submissions.insert(handle.0, (queue, signal_value)) && last_queue != queue {
+                    wait_values[last_queue as usize] = wait_values[last_queue as usize].max(value);
+                }
+
+                let slot = Self::get_use_slot(self.current_frame, is_temporal);
+                let Some(physical) = self.resources.get_mut(&handle.0) else {
+                    continue;
+                };
+
+                if let Some(transfer) = self.pending_ownership.get(&(handle.0, slot)).cloned() && transfer.dst_family == family {
+                    self.pending_ownership.remove(&(handle.0, slot));
+                    Self::push_ownership_barrier(physical, slot, &transfer, false, &mut builder);
+                    physical.set_uniform_state(transfer.new_layout, transfer.dst_stage, transfer.dst_access);
+                }
+
+                physical.current_queue_family = family;
             }
         }
-        
-        let mut wait_semaphores_vec = vec![
-            vk::SemaphoreSubmitInfo::default()
-                .semaphore(self.sync.image_available_semaphores[self.current_frame])
-                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                .value(1)
-        ];
-        
-        if self.sync.pending_transfer_signal[self.current_frame] {
-            wait_semaphores_vec.push(
-                vk::SemaphoreSubmitInfo::default()
-                    .semaphore(self.sync.transfer_finished_semaphores[self.current_frame])
-                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                    .value(1)
-            );
-            self.sync.pending_transfer_signal[self.current_frame] = false;
+
+        builder.record(&self.device.lock().unwrap(), command_buffer);
+
+        QueueType::ALL.iter().cloned()
+            .filter(|&wait_queue| wait_values[wait_queue as usize] > 0)
+            .map(|wait_queue| (wait_queue, wait_values[wait_queue as usize]))
+            .collect()
+    }
+
+    /// Releases resources whose next use, possibly in a later frame, is on a queue of another family
+    fn record_releases(&mut self, batch_index: usize, command_buffer: vk::CommandBuffer) {
+        let queue = self.queue_batches[batch_index].queue;
+        let family = self.queue_families[queue as usize];
+        let backbuffer = self.get_backbuffer_handle();
+
+        let mut released = HashSet::new();
+        let mut builder = BnanBarrierBuilder::new();
+
+        for &pass_index in self.queue_batches[batch_index].passes.iter().rev() {
+            for (handle, _, is_temporal, _) in self.passes[pass_index].resource_uses().into_iter().rev() {
+                if handle == backbuffer || !released.insert((handle.0, is_temporal)) {
+                    continue;
+                }
+
+                let Some((next_queue, next_usage, frames_ahead)) = self.find_next_use(batch_index, handle.0, is_temporal) else {
+                    continue;
+                };
+
+                // aliased memory is discarded between frames so there is nothing to hand over
+                let dst_family = self.queue_families[next_queue as usize];
+                if dst_family == family || (frames_ahead > 0 && self.aliased_resources.contains(&handle.0)) {
+                    continue;
+                }
+
+                let Some(physical) = self.resources.get_mut(&handle.0) else {
+                    continue;
+                };
+
+                // images are handed over as a whole, per-subresource state only lives on one queue
+                let (src_access, src_stage, new_layout) = match &physical.resource {
+                    ResourceType::Buffer(_) => (physical.current_access, physical.current_stage, vk::ImageLayout::UNDEFINED),
+                    ResourceType::Image(_) if physical.is_uniform() && physical.current_layout != vk::ImageLayout::UNDEFINED => {
+                        let (src_access, src_stage) = Self::get_src_access_and_stage(physical.current_layout, physical.current_stage, physical.current_access, false);
+                        (src_access, src_stage, next_usage.get_layout())
+                    }
+                    _ => continue,
+                };
+
+                let (dst_stage, dst_access) = next_usage.get_stage_and_access();
+
+                let transfer = OwnershipTransfer {
+                    src_family: family,
+                    dst_family,
+                    old_layout: physical.current_layout,
+                    new_layout,
+                    src_stage,
+                    src_access,
+                    dst_stage,
+                    dst_access,
+                };
+
+                let slot = Self::get_use_slot(self.current_frame, is_temporal);
+                Self::push_ownership_barrier(physical, slot, &transfer, true, &mut builder);
+                self.pending_ownership.insert((handle.0, slot), transfer);
+            }
         }
-        
-        let signal_semaphores = [
-            vk::SemaphoreSubmitInfo::default()
-                .semaphore(self.sync.render_finished_semaphores[image_index as usize])
-                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                .value(1)
-        ];
-        let command_buffer_infos = [
-             vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer)
-        ];
 
-        let submit_info = [vk::SubmitInfo2::default()
-            .wait_semaphore_infos(&wait_semaphores_vec)
-            .signal_semaphore_infos(&signal_semaphores)
-            .command_buffer_infos(&command_buffer_infos)
-        ];
-        
-        let graphics_queue = self.device.lock().unwrap().graphics_queue;
+        builder.record(&self.device.lock().unwrap(), command_buffer);
+    }
 
-        {
-             let device = self.device.lock().unwrap();
-             unsafe {
-                device.device.queue_submit2(graphics_queue, &submit_info, self.sync.in_flight_fences[self.current_frame])?;
-             }
+    /// Next batch that touches the same frame slot of a resource, searching into the following frames.
+    /// Returns the batch queue, the usage there and how many frames ahead it is.
+    fn find_next_use(&self, batch_index: usize, handle: usize, is_temporal: bool) -> Option<(QueueType, ResourceUsage, usize)> {
+        for frames_ahead in 0..=FRAMES_IN_FLIGHT {
+            let batch_range = match frames_ahead {
+                0 => batch_index + 1..self.queue_batches.len(),
+                FRAMES_IN_FLIGHT => 0..batch_index + 1,
+                _ => 0..self.queue_batches.len(),
+            };
+
+            for batch in &self.queue_batches[batch_range] {
+                for &pass_index in &batch.passes {
+                    for (use_handle, usage, use_temporal, _) in self.passes[pass_index].resource_uses() {
+                        // a temporal use reads the slot written one frame earlier
+                        let slot_offset = frames_ahead as isize - use_temporal as isize + is_temporal as isize;
+
+                        if use_handle.0 == handle && slot_offset.rem_euclid(FRAMES_IN_FLIGHT as isize) == 0 {
+                            return Some((batch.queue, usage, frames_ahead));
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn get_use_slot(current_frame: usize, is_temporal: bool) -> usize {
+        match is_temporal {
+            true => (current_frame + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT,
+            false => current_frame,
         }
+    }
+
+    fn push_ownership_barrier(physical: &PhysicalResource, slot: usize, transfer: &OwnershipTransfer, release: bool, builder: &mut BnanBarrierBuilder) {
+        // the release only has a source scope and the acquire only a destination scope
+        let (src_stage, src_access, dst_stage, dst_access) = match release {
+            true => (transfer.src_stage, transfer.src_access, vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+            false => (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE, transfer.dst_stage, transfer.dst_access),
+        };
+
+        match &physical.resource {
+            ResourceType::Image(images) => {
+                let image = images[slot].lock().unwrap();
+
+                let barrier = vk::ImageMemoryBarrier2::default()
+                    .old_layout(transfer.old_layout)
+                    .new_layout(transfer.new_layout)
+                    .src_queue_family_index(transfer.src_family)
+                    .dst_queue_family_index(transfer.dst_family)
+                    .image(image.image)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: Self::get_aspect_mask_for_format(image.format),
+                        base_mip_level: 0,
+                        level_count: image.mip_levels,
+                        base_array_layer: 0,
+                        layer_count: image.get_layer_count(),
+                    })
+                    .src_stage_mask(src_stage)
+                    .src_access_mask(src_access)
+                    .dst_stage_mask(dst_stage)
+                    .dst_access_mask(dst_access);
+
+                builder.push_barrier(barrier);
+            }
+
+            ResourceType::Buffer(buffers) => {
+                let buffer = buffers[slot].lock().unwrap();
+
+                let barrier = vk::BufferMemoryBarrier2::default()
+                    .buffer(buffer.buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .src_queue_family_index(transfer.src_family)
+                    .dst_queue_family_index(transfer.dst_family)
+                    .src_stage_mask(src_stage)
+                    .src_access_mask(src_access)
+                    .dst_stage_mask(dst_stage)
+                    .dst_access_mask(dst_access);
+
+                builder.push_buffer_barrier(barrier);
+            }
+
+            // swapchain images never leave the graphics queue
+            ResourceType::SwapchainImage(_) => {}
+        }
+    }
+
+    fn acquire_swapchain_image(&mut self) -> Result<Option<(u32, ArcMut<BnanImage>)>> {
+        let Some(swapchain) = self.swapchain.clone() else {
+            bail!("render graph has no swapchain to acquire from");
+        };
+
+        let (swapchain_loader, swapchain_khr, swapchain_images, swapchain_views, swapchain_extent, swapchain_format) = {
+             let device = self.device.lock().unwrap();
+             let swapchain = swapchain.lock().unwrap();
+
+             (
+                 khr::swapchain::Device::new(&device.instance, &device.device),
+                 swapchain.swapchain,
+                 swapchain.images.clone(),
+                 swapchain.image_views.clone(),
+                 swapchain.extent,
+                 swapchain.surface_format.format
+             )
+        };
+
+        let acquire_result = unsafe {
+            swapchain_loader.acquire_next_image(
+                swapchain_khr,
+                u64::MAX,
+                self.sync.image_available_semaphores[self.current_frame],
+                vk::Fence::null()
+            )
+        };
         
+        let image_index = match acquire_result {
+            Ok((idx, _)) => idx,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                 self.recreate_swapchain()?;
+                 return Ok(None);
+            }
+            Err(e) => return Err(Error::new(e)),
+        };
+
+        let swapchain_image = make_arcmut(BnanImage::from_image(
+            self.device.clone(),
+            swapchain_images[image_index as usize],
+            swapchain_views[image_index as usize],
+            swapchain_format,
+            vk::Extent3D { width: swapchain_extent.width, height: swapchain_extent.height, depth: 1 }
+        ));
+
+        Ok(Some((image_index, swapchain_image)))
+    }
+
+    fn present_swapchain_image(&mut self, image_index: u32) -> Result<()> {
+        let Some(swapchain) = self.swapchain.clone() else {
+            bail!("render graph has no swapchain to present to");
+        };
+
+        let (swapchain_loader, graphics_queue) = {
+            let device = self.device.lock().unwrap();
+            (khr::swapchain::Device::new(&device.instance, &device.device), device.graphics_queue)
+        };
+
         let wait_semaphores_present = [self.sync.render_finished_semaphores[image_index as usize]];
-        let swapchains = [swapchain_khr];
+        let swapchains = [swapchain.lock().unwrap().swapchain];
         let image_indices = [image_index];
 
         let present_info = vk::PresentInfoKHR::default()
@@ -524,16 +1460,99 @@ impl BnanRenderGraph {
                 Err(e) => return Err(Error::new(e)),
             }
         }
-        
-        self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT;
+
         Ok(())
     }
+
+    /// Index of the most recently submitted frame
+    pub fn get_last_frame_index(&self) -> usize {
+        (self.current_frame + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT
+    }
+
+    /// Queues a copy of `handle` at the given mip level into the next executed frame.
+    /// Results are collected with `take_readbacks` once that frame's fence has signaled.
+    pub fn request_readback(&mut self, handle: &ResourceHandle, mip_level: u32) {
+        self.readback_requests.push((handle.clone(), mip_level));
+    }
+
+    /// Waits on every frame with outstanding readbacks and returns the decoded pixels
+    pub fn take_readbacks(&mut self) -> Result<Vec<(ResourceHandle, BnanReadback)>> {
+        let mut readbacks = Vec::new();
+
+        for frame in 0..FRAMES_IN_FLIGHT {
+            if self.pending_readbacks[frame].is_empty() {
+                continue;
+            }
+
+            self.sync.wait_in_flight(frame)?;
+
+            for (handle, readback_buffer) in self.pending_readbacks[frame].drain(..) {
+                readbacks.push((handle, readback_buffer.resolve()?));
+            }
+        }
+
+        Ok(readbacks)
+    }
+
+    fn record_readbacks(&mut self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        if self.readback_requests.is_empty() {
+            return Ok(());
+        }
+
+        let requests = std::mem::take(&mut self.readback_requests);
+
+        for (handle, mip_level) in requests {
+            let Some(image_arc) = self.get_image(&handle, self.current_frame) else {
+                bail!("readback requested for unknown image resource {}", handle.0);
+            };
+
+            let physical = self.resources.get(&handle.0).unwrap();
+            if !physical.is_uniform() {
+                bail!("readback of {} whose mips or layers are in different layouts", physical.name);
+            }
+
+            let current_layout = physical.current_layout;
+            let image = image_arc.lock().unwrap();
+            let readback_buffer = BnanReadbackBuffer::new(self.device.clone(), &image, mip_level, 0)?;
+
+            let device = self.device.lock().unwrap();
+
+            // transition back afterwards so the tracked layout stays valid for the rest of the ring
+            let to_transfer = BnanReadback::build_readback_barrier(&image, current_layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+            BnanBarrierBuilder::new().push_barrier(to_transfer).record(&device, command_buffer);
+
+            readback_buffer.record_copy(&device, command_buffer, image.image);
+
+            if current_layout != vk::ImageLayout::UNDEFINED {
+                let from_transfer = BnanReadback::build_readback_barrier(&image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, current_layout);
+                BnanBarrierBuilder::new().push_barrier(from_transfer).record(&device, command_buffer);
+            }
+
+            self.pending_readbacks[self.current_frame].push((handle, readback_buffer));
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the given frame's fence and reads back one layer of its offscreen backbuffer
+    pub fn read_frame(&self, frame: usize, layer: u32) -> Result<BnanReadback> {
+        let Some(images) = &self.offscreen_images else {
+            bail!("render graph has no offscreen target to read back");
+        };
+
+        self.sync.wait_in_flight(frame)?;
+        BnanReadback::capture(self.device.clone(), &images[frame], vk::ImageLayout::TRANSFER_SRC_OPTIMAL, 0, layer)
+    }
     
     pub fn recreate_swapchain(&mut self) -> Result<()> {
-         let extent = self.window.lock().unwrap().get_window_extent();
-         self.swapchain.lock().unwrap().recreate_swapchain(extent)?;
+         let (Some(window), Some(swapchain)) = (&self.window, &self.swapchain) else {
+             bail!("render graph has no swapchain to recreate");
+         };
+
+         let extent = window.lock().unwrap().get_window_extent();
+         swapchain.lock().unwrap().recreate_swapchain(extent)?;
          
-         let image_count = self.swapchain.lock().unwrap().images.len();
+         let image_count = swapchain.lock().unwrap().images.len();
          self.sync.recreate_semaphores(image_count)?;
          
          Ok(())
@@ -595,3 +1614,11 @@ impl BnanRenderGraph {
         self.device.lock().unwrap().get_queue_indices()
     }
 }
+
+impl Drop for BnanRenderGraph {
+    fn drop(&mut self) {
+        if let Err(error) = self.release_transients() {
+            eprintln!("failed to release render graph transients: {:?}", error);
+        }
+    }
+}
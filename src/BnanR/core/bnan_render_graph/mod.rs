@@ -1,4 +1,7 @@
 pub mod resource;
 pub mod pass;
 pub mod graph;
+pub mod compile;
 pub mod sync;
+pub mod debug;
+pub mod profiler;
@@ -1,7 +1,32 @@
 use ash::vk;
 use crate::core::bnan_render_graph::graph::BnanRenderGraph;
 use crate::core::bnan_rendering::BnanFrameInfo;
-use crate::core::bnan_render_graph::resource::{ResourceHandle, ResourceUsage};
+use crate::core::bnan_render_graph::resource::{ResourceHandle, ResourceUsage, SubresourceRange};
+
+/// Queue a pass is recorded on. Falls back to graphics when the device has no separate queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QueueType {
+    Graphics,
+    AsyncCompute,
+    Transfer,
+}
+
+impl QueueType {
+    pub const COUNT: usize = 3;
+    pub const ALL: [QueueType; Self::COUNT] = [QueueType::Graphics, QueueType::AsyncCompute, QueueType::Transfer];
+
+    /// Whether a pass on this queue may use the resource this way
+    pub fn supports_usage(self, usage: ResourceUsage) -> bool {
+        match self {
+            QueueType::Graphics => true,
+            QueueType::AsyncCompute => matches!(usage,
+                ResourceUsage::StorageRead | ResourceUsage::StorageWrite | ResourceUsage::TransferSrc |
+                ResourceUsage::TransferDst | ResourceUsage::IndirectRead | ResourceUsage::UniformRead
+            ),
+            QueueType::Transfer => matches!(usage, ResourceUsage::TransferSrc | ResourceUsage::TransferDst),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct AttachmentConfig {
@@ -26,6 +51,7 @@ pub struct RenderPassResource {
     pub resolve_target: Option<ResourceHandle>,
     pub is_temporal: bool,
     pub config: AttachmentConfig,
+    pub subresource: SubresourceRange,
 }
 
 impl RenderPassResource {
@@ -41,6 +67,7 @@ impl RenderPassResource {
             resolve_target: None,
             is_temporal: false,
             config,
+            subresource: SubresourceRange::ALL,
         }
     }
     
@@ -56,6 +83,7 @@ impl RenderPassResource {
             resolve_target: Some(resolve),
             is_temporal: false,
             config,
+            subresource: SubresourceRange::ALL,
         }
     }
     
@@ -66,9 +94,17 @@ impl RenderPassResource {
             resolve_target: None,
             is_temporal: true,
             config: AttachmentConfig::default(),
+            subresource: SubresourceRange::ALL,
         }
     }
     
+    /// Restricts the use to some mips or layers. Attachments render to one mip and every layer in the range,
+    /// the resolve target is resolved into the same range.
+    pub fn with_subresource(mut self, subresource: SubresourceRange) -> Self {
+        self.subresource = subresource;
+        self
+    }
+
     pub fn set_load_op(mut self, op: vk::AttachmentLoadOp) -> Self {
         self.config.load_op = op;
         self
@@ -123,6 +159,9 @@ pub struct RenderPass {
     pub name: String,
     pub inputs: Vec<RenderPassResource>,
     pub outputs: Vec<RenderPassResource>,
+    pub dependencies: Vec<String>,
+    pub queue: QueueType,
+    pub view_mask: u32,
     pub execute: Box<dyn Fn(&BnanRenderGraph, &BnanFrameInfo)>,
 }
 
@@ -137,7 +176,51 @@ impl RenderPass {
             name,
             inputs,
             outputs,
+            dependencies: Vec::new(),
+            queue: QueueType::Graphics,
+            view_mask: 0,
             execute,
         }
     }
+    
+    /// Orders this pass after the named pass, needed when both write the same resource
+    pub fn depends_on(mut self, name: &str) -> Self {
+        self.dependencies.push(name.to_string());
+        self
+    }
+
+    /// Records this pass on another queue, `frame_info.main_command_buffer` is then that queue's command buffer
+    pub fn on_queue(mut self, queue: QueueType) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Renders every view in the mask with multiview, view `i` goes to layer `i` of each attachment.
+    /// Without a view mask a pass whose attachments cover several layers uses layered rendering instead.
+    pub fn multiview(mut self, view_mask: u32) -> Self {
+        self.view_mask = view_mask;
+        self
+    }
+
+    /// Every resource the pass touches in barrier order, as (handle, usage, is_temporal, subresource).
+    /// Resolve targets follow the attachment they resolve.
+    pub fn resource_uses(&self) -> Vec<(ResourceHandle, ResourceUsage, bool, SubresourceRange)> {
+        let mut uses: Vec<_> = self.inputs.iter()
+            .map(|input| (input.handle.clone(), input.usage, input.is_temporal, input.subresource))
+            .collect();
+
+        for output in &self.outputs {
+            uses.push((output.handle.clone(), output.usage, output.is_temporal, output.subresource));
+
+            if let Some(resolve_target) = &output.resolve_target {
+                let resolve_usage = match output.usage {
+                    ResourceUsage::DepthStencilAttachment => ResourceUsage::DepthStencilResolve,
+                    _ => ResourceUsage::ColorAttachment,
+                };
+                uses.push((resolve_target.clone(), resolve_usage, false, output.subresource));
+            }
+        }
+
+        uses
+    }
 }
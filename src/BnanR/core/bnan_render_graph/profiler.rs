@@ -0,0 +1,307 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use anyhow::*;
+use ash::vk;
+
+use crate::core::ArcMut;
+use crate::core::bnan_device::BnanDevice;
+use crate::core::bnan_rendering::FRAMES_IN_FLIGHT;
+use crate::core::bnan_render_graph::pass::QueueType;
+
+/// Upper bound on passes timed per frame, passes past it are simply not profiled
+pub const MAX_PROFILED_PASSES: u32 = 128;
+
+/// Number of frames the rolling averages are taken over
+pub const PROFILER_HISTORY: usize = 64;
+
+const STATISTICS_COUNT: usize = 7;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStatistics {
+    pub input_assembly_vertices: u64,
+    pub input_assembly_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    pub clipping_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+    pub compute_shader_invocations: u64,
+}
+
+impl PipelineStatistics {
+    const FLAGS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+        vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw() |
+        vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw() |
+        vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw() |
+        vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw() |
+        vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw() |
+        vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw() |
+        vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw()
+    );
+
+    /// Counters are written in flag bit order
+    fn from_results(results: [u64; STATISTICS_COUNT]) -> Self {
+        Self {
+            input_assembly_vertices: results[0],
+            input_assembly_primitives: results[1],
+            vertex_shader_invocations: results[2],
+            clipping_invocations: results[3],
+            clipping_primitives: results[4],
+            fragment_shader_invocations: results[5],
+            compute_shader_invocations: results[6],
+        }
+    }
+}
+
+/// GPU time of one pass over the last `PROFILER_HISTORY` frames
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub name: String,
+    pub queue: QueueType,
+    pub last_ms: f64,
+    pub average_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub statistics: Option<PipelineStatistics>,
+}
+
+struct ProfiledPass {
+    name: String,
+    queue: QueueType,
+    // statistics queries are only issued on graphics, so they are numbered separately from the timestamps
+    statistics_slot: Option<u32>,
+}
+
+/// Timestamp and pipeline statistics queries around every pass. Queries are reset from the host
+/// so passes on different queues can share one pool per frame in flight.
+pub struct GpuProfiler {
+    pub device: ArcMut<BnanDevice>,
+    pub timestamp_pools: [vk::QueryPool; FRAMES_IN_FLIGHT],
+    pub statistics_pools: Option<[vk::QueryPool; FRAMES_IN_FLIGHT]>,
+    pub timestamp_period: f64,
+    pub timestamp_valid_bits: [u32; QueueType::COUNT],
+
+    frame_passes: [Vec<ProfiledPass>; FRAMES_IN_FLIGHT],
+    history: HashMap<String, VecDeque<f64>>,
+    statistics: HashMap<String, PipelineStatistics>,
+    report_order: Vec<(String, QueueType)>,
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        let device = self.device.lock().unwrap();
+
+        unsafe {
+            device.device.device_wait_idle().unwrap();
+
+            for pool in self.timestamp_pools.iter().chain(self.statistics_pools.iter().flatten()) {
+                device.device.destroy_query_pool(*pool, None);
+            }
+        }
+    }
+}
+
+impl GpuProfiler {
+    /// `queue_families` are indexed by QueueType, statistics are only collected when the device supports them
+    pub fn new(device: ArcMut<BnanDevice>, queue_families: [u32; QueueType::COUNT], pipeline_statistics: bool) -> Result<Self> {
+        let (timestamp_period, timestamp_valid_bits, pipeline_statistics) = {
+            let device_guard = device.lock().unwrap();
+
+            (
+                device_guard.get_timestamp_period() as f64,
+                queue_families.map(|family| device_guard.get_timestamp_valid_bits(family)),
+                pipeline_statistics && device_guard.supports_pipeline_statistics(),
+            )
+        };
+
+        let timestamp_pools = Self::create_query_pools(
+            device.clone(),
+            vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(MAX_PROFILED_PASSES * 2),
+        )?;
+
+        let statistics_pools = match pipeline_statistics {
+            true => Some(Self::create_query_pools(
+                device.clone(),
+                vk::QueryPoolCreateInfo::default()
+                    .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                    .pipeline_statistics(PipelineStatistics::FLAGS)
+                    .query_count(MAX_PROFILED_PASSES),
+            )?),
+            false => None,
+        };
+
+        Ok(Self {
+            device,
+            timestamp_pools,
+            statistics_pools,
+            timestamp_period,
+            timestamp_valid_bits,
+            frame_passes: std::array::from_fn(|_| Vec::new()),
+            history: HashMap::new(),
+            statistics: HashMap::new(),
+            report_order: Vec::new(),
+        })
+    }
+
+    fn create_query_pools(device: ArcMut<BnanDevice>, info: vk::QueryPoolCreateInfo) -> Result<[vk::QueryPool; FRAMES_IN_FLIGHT]> {
+        let device_guard = device.lock().unwrap();
+
+        (0..FRAMES_IN_FLIGHT)
+            .map(|_| unsafe {
+                let pool = device_guard.device.create_query_pool(&info, None)?;
+                device_guard.device.reset_query_pool(pool, 0, info.query_count);
+                Ok(pool)
+            })
+            .collect::<Result<Vec<_>>>()?
+            .try_into()
+            .map_err(|_| anyhow!("failed to create query pools"))
+    }
+
+    /// Reads the results of the last frame recorded in this slot and resets its queries.
+    /// The frame's fence and timelines must have been waited on.
+    pub fn begin_frame(&mut self, frame: usize) -> Result<()> {
+        self.collect(frame)?;
+
+        let device = self.device.lock().unwrap();
+        unsafe {
+            device.device.reset_query_pool(self.timestamp_pools[frame], 0, MAX_PROFILED_PASSES * 2);
+
+            if let Some(statistics_pools) = &self.statistics_pools {
+                device.device.reset_query_pool(statistics_pools[frame], 0, MAX_PROFILED_PASSES);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the starting timestamp of a pass, returns the query slot to end it with
+    pub fn begin_pass(&mut self, command_buffer: vk::CommandBuffer, frame: usize, name: &str, queue: QueueType) -> Option<u32> {
+        let slot = self.frame_passes[frame].len() as u32;
+        if slot >= MAX_PROFILED_PASSES || self.timestamp_valid_bits[queue as usize] == 0 {
+            return None;
+        }
+
+        // statistics are graphics counters, the other queues only get timestamps
+        let statistics_slot = (self.statistics_pools.is_some() && queue == QueueType::Graphics)
+            .then(|| self.frame_passes[frame].iter().filter(|pass| pass.statistics_slot.is_some()).count() as u32);
+
+        let device = self.device.lock().unwrap();
+        unsafe {
+            device.device.cmd_write_timestamp2(command_buffer, vk::PipelineStageFlags2::TOP_OF_PIPE, self.timestamp_pools[frame], slot * 2);
+
+            if let (Some(statistics_slot), Some(statistics_pools)) = (statistics_slot, &self.statistics_pools) {
+                device.device.cmd_begin_query(command_buffer, statistics_pools[frame], statistics_slot, vk::QueryControlFlags::empty());
+            }
+        }
+
+        self.frame_passes[frame].push(ProfiledPass { name: name.to_string(), queue, statistics_slot });
+        Some(slot)
+    }
+
+    pub fn end_pass(&mut self, command_buffer: vk::CommandBuffer, frame: usize, slot: u32) {
+        let statistics_slot = self.frame_passes[frame][slot as usize].statistics_slot;
+
+        let device = self.device.lock().unwrap();
+        unsafe {
+            if let (Some(statistics_slot), Some(statistics_pools)) = (statistics_slot, &self.statistics_pools) {
+                device.device.cmd_end_query(command_buffer, statistics_pools[frame], statistics_slot);
+            }
+
+            device.device.cmd_write_timestamp2(command_buffer, vk::PipelineStageFlags2::BOTTOM_OF_PIPE, self.timestamp_pools[frame], slot * 2 + 1);
+        }
+    }
+
+    fn collect(&mut self, frame: usize) -> Result<()> {
+        let passes = std::mem::take(&mut self.frame_passes[frame]);
+        if passes.is_empty() {
+            return Ok(());
+        }
+
+        let device = self.device.lock().unwrap();
+
+        let mut timestamps = vec![0u64; passes.len() * 2];
+        unsafe {
+            device.device.get_query_pool_results(self.timestamp_pools[frame], 0, &mut timestamps, vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT)?;
+        }
+
+        // only the slots that were begun, waiting on the others would never return
+        let statistics_count = passes.iter().filter(|pass| pass.statistics_slot.is_some()).count();
+        let mut statistics = vec![[0u64; STATISTICS_COUNT]; statistics_count];
+        if let (true, Some(statistics_pools)) = (statistics_count > 0, &self.statistics_pools) {
+            unsafe {
+                device.device.get_query_pool_results(statistics_pools[frame], 0, &mut statistics, vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT)?;
+            }
+        }
+
+        self.report_order.clear();
+
+        for (index, pass) in passes.into_iter().enumerate() {
+            let valid_bits = self.timestamp_valid_bits[pass.queue as usize];
+            let mask = if valid_bits >= 64 { u64::MAX } else { (1u64 << valid_bits) - 1 };
+
+            let ticks = (timestamps[index * 2 + 1] & mask).wrapping_sub(timestamps[index * 2] & mask) & mask;
+            let milliseconds = ticks as f64 * self.timestamp_period / 1_000_000.0;
+
+            let history = self.history.entry(pass.name.clone()).or_default();
+            history.push_back(milliseconds);
+            if history.len() > PROFILER_HISTORY {
+                history.pop_front();
+            }
+
+            if let Some(statistics_slot) = pass.statistics_slot {
+                self.statistics.insert(pass.name.clone(), PipelineStatistics::from_results(statistics[statistics_slot as usize]));
+            }
+
+            self.report_order.push((pass.name, pass.queue));
+        }
+
+        Ok(())
+    }
+
+    /// Timings of the passes of the most recently collected frame, in recording order
+    pub fn get_pass_timings(&self) -> Vec<PassTiming> {
+        self.report_order.iter().filter_map(|(name, queue)| {
+            let history = self.history.get(name)?;
+
+            Some(PassTiming {
+                name: name.clone(),
+                queue: *queue,
+                last_ms: *history.back()?,
+                average_ms: history.iter().sum::<f64>() / history.len() as f64,
+                min_ms: history.iter().cloned().fold(f64::MAX, f64::min),
+                max_ms: history.iter().cloned().fold(0.0, f64::max),
+                statistics: self.statistics.get(name).cloned(),
+            })
+        }).collect()
+    }
+
+    /// Human readable table of `get_pass_timings`
+    pub fn format_report(&self) -> String {
+        let timings = self.get_pass_timings();
+        let mut report = String::new();
+
+        writeln!(report, "{:<32} {:<13} {:>9} {:>9} {:>9} {:>9}", "pass", "queue", "last ms", "avg ms", "min ms", "max ms").unwrap();
+
+        for timing in &timings {
+            writeln!(
+                report, "{:<32} {:<13} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+                timing.name, format!("{:?}", timing.queue), timing.last_ms, timing.average_ms, timing.min_ms, timing.max_ms
+            ).unwrap();
+
+            if let Some(statistics) = &timing.statistics {
+                writeln!(
+                    report, "    primitives {} clipped {} vertex invocations {} fragment invocations {} compute invocations {}",
+                    statistics.input_assembly_primitives, statistics.clipping_primitives, statistics.vertex_shader_invocations,
+                    statistics.fragment_shader_invocations, statistics.compute_shader_invocations
+                ).unwrap();
+            }
+        }
+
+        let total: f64 = timings.iter().map(|timing| timing.average_ms).sum();
+        writeln!(report, "{:<32} {:<13} {:>9} {:>9.3}", "total", "", "", total).unwrap();
+
+        report
+    }
+}
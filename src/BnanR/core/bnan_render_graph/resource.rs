@@ -1,7 +1,9 @@
+use std::collections::HashMap;
+
 use ash::vk;
 
 use crate::core::ArcMut;
-use crate::core::bnan_image::BnanImage;
+use crate::core::bnan_image::{BnanImage, ImageLayers};
 use crate::core::bnan_buffer::BnanBuffer;
 use crate::core::bnan_rendering::FRAMES_IN_FLIGHT;
 
@@ -17,6 +19,10 @@ pub enum ResourceUsage {
     TransferSrc,
     TransferDst,
     Present,
+    IndirectRead,
+    VertexRead,
+    IndexRead,
+    UniformRead,
 }
 
 impl ResourceUsage {
@@ -58,6 +64,23 @@ impl ResourceUsage {
                 vk::PipelineStageFlags2::NONE,
                 vk::AccessFlags2::NONE,
             ),
+            ResourceUsage::IndirectRead => (
+                vk::PipelineStageFlags2::DRAW_INDIRECT,
+                vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            ),
+            ResourceUsage::VertexRead => (
+                vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+                vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+            ),
+            ResourceUsage::IndexRead => (
+                vk::PipelineStageFlags2::INDEX_INPUT,
+                vk::AccessFlags2::INDEX_READ,
+            ),
+            ResourceUsage::UniformRead => (
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER |
+                vk::PipelineStageFlags2::TASK_SHADER_EXT | vk::PipelineStageFlags2::MESH_SHADER_EXT,
+                vk::AccessFlags2::UNIFORM_READ,
+            ),
         }
     }
     
@@ -71,13 +94,178 @@ impl ResourceUsage {
             ResourceUsage::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
             ResourceUsage::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
             ResourceUsage::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+
+            // buffer only usages have no layout
+            ResourceUsage::IndirectRead | ResourceUsage::VertexRead |
+            ResourceUsage::IndexRead | ResourceUsage::UniformRead => vk::ImageLayout::UNDEFINED,
         }
     }
+
+    pub fn is_write(self) -> bool {
+        Self::is_write_access(self.get_stage_and_access().1)
+    }
+
+    pub fn is_write_access(access: vk::AccessFlags2) -> bool {
+        access.intersects(
+            vk::AccessFlags2::SHADER_WRITE | vk::AccessFlags2::SHADER_STORAGE_WRITE | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE |
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE | vk::AccessFlags2::TRANSFER_WRITE | vk::AccessFlags2::HOST_WRITE |
+            vk::AccessFlags2::MEMORY_WRITE
+        )
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ResourceHandle(pub usize);
 
+/// Extent of a transient image, either scaled from the backbuffer or fixed
+#[derive(Clone, Copy, Debug)]
+pub enum ExtentDescription {
+    Backbuffer(f32),
+    Fixed(vk::Extent2D),
+}
+
+impl ExtentDescription {
+    pub fn resolve(&self, backbuffer: vk::Extent2D) -> vk::Extent2D {
+        match self {
+            ExtentDescription::Backbuffer(scale) => vk::Extent2D {
+                width: ((backbuffer.width as f32 * scale) as u32).max(1),
+                height: ((backbuffer.height as f32 * scale) as u32).max(1),
+            },
+            ExtentDescription::Fixed(extent) => *extent,
+        }
+    }
+}
+
+/// Description of an image owned by the graph, allocated at compile time and recreated on resize
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDescription {
+    pub format: vk::Format,
+    pub extent: ExtentDescription,
+    pub usage: vk::ImageUsageFlags,
+    pub samples: vk::SampleCountFlags,
+    pub mip_levels: u32,
+    pub layers: ImageLayers,
+}
+
+impl ImageDescription {
+    /// Clamped to the full chain for the resolved extent
+    pub const FULL_MIP_CHAIN: u32 = u32::MAX;
+
+    pub fn new(format: vk::Format, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            format,
+            extent: ExtentDescription::Backbuffer(1.0),
+            usage,
+            samples: vk::SampleCountFlags::TYPE_1,
+            mip_levels: 1,
+            layers: ImageLayers::Single,
+        }
+    }
+
+    pub fn extent(mut self, extent: ExtentDescription) -> Self {
+        self.extent = extent;
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    pub fn layers(mut self, layers: ImageLayers) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn resolve_extent(&self, backbuffer: vk::Extent2D) -> vk::Extent3D {
+        let extent = self.extent.resolve(backbuffer);
+        vk::Extent3D { width: extent.width, height: extent.height, depth: 1 }
+    }
+
+    pub fn resolve_mip_levels(&self, backbuffer: vk::Extent2D) -> u32 {
+        self.mip_levels.clamp(1, BnanImage::calculate_mip_levels(self.resolve_extent(backbuffer)))
+    }
+}
+
+pub struct TransientImage {
+    pub name: String,
+    pub description: ImageDescription,
+}
+
+/// Queue family ownership transfer released on one queue and not yet acquired on the other
+#[derive(Clone, Copy, Debug)]
+pub struct OwnershipTransfer {
+    pub src_family: u32,
+    pub dst_family: u32,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    pub dst_stage: vk::PipelineStageFlags2,
+    pub dst_access: vk::AccessFlags2,
+}
+
+/// Mip levels and array layers of an image a pass uses, counts past the end of the image are clamped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubresourceRange {
+    pub base_mip: u32,
+    pub mip_count: u32,
+    pub base_layer: u32,
+    pub layer_count: u32,
+}
+
+impl SubresourceRange {
+    pub const ALL: SubresourceRange = SubresourceRange { base_mip: 0, mip_count: u32::MAX, base_layer: 0, layer_count: u32::MAX };
+
+    pub fn mip(mip_level: u32) -> Self {
+        Self { base_mip: mip_level, mip_count: 1, ..Self::ALL }
+    }
+
+    pub fn layers(base_layer: u32, layer_count: u32) -> Self {
+        Self { base_layer, layer_count, ..Self::ALL }
+    }
+
+    pub fn layer(layer: u32) -> Self {
+        Self::layers(layer, 1)
+    }
+
+    /// Clamps the range to an image with the given mip and layer counts
+    pub fn resolve(&self, mip_levels: u32, layer_count: u32) -> SubresourceRange {
+        let base_mip = self.base_mip.min(mip_levels - 1);
+        let base_layer = self.base_layer.min(layer_count - 1);
+
+        SubresourceRange {
+            base_mip,
+            mip_count: self.mip_count.clamp(1, mip_levels - base_mip),
+            base_layer,
+            layer_count: self.layer_count.clamp(1, layer_count - base_layer),
+        }
+    }
+
+    pub fn to_vk(&self, aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: self.base_mip,
+            level_count: self.mip_count,
+            base_array_layer: self.base_layer,
+            layer_count: self.layer_count,
+        }
+    }
+}
+
+/// Layout and last access of a single mip level of a single layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubresourceState {
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
 #[derive(Clone)]
 pub enum ResourceType {
     SwapchainImage(ArcMut<BnanImage>),
@@ -92,7 +280,11 @@ pub struct PhysicalResource {
     
     pub current_layout: vk::ImageLayout,
     pub current_stage: vk::PipelineStageFlags2,
+    pub current_access: vk::AccessFlags2,
     pub current_queue_family: u32,
+
+    /// Keyed by (mip, layer), only holds subresources whose state differs from the `current_*` fields
+    pub subresource_states: HashMap<(u32, u32), SubresourceState>,
 }
 
 impl PhysicalResource {
@@ -103,7 +295,9 @@ impl PhysicalResource {
             resource: ResourceType::SwapchainImage(image),
             current_layout: vk::ImageLayout::UNDEFINED,
             current_stage: vk::PipelineStageFlags2::NONE,
+            current_access: vk::AccessFlags2::NONE,
             current_queue_family: vk::QUEUE_FAMILY_IGNORED,
+            subresource_states: HashMap::new(),
         }
     }
     
@@ -114,7 +308,9 @@ impl PhysicalResource {
             resource: ResourceType::Image(images),
             current_layout: vk::ImageLayout::UNDEFINED,
             current_stage: vk::PipelineStageFlags2::NONE,
+            current_access: vk::AccessFlags2::NONE,
             current_queue_family: vk::QUEUE_FAMILY_IGNORED,
+            subresource_states: HashMap::new(),
         }
     }
     
@@ -125,7 +321,91 @@ impl PhysicalResource {
             resource: ResourceType::Buffer(buffers),
             current_layout: vk::ImageLayout::UNDEFINED,
             current_stage: vk::PipelineStageFlags2::NONE,
+            current_access: vk::AccessFlags2::NONE,
             current_queue_family: vk::QUEUE_FAMILY_IGNORED,
+            subresource_states: HashMap::new(),
+        }
+    }
+
+    /// Whether every subresource is in the state of the `current_*` fields
+    pub fn is_uniform(&self) -> bool {
+        self.subresource_states.is_empty()
+    }
+
+    pub fn get_subresource_state(&self, mip: u32, layer: u32) -> SubresourceState {
+        self.subresource_states.get(&(mip, layer)).cloned().unwrap_or(SubresourceState {
+            layout: self.current_layout,
+            stage: self.current_stage,
+            access: self.current_access,
+        })
+    }
+
+    /// Sets the state of the whole resource, dropping any per-subresource state
+    pub fn set_uniform_state(&mut self, layout: vk::ImageLayout, stage: vk::PipelineStageFlags2, access: vk::AccessFlags2) {
+        self.current_layout = layout;
+        self.current_stage = stage;
+        self.current_access = access;
+        self.subresource_states.clear();
+    }
+
+    /// `range` must already be resolved against the image's mip and layer counts
+    pub fn set_subresource_state(&mut self, range: SubresourceRange, mip_levels: u32, layer_count: u32, state: SubresourceState) {
+        if range == SubresourceRange::ALL.resolve(mip_levels, layer_count) {
+            self.set_uniform_state(state.layout, state.stage, state.access);
+            return;
+        }
+
+        for layer in range.base_layer..range.base_layer + range.layer_count {
+            for mip in range.base_mip..range.base_mip + range.mip_count {
+                self.subresource_states.insert((mip, layer), state);
+            }
+        }
+
+        // collapse back once every subresource agrees again
+        let total = (mip_levels * layer_count) as usize;
+        let first = self.subresource_states.values().next().cloned();
+
+        if let Some(first) = first
+            && self.subresource_states.len() == total && self.subresource_states.values().all(|other| *other == first) {
+            self.set_uniform_state(first.layout, first.stage, first.access);
         }
     }
+
+    /// Splits `range` into the largest pieces that share one state, so each needs a single barrier.
+    /// Mips are merged within a layer, then layers with identical mip runs are merged.
+    pub fn get_subresource_transitions(&self, range: SubresourceRange) -> Vec<(SubresourceRange, SubresourceState)> {
+        if self.is_uniform() {
+            return vec![(range, self.get_subresource_state(range.base_mip, range.base_layer))];
+        }
+
+        let mut transitions: Vec<(SubresourceRange, SubresourceState)> = Vec::new();
+        let mut previous_runs: Vec<(u32, u32, SubresourceState)> = Vec::new();
+
+        for layer in range.base_layer..range.base_layer + range.layer_count {
+            let mut runs: Vec<(u32, u32, SubresourceState)> = Vec::new();
+
+            for mip in range.base_mip..range.base_mip + range.mip_count {
+                let state = self.get_subresource_state(mip, layer);
+
+                match runs.last_mut() {
+                    Some((_, count, run_state)) if *run_state == state => *count += 1,
+                    _ => runs.push((mip, 1, state)),
+                }
+            }
+
+            if layer > range.base_layer && runs == previous_runs {
+                for (transition, _) in transitions.iter_mut().rev().take(runs.len()) {
+                    transition.layer_count += 1;
+                }
+                continue;
+            }
+
+            transitions.extend(runs.iter().map(|&(base_mip, mip_count, state)| {
+                (SubresourceRange { base_mip, mip_count, base_layer: layer, layer_count: 1 }, state)
+            }));
+            previous_runs = runs;
+        }
+
+        transitions
+    }
 }
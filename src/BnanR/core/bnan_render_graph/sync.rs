@@ -5,6 +5,7 @@ use crate::core::ArcMut;
 use crate::core::bnan_device::BnanDevice;
 use crate::core::bnan_rendering::FRAMES_IN_FLIGHT;
 use crate::core::bnan_swapchain::BnanSwapchain;
+use crate::core::bnan_render_graph::pass::QueueType;
 
 pub struct RenderGraphSync {
     pub device: ArcMut<BnanDevice>,
@@ -19,6 +20,12 @@ pub struct RenderGraphSync {
     pub transfer_finished_semaphores: [vk::Semaphore; FRAMES_IN_FLIGHT],
     pub transfer_fences: [vk::Fence; FRAMES_IN_FLIGHT],
     pub pending_transfer_signal: [bool; FRAMES_IN_FLIGHT],
+
+    // Multi queue sync, indexed by QueueType
+    pub queue_command_buffers: [[Vec<vk::CommandBuffer>; QueueType::COUNT]; FRAMES_IN_FLIGHT],
+    pub timeline_semaphores: [vk::Semaphore; QueueType::COUNT],
+    pub timeline_values: [u64; QueueType::COUNT],
+    pub frame_timeline_values: [[u64; QueueType::COUNT]; FRAMES_IN_FLIGHT],
 }
 
 impl Drop for RenderGraphSync {
@@ -38,6 +45,10 @@ impl Drop for RenderGraphSync {
              for sem in &self.render_finished_semaphores {
                  device.device.destroy_semaphore(*sem, None);
              }
+
+             for sem in &self.timeline_semaphores {
+                 device.device.destroy_semaphore(*sem, None);
+             }
         }
     }
 }
@@ -53,6 +64,8 @@ impl RenderGraphSync {
             transfer_finished_semaphores,
             transfer_fences
         ) = Self::create_sync_objects(device.clone(), swapchain_image_count)?;
+
+        let timeline_semaphores = Self::create_timeline_semaphores(device.clone())?;
         
         Ok(Self {
             device,
@@ -64,8 +77,28 @@ impl RenderGraphSync {
             transfer_finished_semaphores,
             transfer_fences,
             pending_transfer_signal: [false; FRAMES_IN_FLIGHT],
+            queue_command_buffers: std::array::from_fn(|_| std::array::from_fn(|_| Vec::new())),
+            timeline_semaphores,
+            timeline_values: [0; QueueType::COUNT],
+            frame_timeline_values: [[0; QueueType::COUNT]; FRAMES_IN_FLIGHT],
         })
     }
+
+    fn create_timeline_semaphores(device: ArcMut<BnanDevice>) -> Result<[vk::Semaphore; QueueType::COUNT]> {
+        let device_guard = device.lock().unwrap();
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+
+        let semaphore_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+
+        (0..QueueType::COUNT)
+            .map(|_| unsafe { Ok(device_guard.device.create_semaphore(&semaphore_info, None)?) })
+            .collect::<Result<Vec<_>>>()?
+            .try_into()
+            .map_err(|_| anyhow!("failed to create timeline semaphores"))
+    }
     
     fn create_sync_objects(device: ArcMut<BnanDevice>, swapchain_image_count: u32) -> Result<(
         [vk::CommandBuffer; FRAMES_IN_FLIGHT],
@@ -159,7 +192,71 @@ impl RenderGraphSync {
         Ok(())
     }
     
+    pub fn wait_in_flight(&self, frame: usize) -> Result<()> {
+        let device = self.device.lock().unwrap();
+        unsafe { device.device.wait_for_fences(&[self.in_flight_fences[frame]], true, u64::MAX)?; }
+        Ok(())
+    }
+    
     pub fn get_command_buffer(&self, current_frame: usize) -> vk::CommandBuffer {
         self.command_buffers[current_frame]
     }
+
+    /// Command buffer for the nth batch submitted to `queue` this frame, allocated on first use.
+    /// The first graphics batch records into the frame's main command buffer.
+    pub fn get_queue_command_buffer(&mut self, current_frame: usize, queue: QueueType, index: usize) -> Result<vk::CommandBuffer> {
+        if queue == QueueType::Graphics && index == 0 {
+            return Ok(self.command_buffers[current_frame]);
+        }
+
+        let command_buffers = &mut self.queue_command_buffers[current_frame][queue as usize];
+
+        while command_buffers.len() <= index {
+            let device = self.device.lock().unwrap();
+
+            let pool = match queue {
+                QueueType::Graphics => device.command_pools[BnanDevice::GRAPHICS_COMMAND_POOL],
+                QueueType::AsyncCompute => device.command_pools[BnanDevice::COMPUTE_COMMAND_POOL],
+                QueueType::Transfer => device.command_pools[BnanDevice::TRANSFER_COMMAND_POOL],
+            };
+
+            let alloc_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+
+            command_buffers.extend(unsafe { device.device.allocate_command_buffers(&alloc_info)? });
+        }
+
+        Ok(command_buffers[index])
+    }
+
+    /// Reserves the next value of the queue's timeline semaphore and records it as the frame's latest signal
+    pub fn next_timeline_value(&mut self, current_frame: usize, queue: QueueType) -> u64 {
+        self.timeline_values[queue as usize] += 1;
+        self.frame_timeline_values[current_frame][queue as usize] = self.timeline_values[queue as usize];
+
+        self.timeline_values[queue as usize]
+    }
+
+    /// Waits until every queue has finished the work submitted for this frame slot
+    pub fn wait_timelines(&self, frame: usize) -> Result<()> {
+        let (semaphores, values): (Vec<_>, Vec<_>) = self.timeline_semaphores.iter().cloned()
+            .zip(self.frame_timeline_values[frame].iter().cloned())
+            .filter(|(_, value)| *value > 0)
+            .unzip();
+
+        if semaphores.is_empty() {
+            return Ok(());
+        }
+
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+
+        let device = self.device.lock().unwrap();
+        unsafe { device.device.wait_semaphores(&wait_info, u64::MAX)?; }
+
+        Ok(())
+    }
 }
@@ -16,6 +16,7 @@ pub mod bnan_buffer;
 pub mod bnan_camera;
 pub mod bnan_render_graph;
 pub mod bnan_mesh;
+pub mod bnan_readback;
 
 pub type RcMut<T> = Rc<RefCell<T>>;
 pub type ArcMut<T> = Arc<Mutex<T>>;
@@ -0,0 +1,361 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use crate::ecs::change_detection::ComponentTicks;
+use crate::ecs::component::Component;
+use crate::ecs::entity::Entity;
+use crate::ecs::storage::DataCell;
+
+pub type ArchetypeId = usize;
+
+/// Archetype every entity starts in, it has no table components
+pub const EMPTY_ARCHETYPE: ArchetypeId = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityLocation {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+pub trait AnyColumn: Any + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// A column of the same component type without any rows
+    fn new_empty(&self) -> Box<DataCell<dyn AnyColumn>>;
+
+    /// Drops the component at `row`, the last row takes its place
+    fn swap_remove(&mut self, row: usize);
+
+    /// Moves the component at `row` to the end of `dst`, which must hold the same component type
+    fn move_row(&mut self, row: usize, dst: &mut dyn AnyColumn);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Components of one type for every entity of an archetype, row `i` belongs to `Archetype::entities()[i]`
+pub struct Column<T: Component> {
+    data: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+}
+
+impl<T: Component> Column<T> {
+    pub fn new() -> Self {
+        Self { data: Vec::new(), ticks: Vec::new() }
+    }
+
+    pub fn push(&mut self, component: T, ticks: ComponentTicks) {
+        self.data.push(component);
+        self.ticks.push(ticks);
+    }
+
+    pub fn swap_remove_value(&mut self, row: usize) -> T {
+        self.ticks.swap_remove(row);
+        self.data.swap_remove(row)
+    }
+
+    pub fn get(&self, row: usize) -> Option<&T> {
+        self.data.get(row)
+    }
+
+    pub fn get_mut(&mut self, row: usize) -> Option<&mut T> {
+        self.data.get_mut(row)
+    }
+
+    pub fn get_ticks(&self, row: usize) -> Option<&ComponentTicks> {
+        self.ticks.get(row)
+    }
+
+    pub fn get_ticks_mut(&mut self, row: usize) -> Option<&mut ComponentTicks> {
+        self.ticks.get_mut(row)
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.data.as_ptr()
+    }
+
+    pub fn ticks_as_ptr(&self) -> *const ComponentTicks {
+        self.ticks.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.data.as_mut_ptr()
+    }
+
+    pub fn ticks_as_mut_ptr(&mut self) -> *mut ComponentTicks {
+        self.ticks.as_mut_ptr()
+    }
+}
+
+impl<T: Component> Default for Column<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Component> AnyColumn for Column<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn new_empty(&self) -> Box<DataCell<dyn AnyColumn>> {
+        Box::new(DataCell::new(Column::<T>::new()))
+    }
+
+    fn swap_remove(&mut self, row: usize) {
+        self.swap_remove_value(row);
+    }
+
+    fn move_row(&mut self, row: usize, dst: &mut dyn AnyColumn) {
+        let dst = dst.as_any_mut().downcast_mut::<Column<T>>().expect("moving a row between columns of different types");
+        dst.data.push(self.data.swap_remove(row));
+        dst.ticks.push(self.ticks.swap_remove(row));
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Every entity with exactly the same set of table components, stored as one column per component
+pub struct Archetype {
+    id: ArchetypeId,
+    // sorted
+    components: Vec<TypeId>,
+    // keyed by component type, systems of a batch reach their columns through the cells
+    columns: HashMap<TypeId, Box<DataCell<dyn AnyColumn>>>,
+    entities: Vec<Entity>,
+    // archetypes reached by inserting or removing one table component
+    add_edges: HashMap<TypeId, ArchetypeId>,
+    remove_edges: HashMap<TypeId, ArchetypeId>,
+}
+
+impl Archetype {
+    pub fn get_id(&self) -> ArchetypeId {
+        self.id
+    }
+
+    pub fn get_components(&self) -> &[TypeId] {
+        &self.components
+    }
+
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.columns.contains_key(&type_id)
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Nothing may write the column while the reference is alive, which a shared `World` guarantees
+    pub fn get_column<T: Component>(&self) -> Option<&Column<T>> {
+        self.columns.get(&TypeId::of::<T>())
+            .and_then(|column| unsafe { column.get_ref() }.as_any().downcast_ref::<Column<T>>())
+    }
+
+    pub fn get_column_mut<T: Component>(&mut self) -> Option<&mut Column<T>> {
+        self.columns.get_mut(&TypeId::of::<T>())
+            .and_then(|column| column.get_mut().as_any_mut().downcast_mut::<Column<T>>())
+    }
+
+    /// Queries reach columns through this, so a shared archetype is all a system needs and other columns are never borrowed
+    pub(crate) fn get_column_ptr<T: Component>(&self) -> Option<*mut Column<T>> {
+        self.columns.get(&TypeId::of::<T>())
+            .map(|column| column.get() as *mut Column<T>)
+    }
+
+    /// Removes the row from every column, returns the entity that moved into `row` if any
+    pub fn swap_remove(&mut self, row: usize) -> Option<Entity> {
+        for column in self.columns.values_mut() {
+            column.get_mut().swap_remove(row);
+        }
+
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+
+    pub(crate) fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+}
+
+/// Every archetype of a `World`, created on demand as table components are inserted and removed
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    by_components: HashMap<Vec<TypeId>, ArchetypeId>,
+}
+
+impl Archetypes {
+    pub fn new() -> Self {
+        let empty = Archetype {
+            id: EMPTY_ARCHETYPE,
+            components: Vec::new(),
+            columns: HashMap::new(),
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        };
+
+        Self {
+            archetypes: vec![empty],
+            by_components: HashMap::from([(Vec::new(), EMPTY_ARCHETYPE)]),
+        }
+    }
+
+    pub fn get(&self, id: ArchetypeId) -> &Archetype {
+        &self.archetypes[id]
+    }
+
+    pub fn get_mut(&mut self, id: ArchetypeId) -> &mut Archetype {
+        &mut self.archetypes[id]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archetypes.is_empty()
+    }
+
+    /// Both archetypes mutably, they must differ
+    pub fn get_pair_mut(&mut self, a: ArchetypeId, b: ArchetypeId) -> (&mut Archetype, &mut Archetype) {
+        assert_ne!(a, b);
+
+        if a < b {
+            let (low, high) = self.archetypes.split_at_mut(b);
+            (&mut low[a], &mut high[0])
+        } else {
+            let (low, high) = self.archetypes.split_at_mut(a);
+            (&mut high[0], &mut low[b])
+        }
+    }
+
+    /// Archetype with the components of `source` plus `T`
+    pub fn get_with<T: Component>(&mut self, source: ArchetypeId) -> ArchetypeId {
+        let type_id = TypeId::of::<T>();
+        if let Some(&target) = self.archetypes[source].add_edges.get(&type_id) {
+            return target;
+        }
+
+        let mut components = self.archetypes[source].components.clone();
+        components.push(type_id);
+        components.sort();
+
+        let target = match self.by_components.get(&components) {
+            Some(&target) => target,
+            None => {
+                let mut columns = self.copy_columns(source, |_| true);
+                columns.insert(type_id, Box::new(DataCell::new(Column::<T>::new())));
+                self.create(components, columns)
+            }
+        };
+
+        self.archetypes[source].add_edges.insert(type_id, target);
+        self.archetypes[target].remove_edges.insert(type_id, source);
+        target
+    }
+
+    /// Archetype with the components of `source` except `T`
+    pub fn get_without<T: Component>(&mut self, source: ArchetypeId) -> ArchetypeId {
+        let type_id = TypeId::of::<T>();
+        if let Some(&target) = self.archetypes[source].remove_edges.get(&type_id) {
+            return target;
+        }
+
+        let components: Vec<TypeId> = self.archetypes[source].components.iter()
+            .filter(|&&other| other != type_id)
+            .cloned()
+            .collect();
+
+        let target = match self.by_components.get(&components) {
+            Some(&target) => target,
+            None => {
+                let columns = self.copy_columns(source, |other| other != type_id);
+                self.create(components, columns)
+            }
+        };
+
+        self.archetypes[source].remove_edges.insert(type_id, target);
+        self.archetypes[target].add_edges.insert(type_id, source);
+        target
+    }
+
+    /// Empty columns for the components of `source` accepted by `keep`
+    fn copy_columns(&mut self, source: ArchetypeId, keep: impl Fn(TypeId) -> bool) -> HashMap<TypeId, Box<DataCell<dyn AnyColumn>>> {
+        self.archetypes[source].columns.iter_mut()
+            .filter(|(type_id, _)| keep(**type_id))
+            .map(|(type_id, column)| (*type_id, column.get_mut().new_empty()))
+            .collect()
+    }
+
+    fn create(&mut self, components: Vec<TypeId>, columns: HashMap<TypeId, Box<DataCell<dyn AnyColumn>>>) -> ArchetypeId {
+        let id = self.archetypes.len();
+
+        self.by_components.insert(components.clone(), id);
+        self.archetypes.push(Archetype {
+            id,
+            components,
+            columns,
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        });
+
+        id
+    }
+
+    /// Moves the entity at `row` of `source` into `target`, dropping components `target` lacks.
+    /// `taken` is a column whose row was already removed by the caller.
+    /// Returns its new row and the entity that took its place in `source`.
+    pub fn move_entity(&mut self, source: ArchetypeId, row: usize, target: ArchetypeId, taken: Option<TypeId>) -> (usize, Option<Entity>) {
+        let (source, target) = self.get_pair_mut(source, target);
+
+        for (type_id, column) in source.columns.iter_mut() {
+            if taken == Some(*type_id) {
+                continue;
+            }
+
+            match target.columns.get_mut(type_id) {
+                Some(target_column) => column.get_mut().move_row(row, target_column.get_mut()),
+                None => column.get_mut().swap_remove(row),
+            }
+        }
+
+        let entity = source.entities.swap_remove(row);
+        let new_row = target.push_entity(entity);
+
+        (new_row, source.entities.get(row).copied())
+    }
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
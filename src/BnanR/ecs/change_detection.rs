@@ -0,0 +1,136 @@
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::ecs::archetype::Archetype;
+use crate::ecs::component::Component;
+use crate::ecs::entity::Entity;
+use crate::ecs::query::{ComponentAccess, ComponentFetch, QueryFilter};
+use crate::ecs::world::UnsafeWorldCell;
+
+/// World change ticks at which a component was added and last mutably accessed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u64) -> Self {
+        Self { added: tick, changed: tick }
+    }
+
+    pub fn is_added(&self, last_run: u64) -> bool {
+        self.added > last_run
+    }
+
+    pub fn is_changed(&self, last_run: u64) -> bool {
+        self.changed > last_run
+    }
+}
+
+/// Ticks a query compares against: changes after `last_run` are new, writes are stamped with `this_run`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QueryTicks {
+    pub last_run: u64,
+    pub this_run: u64,
+}
+
+/// Mutable component from a query, marks the component changed only when it is written through
+pub struct Mut<'w, T> {
+    value: &'w mut T,
+    ticks: &'w mut ComponentTicks,
+    this_run: u64,
+}
+
+impl<'w, T> Mut<'w, T> {
+    pub(crate) fn new(value: &'w mut T, ticks: &'w mut ComponentTicks, this_run: u64) -> Self {
+        Self { value, ticks, this_run }
+    }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        *self.ticks
+    }
+
+    pub fn set_changed(&mut self) {
+        self.ticks.changed = self.this_run;
+    }
+
+    /// Mutable access that does not count as a change
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+
+    pub fn into_inner(self) -> &'w mut T {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+}
+
+/// Matches entities whose `T` was added since the system last ran
+pub struct Added<T: Component>(PhantomData<T>);
+
+/// Matches entities whose `T` was added or mutably accessed since the system last ran
+pub struct Changed<T: Component>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for Added<T> {
+    type Fetch = (ComponentFetch<T>, u64);
+
+    fn access(access: &mut ComponentAccess) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { ComponentFetch::new(world, false).map(|fetch| (fetch, ticks.last_run)) }
+    }
+
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+        unsafe { fetch.0.driver(driver) }
+    }
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        unsafe { fetch.0.set_archetype(archetype, false) }
+    }
+
+    unsafe fn matches(fetch: &Self::Fetch, entity: Entity, row: usize) -> bool {
+        unsafe { fetch.0.get_ticks(entity, row).is_some_and(|ticks| ticks.is_added(fetch.1)) }
+    }
+}
+
+impl<T: Component> QueryFilter for Changed<T> {
+    type Fetch = (ComponentFetch<T>, u64);
+
+    fn access(access: &mut ComponentAccess) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { ComponentFetch::new(world, false).map(|fetch| (fetch, ticks.last_run)) }
+    }
+
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+        unsafe { fetch.0.driver(driver) }
+    }
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        unsafe { fetch.0.set_archetype(archetype, false) }
+    }
+
+    unsafe fn matches(fetch: &Self::Fetch, entity: Entity, row: usize) -> bool {
+        unsafe { fetch.0.get_ticks(entity, row).is_some_and(|ticks| ticks.is_changed(fetch.1)) }
+    }
+}
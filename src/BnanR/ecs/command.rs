@@ -0,0 +1,95 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::ecs::component::Component;
+use crate::ecs::entity::{Entity, EntityAllocator};
+use crate::ecs::resource::Resource;
+use crate::ecs::world::World;
+
+pub type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// World changes recorded while the world is borrowed, e.g. despawning during a query, applied later in order
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<Command>,
+}
+
+impl CommandQueue {
+    pub fn new() -> Self {
+        Self { commands: Vec::new() }
+    }
+
+    pub fn push(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.push(move |world| { world.despawn(entity); });
+    }
+
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) {
+        self.push(move |world| { world.insert(entity, component); });
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) {
+        self.push(move |world| { world.remove::<T>(entity); });
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) {
+        self.push(move |world| { world.insert_resource(resource); });
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) {
+        self.push(|world| { world.remove_resource::<R>(); });
+    }
+
+    pub fn send_event<E: Resource>(&mut self, event: E) {
+        self.push(move |world| world.send_event(event));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Makes reserved entities alive, then runs the commands in the order they were recorded
+    pub fn apply(&mut self, world: &mut World) {
+        world.flush_entities();
+
+        for command in self.commands.drain(..) {
+            command(world);
+        }
+    }
+}
+
+/// A `CommandQueue` that can also spawn, the entity is reserved right away and comes alive when the queue is applied
+pub struct Commands<'w> {
+    queue: &'w mut CommandQueue,
+    entities: &'w EntityAllocator,
+}
+
+impl<'w> Commands<'w> {
+    pub fn new(queue: &'w mut CommandQueue, entities: &'w EntityAllocator) -> Self {
+        Self { queue, entities }
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.reserve()
+    }
+}
+
+impl Deref for Commands<'_> {
+    type Target = CommandQueue;
+
+    fn deref(&self) -> &CommandQueue {
+        self.queue
+    }
+}
+
+impl DerefMut for Commands<'_> {
+    fn deref_mut(&mut self) -> &mut CommandQueue {
+        self.queue
+    }
+}
@@ -1,4 +1,15 @@
-pub trait Component: 'static + Sized {}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageType {
+    /// One sparse set per component type, cheap to insert and remove
+    SparseSet,
+    /// Columns of the entity's archetype, fast to iterate together with other table components
+    Table,
+}
+
+/// Components are Send + Sync so systems touching different components can run on different threads
+pub trait Component: 'static + Sized + Send + Sync {
+    const STORAGE: StorageType = StorageType::SparseSet;
+}
 
 #[macro_export]
 macro_rules! impl_component {
@@ -15,3 +26,15 @@ macro_rules! derive_component {
         )+
     };
 }
+
+/// Components stored in archetype tables, for data iterated every frame on many entities
+#[macro_export]
+macro_rules! derive_table_component {
+    ($($t:ty),+ $(,)?) => {
+        $(
+            impl $crate::ecs::Component for $t {
+                const STORAGE: $crate::ecs::component::StorageType = $crate::ecs::component::StorageType::Table;
+            }
+        )+
+    };
+}
@@ -1,10 +1,17 @@
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::*;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct Entity {
     pub id: u32,
     pub generation: u32,
 }
 
 impl Entity {
+    /// Never alive, stands in for references that could not be resolved
+    pub const PLACEHOLDER: Entity = Entity { id: u32::MAX, generation: u32::MAX };
+
     pub fn new(id: u32, generation: u32) -> Self {
         Self { id, generation }
     }
@@ -14,37 +21,68 @@ impl Entity {
     }
 }
 
+/// Allocation only depends on the allocator's own state: freed ids are reused last in first out,
+/// so replaying the same spawns and despawns from a clone hands out the same entities
 pub struct EntityAllocator {
     generations: Vec<u32>,
+    alive: Vec<bool>,
     free_ids: Vec<u32>,
     next_id: u32,
+    // ids past `next_id` handed out by `reserve`, they become live on `flush`
+    reserved: AtomicU32,
 }
 
 impl EntityAllocator {
     pub fn new() -> Self {
         Self {
             generations: Vec::new(),
+            alive: Vec::new(),
             free_ids: Vec::new(),
             next_id: 0,
+            reserved: AtomicU32::new(0),
         }
     }
 
     pub fn allocate(&mut self) -> Entity {
+        self.flush();
+
         if let Some(id) = self.free_ids.pop() {
             let generation = self.generations[id as usize];
+            self.alive[id as usize] = true;
             Entity::new(id, generation)
         } else {
             let id = self.next_id;
             self.next_id += 1;
             self.generations.push(0);
+            self.alive.push(true);
             Entity::new(id, 0)
         }
     }
 
+    /// Hands out a fresh entity from a shared reference, e.g. while systems run in parallel.
+    /// It is not alive until `flush`.
+    pub fn reserve(&self) -> Entity {
+        let id = self.next_id + self.reserved.fetch_add(1, Ordering::Relaxed);
+        Entity::new(id, 0)
+    }
+
+    /// Makes every reserved entity alive
+    pub fn flush(&mut self) {
+        let reserved = std::mem::take(self.reserved.get_mut());
+
+        for _ in 0..reserved {
+            self.generations.push(0);
+            self.alive.push(true);
+        }
+
+        self.next_id += reserved;
+    }
+
     pub fn deallocate(&mut self, entity: Entity) -> bool {
         let idx = entity.id as usize;
         if idx < self.generations.len() && self.generations[idx] == entity.generation {
             self.generations[idx] += 1;
+            self.alive[idx] = false;
             self.free_ids.push(entity.id);
             true
         } else {
@@ -54,7 +92,40 @@ impl EntityAllocator {
 
     pub fn is_alive(&self, entity: Entity) -> bool {
         let idx = entity.id as usize;
-        idx < self.generations.len() && self.generations[idx] == entity.generation
+        idx < self.generations.len() && self.alive[idx] && self.generations[idx] == entity.generation
+    }
+
+    /// The live entity currently using this index, if any
+    pub fn get(&self, index: usize) -> Option<Entity> {
+        match self.alive.get(index) {
+            Some(true) => Some(Entity::new(index as u32, self.generations[index])),
+            _ => None,
+        }
+    }
+
+    /// Number of indices ever handed out, live or not
+    pub fn len(&self) -> usize {
+        self.generations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.generations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+}
+
+impl Clone for EntityAllocator {
+    fn clone(&self) -> Self {
+        Self {
+            generations: self.generations.clone(),
+            alive: self.alive.clone(),
+            free_ids: self.free_ids.clone(),
+            next_id: self.next_id,
+            reserved: AtomicU32::new(self.reserved.load(Ordering::Relaxed)),
+        }
     }
 }
 
@@ -0,0 +1,95 @@
+use std::marker::PhantomData;
+
+use crate::ecs::resource::Resource;
+
+/// Double-buffered event channel stored as a resource. Events stay readable for the update they were
+/// sent in and the one after, so every system sees them once no matter where it runs in the frame.
+pub struct Events<E: Resource> {
+    previous: Vec<E>,
+    current: Vec<E>,
+    // total number of events ever sent, also the id of the next one
+    event_count: usize,
+}
+
+impl<E: Resource> Events<E> {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            event_count: 0,
+        }
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+        self.event_count += 1;
+    }
+
+    /// Drops the events of the previous update, called once per frame by `World::update_events`
+    pub fn update(&mut self) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+
+    /// Events with an id of at least `first`, oldest first
+    pub fn iter_since(&self, first: usize) -> impl Iterator<Item = &E> {
+        let current_start = self.event_count - self.current.len();
+        let previous_start = current_start - self.previous.len();
+
+        self.previous.iter().skip(first.saturating_sub(previous_start))
+            .chain(self.current.iter().skip(first.saturating_sub(current_start)))
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.event_count
+    }
+
+    /// Number of events still buffered
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.current.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+}
+
+impl<E: Resource> Default for Events<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-reader cursor into `Events<E>`, each reader sees every event once. Systems keep theirs in the closure.
+pub struct EventReader<E: Resource> {
+    last_event_count: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E: Resource> EventReader<E> {
+    pub fn new() -> Self {
+        Self { last_event_count: 0, _marker: PhantomData }
+    }
+
+    /// Events sent since the last `read`
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> + use<'a, E> {
+        let first = std::mem::replace(&mut self.last_event_count, events.event_count());
+        events.iter_since(first)
+    }
+
+    /// Skips everything sent so far
+    pub fn clear(&mut self, events: &Events<E>) {
+        self.last_event_count = events.event_count();
+    }
+}
+
+impl<E: Resource> Default for EventReader<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
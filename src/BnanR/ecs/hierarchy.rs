@@ -0,0 +1,117 @@
+use serde::*;
+
+use crate::derive_component;
+use crate::ecs::entity::Entity;
+use crate::ecs::world::World;
+
+/// The entity this one is attached to, maintained by `World::set_parent`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(pub Entity);
+
+/// Entities attached to this one in the order they were attached, maintained by `World::set_parent`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Children(pub Vec<Entity>);
+
+derive_component!(Parent, Children);
+
+impl World {
+    /// Attaches `child` to `parent`, detaching it from its previous parent first.
+    /// Fails if either is dead or `child` is `parent` or one of its ancestors.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) || self.is_ancestor(child, parent) {
+            return false;
+        }
+
+        if self.get_parent(child) == Some(parent) {
+            return true;
+        }
+
+        self.remove_parent(child);
+        self.insert(child, Parent(parent));
+
+        match self.get_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => { self.insert(parent, Children(vec![child])); }
+        }
+
+        true
+    }
+
+    /// Detaches `child` from its parent, it becomes a root. Returns the old parent.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        let Parent(parent) = self.remove::<Parent>(child)?;
+
+        if let Some(children) = self.get_mut::<Children>(parent) {
+            children.0.retain(|&other| other != child);
+
+            if children.0.is_empty() {
+                self.remove::<Children>(parent);
+            }
+        }
+
+        Some(parent)
+    }
+
+    pub fn get_parent(&self, entity: Entity) -> Option<Entity> {
+        self.get::<Parent>(entity).map(|parent| parent.0)
+    }
+
+    pub fn get_children(&self, entity: Entity) -> &[Entity] {
+        self.get::<Children>(entity).map_or(&[], |children| children.0.as_slice())
+    }
+
+    /// Whether `ancestor` is `entity` or one of the entities above it
+    pub fn is_ancestor(&self, ancestor: Entity, entity: Entity) -> bool {
+        let mut current = Some(entity);
+
+        while let Some(entity) = current {
+            if entity == ancestor {
+                return true;
+            }
+
+            current = self.get_parent(entity);
+        }
+
+        false
+    }
+
+    /// Every entity below `entity`, parents before their children
+    pub fn get_descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut descendants = Vec::new();
+        let mut stack: Vec<Entity> = self.get_children(entity).iter().rev().cloned().collect();
+
+        while let Some(entity) = stack.pop() {
+            descendants.push(entity);
+            stack.extend(self.get_children(entity).iter().rev());
+        }
+
+        descendants
+    }
+
+    /// Despawns `entity` and everything below it
+    pub fn despawn_recursive(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let descendants = self.get_descendants(entity);
+
+        // children first so no parent is left pointing at a dead child
+        for &descendant in descendants.iter().rev() {
+            self.despawn(descendant);
+        }
+
+        self.despawn(entity)
+    }
+
+    /// Unlinks an entity that is about to be despawned, its children become roots
+    pub(crate) fn detach_hierarchy(&mut self, entity: Entity) {
+        self.remove_parent(entity);
+
+        if let Some(Children(children)) = self.remove::<Children>(entity) {
+            for child in children {
+                self.remove::<Parent>(child);
+            }
+        }
+    }
+}
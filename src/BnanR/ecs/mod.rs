@@ -1,4 +1,33 @@
-mod component;
-mod entity;
-mod storage;
-mod world;
+pub mod archetype;
+pub mod change_detection;
+pub mod command;
+pub mod component;
+pub mod entity;
+pub mod event;
+pub mod hierarchy;
+pub mod observer;
+pub mod query;
+pub mod resource;
+pub mod scene;
+pub mod schedule;
+pub mod snapshot;
+pub mod storage;
+pub mod system;
+pub mod transform;
+pub mod world;
+
+pub use change_detection::{Added, Changed, Mut};
+pub use command::{CommandQueue, Commands};
+pub use component::{Component, StorageType};
+pub use entity::Entity;
+pub use event::{EventReader, Events};
+pub use hierarchy::{Children, Parent};
+pub use observer::{ComponentHooks, Lifecycle, ObserverId};
+pub use query::{With, Without};
+pub use resource::Resource;
+pub use scene::{EntityMap, MapEntities, SceneRegistry};
+pub use schedule::{Schedule, Stage};
+pub use snapshot::{SnapshotRegistry, WorldSnapshot};
+pub use system::{System, SystemWorld};
+pub use transform::{GlobalTransform, LocalTransform};
+pub use world::World;
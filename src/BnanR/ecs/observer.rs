@@ -0,0 +1,230 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::ecs::entity::Entity;
+use crate::ecs::world::World;
+
+pub type ObserverFn = Arc<dyn Fn(&mut World, Entity) + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lifecycle {
+    /// The entity did not have the component before, runs after it was inserted
+    Add,
+    /// Every insert including replacing an existing value, runs after `Add`
+    Insert,
+    /// Runs before the component is removed, also on despawn, so it can still be read
+    Remove,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+/// The single hook per lifecycle event of a component type, runs before any observer.
+/// Meant for cleanup the component type always needs, e.g. freeing a GPU slot.
+#[derive(Default, Clone)]
+pub struct ComponentHooks {
+    pub on_add: Option<ObserverFn>,
+    pub on_insert: Option<ObserverFn>,
+    pub on_remove: Option<ObserverFn>,
+}
+
+impl ComponentHooks {
+    pub fn on_add(&mut self, hook: impl Fn(&mut World, Entity) + Send + Sync + 'static) -> &mut Self {
+        self.on_add = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_insert(&mut self, hook: impl Fn(&mut World, Entity) + Send + Sync + 'static) -> &mut Self {
+        self.on_insert = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_remove(&mut self, hook: impl Fn(&mut World, Entity) + Send + Sync + 'static) -> &mut Self {
+        self.on_remove = Some(Arc::new(hook));
+        self
+    }
+
+    fn get(&self, event: Lifecycle) -> Option<&ObserverFn> {
+        match event {
+            Lifecycle::Add => self.on_add.as_ref(),
+            Lifecycle::Insert => self.on_insert.as_ref(),
+            Lifecycle::Remove => self.on_remove.as_ref(),
+        }
+    }
+}
+
+struct Observer {
+    id: ObserverId,
+    // only this entity, or every entity
+    entity: Option<Entity>,
+    run: ObserverFn,
+}
+
+/// Hooks and observers of a `World`, keyed by component type
+#[derive(Default)]
+pub struct Observers {
+    hooks: HashMap<TypeId, ComponentHooks>,
+    observers: HashMap<(TypeId, Lifecycle), Vec<Observer>>,
+    next_id: u64,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty() && self.observers.is_empty()
+    }
+
+    pub fn get_hooks_mut(&mut self, type_id: TypeId) -> &mut ComponentHooks {
+        self.hooks.entry(type_id).or_default()
+    }
+
+    pub fn add(&mut self, type_id: TypeId, event: Lifecycle, entity: Option<Entity>, run: ObserverFn) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+
+        self.observers.entry((type_id, event)).or_default().push(Observer { id, entity, run });
+        id
+    }
+
+    pub fn remove(&mut self, id: ObserverId) -> bool {
+        let mut found = false;
+
+        self.observers.retain(|_, observers| {
+            observers.retain(|observer| {
+                found |= observer.id == id;
+                observer.id != id
+            });
+            !observers.is_empty()
+        });
+
+        found
+    }
+
+    /// Drops the observers watching a despawned entity
+    pub fn remove_entity(&mut self, entity: Entity) {
+        self.observers.retain(|_, observers| {
+            observers.retain(|observer| observer.entity != Some(entity));
+            !observers.is_empty()
+        });
+    }
+
+    /// The hook then the observers to run, cloned out so they can borrow the world mutably
+    pub fn collect(&self, type_id: TypeId, event: Lifecycle, entity: Entity) -> Vec<ObserverFn> {
+        let hook = self.hooks.get(&type_id).and_then(|hooks| hooks.get(event));
+
+        let observers = self.observers.get(&(type_id, event)).into_iter()
+            .flatten()
+            .filter(|observer| observer.entity.is_none_or(|watched| watched == entity));
+
+        hook.into_iter().cloned()
+            .chain(observers.map(|observer| observer.run.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{derive_component, derive_table_component};
+    use crate::ecs::{Entity, Lifecycle, World};
+
+    struct Health(u32);
+    struct Shield;
+    derive_component!(Health);
+    derive_table_component!(Shield);
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    fn record(log: &Log, entry: &'static str) -> impl Fn(&mut World, Entity) + Send + Sync + 'static {
+        let log = log.clone();
+        move |_, _| log.lock().unwrap().push(entry)
+    }
+
+    #[test]
+    fn hooks_run_before_observers_and_add_before_insert() {
+        let mut world = World::new();
+        let log = Log::default();
+
+        world.add_observer::<Health>(Lifecycle::Insert, record(&log, "observer insert"));
+        world.add_observer::<Health>(Lifecycle::Add, record(&log, "observer add"));
+        world.add_observer::<Health>(Lifecycle::Remove, record(&log, "observer remove"));
+        world.get_component_hooks_mut::<Health>()
+            .on_add(record(&log, "hook add"))
+            .on_insert(record(&log, "hook insert"))
+            .on_remove(record(&log, "hook remove"));
+
+        let entity = world.spawn();
+        world.insert(entity, Health(1));
+        world.remove::<Health>(entity);
+
+        assert_eq!(*log.lock().unwrap(), [
+            "hook add", "observer add", "hook insert", "observer insert", "hook remove", "observer remove",
+        ]);
+    }
+
+    #[test]
+    fn replacing_only_triggers_insert() {
+        let mut world = World::new();
+        let log = Log::default();
+
+        world.add_observer::<Health>(Lifecycle::Add, record(&log, "add"));
+        world.add_observer::<Health>(Lifecycle::Insert, record(&log, "insert"));
+
+        let entity = world.spawn();
+        world.insert(entity, Health(1));
+        assert_eq!(world.insert(entity, Health(2)).map(|health| health.0), Some(1));
+
+        assert_eq!(*log.lock().unwrap(), ["add", "insert", "insert"]);
+    }
+
+    #[test]
+    fn entity_observers_are_dropped_on_despawn() {
+        let mut world = World::new();
+        let log = Log::default();
+
+        let watched = world.spawn();
+        world.insert(watched, Health(1));
+        world.add_entity_observer::<Health>(watched, Lifecycle::Remove, record(&log, "watched"));
+
+        let other = world.spawn();
+        world.insert(other, Health(1));
+        world.remove::<Health>(other);
+        assert!(log.lock().unwrap().is_empty());
+
+        world.despawn(watched);
+        assert_eq!(*log.lock().unwrap(), ["watched"]);
+
+        // the index is reused by a new entity, the observer must not follow it
+        let reused = world.spawn();
+        assert_eq!(reused.index(), watched.index());
+        world.insert(reused, Health(1));
+        world.remove::<Health>(reused);
+        assert_eq!(*log.lock().unwrap(), ["watched"]);
+    }
+
+    #[test]
+    fn despawn_skips_components_a_hook_already_removed() {
+        let mut world = World::new();
+        let log = Log::default();
+
+        world.get_component_hooks_mut::<Health>().on_remove(|world, entity| {
+            world.remove::<Shield>(entity);
+        });
+        world.add_observer::<Shield>(Lifecycle::Remove, record(&log, "shield removed"));
+
+        // despawn visits the sparse `Health` before the table `Shield` its hook already removed
+        for _ in 0..2 {
+            let entity = world.spawn();
+            world.insert(entity, Health(1));
+            world.insert(entity, Shield);
+            world.despawn(entity);
+        }
+
+        assert_eq!(*log.lock().unwrap(), ["shield removed", "shield removed"]);
+    }
+}
@@ -0,0 +1,656 @@
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+
+use crate::ecs::archetype::{Archetype, ArchetypeId};
+use crate::ecs::change_detection::{ComponentTicks, Mut, QueryTicks};
+use crate::ecs::component::{Component, StorageType};
+use crate::ecs::entity::{Entity, EntityAllocator};
+use crate::ecs::resource::Resource;
+use crate::ecs::storage::ComponentStorage;
+use crate::ecs::world::{UnsafeWorldCell, World};
+
+/// Component types a query reads and writes, plus the resources of a system
+#[derive(Clone, Default, Debug)]
+pub struct ComponentAccess {
+    pub reads: Vec<(TypeId, &'static str)>,
+    pub writes: Vec<(TypeId, &'static str)>,
+    pub resource_reads: Vec<(TypeId, &'static str)>,
+    pub resource_writes: Vec<(TypeId, &'static str)>,
+}
+
+impl ComponentAccess {
+    pub fn add_read<T: Component>(&mut self) {
+        self.reads.push((TypeId::of::<T>(), type_name::<T>()));
+    }
+
+    pub fn add_write<T: Component>(&mut self) {
+        self.writes.push((TypeId::of::<T>(), type_name::<T>()));
+    }
+
+    pub fn add_resource_read<R: Resource>(&mut self) {
+        self.resource_reads.push((TypeId::of::<R>(), type_name::<R>()));
+    }
+
+    pub fn add_resource_write<R: Resource>(&mut self) {
+        self.resource_writes.push((TypeId::of::<R>(), type_name::<R>()));
+    }
+
+    /// Name of a component that is written and also read or written again, such a query would alias
+    pub fn find_self_conflict(&self) -> Option<&'static str> {
+        self.writes.iter().enumerate().find_map(|(index, (write, name))| {
+            let written_again = self.writes[index + 1..].iter().any(|(other, _)| other == write);
+            let also_read = self.reads.iter().any(|(read, _)| read == write);
+
+            (written_again || also_read).then_some(*name)
+        })
+    }
+
+    /// Whether running both at once could alias, one writes what the other touches
+    pub fn conflicts_with(&self, other: &ComponentAccess) -> bool {
+        type List = [(TypeId, &'static str)];
+
+        let overlaps = |writes: &List, reads: &List, other_writes: &List| {
+            writes.iter().any(|(write, _)| reads.iter().chain(other_writes.iter()).any(|(other, _)| other == write))
+        };
+
+        overlaps(&self.writes, &other.reads, &other.writes) ||
+            overlaps(&other.writes, &self.reads, &self.writes) ||
+            overlaps(&self.resource_writes, &other.resource_reads, &other.resource_writes) ||
+            overlaps(&other.resource_writes, &self.resource_reads, &self.resource_writes)
+    }
+
+    pub fn extend(&mut self, other: &ComponentAccess) {
+        self.reads.extend(other.reads.iter().cloned());
+        self.writes.extend(other.writes.iter().cloned());
+        self.resource_reads.extend(other.resource_reads.iter().cloned());
+        self.resource_writes.extend(other.resource_writes.iter().cloned());
+    }
+}
+
+/// Something a query fetches per entity: `&T`, `&mut T`, `Option<&T>`, `Option<&mut T>` or a tuple of them.
+/// `&mut T` yields a `Mut<T>` which marks the component changed when written through.
+///
+/// # Safety
+/// `access` must report every component `fetch` hands out, `&mut T` as a write.
+pub unsafe trait QueryData {
+    type Item<'w>;
+    type Fetch: Copy;
+
+    fn access(access: &mut ComponentAccess);
+
+    /// `None` when a required sparse storage does not exist, the query then matches nothing.
+    ///
+    /// # Safety
+    /// `world` must outlive the fetch. Mutable fetches need exclusive access to the components they write.
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch>;
+
+    /// Offers the entity list of every required sparse storage, the smallest one drives iteration
+    ///
+    /// # Safety
+    /// `fetch` must come from `init` on a world that is still borrowed.
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>);
+
+    /// Points table components at the columns of `archetype`, `false` if it lacks a required one
+    ///
+    /// # Safety
+    /// `archetype` must belong to the world `fetch` was created from.
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool;
+
+    /// # Safety
+    /// `row` is the entity's row in the archetype last passed to `set_archetype`.
+    /// Each entity may be fetched at most once while the items are alive.
+    unsafe fn fetch<'w>(fetch: &Self::Fetch, entity: Entity, row: usize) -> Option<Self::Item<'w>>;
+}
+
+/// Query data that never hands out mutable references, usable on a shared `World`
+///
+/// # Safety
+/// `fetch` must only hand out shared references.
+pub unsafe trait ReadOnlyQueryData: QueryData {}
+
+/// Restricts a query without fetching anything: `With<T>`, `Without<T>`, `Added<T>`, `Changed<T>` or a tuple of them
+pub trait QueryFilter {
+    type Fetch: Copy;
+
+    /// Components whose ticks `matches` reads. Presence checks report nothing, it only changes at sync points.
+    fn access(access: &mut ComponentAccess);
+
+    /// # Safety
+    /// `world` must outlive the fetch.
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch>;
+
+    /// # Safety
+    /// `fetch` must come from `init` on a world that is still borrowed.
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>);
+
+    /// # Safety
+    /// `archetype` must belong to the world `fetch` was created from.
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool;
+
+    /// # Safety
+    /// `row` is the entity's row in the archetype last passed to `set_archetype`.
+    unsafe fn matches(fetch: &Self::Fetch, entity: Entity, row: usize) -> bool;
+}
+
+/// Matches entities that have a `T`, without borrowing it
+pub struct With<T: Component>(PhantomData<T>);
+
+/// Matches entities that do not have a `T`
+pub struct Without<T: Component>(PhantomData<T>);
+
+pub(crate) unsafe fn offer_driver<'w>(indices: &'w [usize], driver: &mut Option<&'w [usize]>) {
+    if driver.is_none_or(|current| indices.len() < current.len()) {
+        *driver = Some(indices);
+    }
+}
+
+/// Where a query finds `T`: the sparse set of the type, or the column of the archetype being iterated
+pub struct ComponentFetch<T: Component> {
+    storage: *const ComponentStorage<T>,
+    data: *mut T,
+    ticks: *mut ComponentTicks,
+}
+
+impl<T: Component> Clone for ComponentFetch<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Component> Copy for ComponentFetch<T> {}
+
+impl<T: Component> ComponentFetch<T> {
+    /// `None` for a sparse component nobody inserted yet, table columns are resolved per archetype
+    pub(crate) unsafe fn new(world: UnsafeWorldCell<'_>, mutable: bool) -> Option<Self> {
+        let empty = Self { storage: std::ptr::null(), data: std::ptr::null_mut(), ticks: std::ptr::null_mut() };
+
+        // sparse components are reached through the dense pointers so no two items ever share a reference
+        unsafe {
+            match (T::STORAGE, mutable) {
+                (StorageType::Table, _) => Some(empty),
+                (StorageType::SparseSet, true) => world.get_storage_mut::<T>().map(|storage| Self {
+                    data: storage.as_mut_ptr(),
+                    ticks: storage.ticks_as_mut_ptr(),
+                    storage,
+                }),
+                (StorageType::SparseSet, false) => world.get_storage::<T>().map(|storage| Self {
+                    data: storage.as_ptr() as *mut T,
+                    ticks: storage.ticks_as_ptr() as *mut ComponentTicks,
+                    storage,
+                }),
+            }
+        }
+    }
+
+    pub(crate) unsafe fn driver(&self, driver: &mut Option<&[usize]>) {
+        if T::STORAGE == StorageType::SparseSet {
+            unsafe { offer_driver((*self.storage).indices(), driver) }
+        }
+    }
+
+    /// Whether the archetype has `T`, always true for sparse components
+    pub(crate) unsafe fn set_archetype(&mut self, archetype: &Archetype, mutable: bool) -> bool {
+        if T::STORAGE == StorageType::SparseSet {
+            return true;
+        }
+
+        let pointers = archetype.get_column_ptr::<T>().map(|column| unsafe {
+            match mutable {
+                true => ((*column).as_mut_ptr(), (*column).ticks_as_mut_ptr()),
+                false => ((*column).as_ptr() as *mut T, (*column).ticks_as_ptr() as *mut ComponentTicks),
+            }
+        });
+
+        (self.data, self.ticks) = pointers.unwrap_or((std::ptr::null_mut(), std::ptr::null_mut()));
+        pointers.is_some()
+    }
+
+    /// Index of the entity's component behind `data` and `ticks`
+    pub(crate) unsafe fn locate(&self, entity: Entity, row: usize) -> Option<usize> {
+        match T::STORAGE {
+            StorageType::SparseSet => unsafe { (*self.storage).dense_index(entity.index()) },
+            StorageType::Table => (!self.data.is_null()).then_some(row),
+        }
+    }
+
+    pub(crate) unsafe fn get_ticks<'w>(&self, entity: Entity, row: usize) -> Option<&'w ComponentTicks> {
+        unsafe { self.locate(entity, row).map(|index| &*self.ticks.add(index)) }
+    }
+}
+
+// --- Query Data ---
+
+unsafe impl<T: Component> QueryData for &T {
+    type Item<'w> = &'w T;
+    type Fetch = ComponentFetch<T>;
+
+    fn access(access: &mut ComponentAccess) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, _ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { ComponentFetch::new(world, false) }
+    }
+
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+        unsafe { fetch.driver(driver) }
+    }
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        unsafe { fetch.set_archetype(archetype, false) }
+    }
+
+    unsafe fn fetch<'w>(fetch: &Self::Fetch, entity: Entity, row: usize) -> Option<Self::Item<'w>> {
+        unsafe { fetch.locate(entity, row).map(|index| &*fetch.data.add(index)) }
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQueryData for &T {}
+
+unsafe impl<T: Component> QueryData for &mut T {
+    type Item<'w> = Mut<'w, T>;
+    type Fetch = (ComponentFetch<T>, u64);
+
+    fn access(access: &mut ComponentAccess) {
+        access.add_write::<T>();
+    }
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { ComponentFetch::new(world, true).map(|fetch| (fetch, ticks.this_run)) }
+    }
+
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+        unsafe { fetch.0.driver(driver) }
+    }
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        unsafe { fetch.0.set_archetype(archetype, true) }
+    }
+
+    unsafe fn fetch<'w>(fetch: &Self::Fetch, entity: Entity, row: usize) -> Option<Self::Item<'w>> {
+        let (component, this_run) = fetch;
+
+        unsafe {
+            component.locate(entity, row)
+                .map(|index| Mut::new(&mut *component.data.add(index), &mut *component.ticks.add(index), *this_run))
+        }
+    }
+}
+
+unsafe impl<T: Component> QueryData for Option<&T> {
+    type Item<'w> = Option<&'w T>;
+    type Fetch = Option<ComponentFetch<T>>;
+
+    fn access(access: &mut ComponentAccess) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { Some(<&T as QueryData>::init(world, ticks)) }
+    }
+
+    unsafe fn driver(_fetch: &Self::Fetch, _driver: &mut Option<&[usize]>) {}
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        if let Some(fetch) = fetch {
+            unsafe { <&T as QueryData>::set_archetype(fetch, archetype) };
+        }
+        true
+    }
+
+    unsafe fn fetch<'w>(fetch: &Self::Fetch, entity: Entity, row: usize) -> Option<Self::Item<'w>> {
+        unsafe { Some(fetch.as_ref().and_then(|fetch| <&T as QueryData>::fetch(fetch, entity, row))) }
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQueryData for Option<&T> {}
+
+unsafe impl<T: Component> QueryData for Option<&mut T> {
+    type Item<'w> = Option<Mut<'w, T>>;
+    type Fetch = Option<(ComponentFetch<T>, u64)>;
+
+    fn access(access: &mut ComponentAccess) {
+        access.add_write::<T>();
+    }
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { Some(<&mut T as QueryData>::init(world, ticks)) }
+    }
+
+    unsafe fn driver(_fetch: &Self::Fetch, _driver: &mut Option<&[usize]>) {}
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        if let Some(fetch) = fetch {
+            unsafe { <&mut T as QueryData>::set_archetype(fetch, archetype) };
+        }
+        true
+    }
+
+    unsafe fn fetch<'w>(fetch: &Self::Fetch, entity: Entity, row: usize) -> Option<Self::Item<'w>> {
+        unsafe { Some(fetch.as_ref().and_then(|fetch| <&mut T as QueryData>::fetch(fetch, entity, row))) }
+    }
+}
+
+macro_rules! impl_query_data_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: QueryData),+> QueryData for ($($name,)+) {
+            type Item<'w> = ($($name::Item<'w>,)+);
+            type Fetch = ($($name::Fetch,)+);
+
+            fn access(access: &mut ComponentAccess) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+                unsafe { Some(($($name::init(world, ticks)?,)+)) }
+            }
+
+            unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+                let ($($name,)+) = fetch;
+                unsafe { $($name::driver($name, driver);)+ }
+            }
+
+            unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+                let ($($name,)+) = fetch;
+                unsafe { $($name::set_archetype($name, archetype))&&+ }
+            }
+
+            unsafe fn fetch<'w>(fetch: &Self::Fetch, entity: Entity, row: usize) -> Option<Self::Item<'w>> {
+                let ($($name,)+) = fetch;
+                unsafe { Some(($($name::fetch($name, entity, row)?,)+)) }
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyQueryData),+> ReadOnlyQueryData for ($($name,)+) {}
+    };
+}
+
+impl_query_data_tuple!(A);
+impl_query_data_tuple!(A, B);
+impl_query_data_tuple!(A, B, C);
+impl_query_data_tuple!(A, B, C, D);
+impl_query_data_tuple!(A, B, C, D, E);
+impl_query_data_tuple!(A, B, C, D, E, F);
+impl_query_data_tuple!(A, B, C, D, E, F, G);
+impl_query_data_tuple!(A, B, C, D, E, F, G, H);
+
+// --- Query Filters ---
+
+impl QueryFilter for () {
+    type Fetch = ();
+
+    fn access(_access: &mut ComponentAccess) {}
+
+    unsafe fn init(_world: UnsafeWorldCell<'_>, _ticks: QueryTicks) -> Option<Self::Fetch> {
+        Some(())
+    }
+
+    unsafe fn driver(_fetch: &Self::Fetch, _driver: &mut Option<&[usize]>) {}
+
+    unsafe fn set_archetype(_fetch: &mut Self::Fetch, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    unsafe fn matches(_fetch: &Self::Fetch, _entity: Entity, _row: usize) -> bool {
+        true
+    }
+}
+
+impl<T: Component> QueryFilter for With<T> {
+    type Fetch = ComponentFetch<T>;
+
+    fn access(_access: &mut ComponentAccess) {}
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, _ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { ComponentFetch::new(world, false) }
+    }
+
+    unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+        unsafe { fetch.driver(driver) }
+    }
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        unsafe { fetch.set_archetype(archetype, false) }
+    }
+
+    unsafe fn matches(fetch: &Self::Fetch, entity: Entity, row: usize) -> bool {
+        unsafe { fetch.locate(entity, row).is_some() }
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    type Fetch = Option<ComponentFetch<T>>;
+
+    fn access(_access: &mut ComponentAccess) {}
+
+    unsafe fn init(world: UnsafeWorldCell<'_>, _ticks: QueryTicks) -> Option<Self::Fetch> {
+        unsafe { Some(ComponentFetch::new(world, false)) }
+    }
+
+    unsafe fn driver(_fetch: &Self::Fetch, _driver: &mut Option<&[usize]>) {}
+
+    unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+        // table components are excluded per archetype, sparse ones per entity in `matches`
+        match fetch {
+            Some(fetch) if T::STORAGE == StorageType::Table => unsafe { !fetch.set_archetype(archetype, false) },
+            _ => true,
+        }
+    }
+
+    unsafe fn matches(fetch: &Self::Fetch, entity: Entity, row: usize) -> bool {
+        unsafe { fetch.is_none_or(|fetch| fetch.locate(entity, row).is_none()) }
+    }
+}
+
+macro_rules! impl_query_filter_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: QueryFilter),+> QueryFilter for ($($name,)+) {
+            type Fetch = ($($name::Fetch,)+);
+
+            fn access(access: &mut ComponentAccess) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn init(world: UnsafeWorldCell<'_>, ticks: QueryTicks) -> Option<Self::Fetch> {
+                unsafe { Some(($($name::init(world, ticks)?,)+)) }
+            }
+
+            unsafe fn driver(fetch: &Self::Fetch, driver: &mut Option<&[usize]>) {
+                let ($($name,)+) = fetch;
+                unsafe { $($name::driver($name, driver);)+ }
+            }
+
+            unsafe fn set_archetype(fetch: &mut Self::Fetch, archetype: &Archetype) -> bool {
+                let ($($name,)+) = fetch;
+                unsafe { $($name::set_archetype($name, archetype))&&+ }
+            }
+
+            unsafe fn matches(fetch: &Self::Fetch, entity: Entity, row: usize) -> bool {
+                let ($($name,)+) = fetch;
+                unsafe { $($name::matches($name, entity, row))&&+ }
+            }
+        }
+    };
+}
+
+impl_query_filter_tuple!(A);
+impl_query_filter_tuple!(A, B);
+impl_query_filter_tuple!(A, B, C);
+impl_query_filter_tuple!(A, B, C, D);
+impl_query_filter_tuple!(A, B, C, D, E);
+impl_query_filter_tuple!(A, B, C, D, E, F);
+
+// --- Iteration ---
+
+fn check_access<Q: QueryData>() {
+    let mut access = ComponentAccess::default();
+    Q::access(&mut access);
+
+    if let Some(name) = access.find_self_conflict() {
+        panic!("query borrows {} mutably while also accessing it elsewhere", name);
+    }
+}
+
+/// Fetches `Q` for one live entity, same requirements as `QueryIter::new`
+pub(crate) unsafe fn fetch_one<'w, Q: QueryData>(world: UnsafeWorldCell<'w>, ticks: QueryTicks, entity: Entity) -> Option<Q::Item<'w>> {
+    check_access::<Q>();
+
+    unsafe {
+        let mut fetch = Q::init(world, ticks)?;
+        let location = world.get_location(entity);
+
+        match Q::set_archetype(&mut fetch, world.archetypes().get(location.archetype)) {
+            true => Q::fetch(&fetch, entity, location.row),
+            false => None,
+        }
+    }
+}
+
+/// Iterates `(Entity, Q::Item)` for every entity matching `Q` and `F`, see `World::query`
+pub struct QueryIter<'w, Q: QueryData, F: QueryFilter = ()> {
+    world: UnsafeWorldCell<'w>,
+    entities: &'w EntityAllocator,
+    fetch: Option<(Q::Fetch, F::Fetch)>,
+    driver: Option<&'w [usize]>,
+    // archetype the fetches point at, whether it matches and its entities
+    archetype: Option<ArchetypeId>,
+    archetype_matches: bool,
+    archetype_entities: &'w [Entity],
+    // index into `driver`, or the row of `archetype` without a driver
+    position: usize,
+    _marker: PhantomData<&'w mut World>,
+}
+
+impl<'w, Q: QueryData, F: QueryFilter> QueryIter<'w, Q, F> {
+    /// Panics if `Q` borrows a component mutably more than once.
+    /// Mutable `Q` needs exclusive access to the components it writes for `'w`.
+    /// `Added` and `Changed` compare against `ticks.last_run`, writes are stamped with `ticks.this_run`.
+    pub(crate) unsafe fn new(world: UnsafeWorldCell<'w>, ticks: QueryTicks) -> Self {
+        check_access::<Q>();
+
+        unsafe {
+            let fetch = Q::init(world, ticks).zip(F::init(world, ticks));
+
+            let mut driver = None;
+            if let Some((query_fetch, filter_fetch)) = &fetch {
+                Q::driver(query_fetch, &mut driver);
+                F::driver(filter_fetch, &mut driver);
+            }
+
+            Self {
+                world,
+                entities: world.entities(),
+                fetch,
+                driver,
+                archetype: None,
+                archetype_matches: false,
+                archetype_entities: &[],
+                position: 0,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    unsafe fn set_archetype(&mut self, id: ArchetypeId) {
+        let Some((query_fetch, filter_fetch)) = &mut self.fetch else {
+            return;
+        };
+
+        unsafe {
+            let archetype = self.world.archetypes().get(id);
+
+            self.archetype = Some(id);
+            self.archetype_matches = Q::set_archetype(query_fetch, archetype) && F::set_archetype(filter_fetch, archetype);
+            self.archetype_entities = archetype.entities();
+        }
+    }
+
+    /// Walks the rows of every matching archetype
+    unsafe fn next_in_archetypes(&mut self) -> Option<(Entity, Q::Item<'w>)> {
+        unsafe {
+            loop {
+                if !self.archetype_matches || self.position >= self.archetype_entities.len() {
+                    let next = self.archetype.map_or(0, |id| id + 1);
+                    if next >= self.world.archetypes().len() {
+                        return None;
+                    }
+
+                    self.set_archetype(next);
+                    self.position = 0;
+                    continue;
+                }
+
+                let row = self.position;
+                self.position += 1;
+
+                let entity = self.archetype_entities[row];
+                let (query_fetch, filter_fetch) = self.fetch.as_ref()?;
+
+                if !F::matches(filter_fetch, entity, row) {
+                    continue;
+                }
+
+                if let Some(item) = Q::fetch(query_fetch, entity, row) {
+                    return Some((entity, item));
+                }
+            }
+        }
+    }
+
+    /// Walks the entities of the smallest required sparse set
+    unsafe fn next_in_driver(&mut self, driver: &'w [usize]) -> Option<(Entity, Q::Item<'w>)> {
+        unsafe {
+            while self.position < driver.len() {
+                let index = driver[self.position];
+                self.position += 1;
+
+                let Some(entity) = self.entities.get(index) else {
+                    continue;
+                };
+
+                let location = self.world.get_location(entity);
+                if self.archetype != Some(location.archetype) {
+                    self.set_archetype(location.archetype);
+                }
+
+                let (query_fetch, filter_fetch) = self.fetch.as_ref()?;
+
+                // every index is visited once, so mutable items never alias
+                if !self.archetype_matches || !F::matches(filter_fetch, entity, location.row) {
+                    continue;
+                }
+
+                if let Some(item) = Q::fetch(query_fetch, entity, location.row) {
+                    return Some((entity, item));
+                }
+            }
+
+            None
+        }
+    }
+}
+
+impl<'w, Q: QueryData, F: QueryFilter> Iterator for QueryIter<'w, Q, F> {
+    type Item = (Entity, Q::Item<'w>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fetch.as_ref()?;
+
+        unsafe {
+            match self.driver {
+                Some(driver) => self.next_in_driver(driver),
+                None => self.next_in_archetypes(),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match (&self.fetch, self.driver) {
+            (None, _) => (0, Some(0)),
+            (Some(_), Some(driver)) => (0, Some(driver.len().saturating_sub(self.position))),
+            (Some(_), None) => (0, None),
+        }
+    }
+}
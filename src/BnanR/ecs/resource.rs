@@ -0,0 +1,6 @@
+use std::any::Any;
+
+/// A singleton stored on the `World` instead of an entity, e.g. frame time, camera or input state
+pub trait Resource: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> Resource for T {}
@@ -0,0 +1,303 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use anyhow::*;
+use serde::*;
+use serde::de::DeserializeOwned;
+use bincode;
+
+use crate::ecs::component::Component;
+use crate::ecs::entity::Entity;
+use crate::ecs::hierarchy::{Children, Parent};
+use crate::ecs::transform::LocalTransform;
+use crate::ecs::world::World;
+use crate::fs::bpk::BpkArchive;
+
+const SCENE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SceneHeader {
+    version: u32,
+    entities: Vec<Entity>,
+    components: Vec<String>,
+}
+
+/// Saved entity ids to the entities spawned for them by `World::load_scene`
+#[derive(Default, Debug, Clone)]
+pub struct EntityMap {
+    map: HashMap<Entity, Entity>,
+}
+
+impl EntityMap {
+    /// References to entities that were not part of the scene become `Entity::PLACEHOLDER`
+    pub fn get(&self, saved: Entity) -> Entity {
+        self.map.get(&saved).copied().unwrap_or(Entity::PLACEHOLDER)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.map.iter().map(|(saved, loaded)| (*saved, *loaded))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Components holding entity references, rewritten when a scene is loaded
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for Parent {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0 = map.get(self.0);
+    }
+}
+
+impl MapEntities for Children {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for child in &mut self.0 {
+            *child = map.get(*child);
+        }
+    }
+}
+
+type ApplyFn = Box<dyn FnOnce(&mut World, &EntityMap)>;
+
+struct SceneComponent {
+    name: String,
+    save: fn(&World) -> Result<Vec<u8>>,
+    // deserializes up front so a bad scene fails before anything is spawned
+    load: fn(&[u8]) -> Result<ApplyFn>,
+}
+
+/// Components that are written to scenes, by the name they are stored under.
+/// Lives on the world as a resource, see `World::register_scene_component`.
+pub struct SceneRegistry {
+    components: Vec<SceneComponent>,
+    by_type: HashMap<TypeId, usize>,
+}
+
+impl SceneRegistry {
+    pub fn new() -> Self {
+        Self { components: Vec::new(), by_type: HashMap::new() }
+    }
+
+    /// Registry with the hierarchy and `LocalTransform`, `GlobalTransform` is recomputed after loading
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register_mapped::<Parent>("Parent");
+        registry.register_mapped::<Children>("Children");
+        registry.register::<LocalTransform>("LocalTransform");
+        registry
+    }
+
+    pub fn register<T: Component + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        self.add::<T>(name, save_component::<T>, load_component::<T>);
+    }
+
+    /// For components that reference other entities
+    pub fn register_mapped<T: Component + Serialize + DeserializeOwned + MapEntities>(&mut self, name: &str) {
+        self.add::<T>(name, save_component::<T>, load_mapped_component::<T>);
+    }
+
+    pub fn is_registered<T: Component>(&self) -> bool {
+        self.by_type.contains_key(&TypeId::of::<T>())
+    }
+
+    fn add<T: Component>(&mut self, name: &str, save: fn(&World) -> Result<Vec<u8>>, load: fn(&[u8]) -> Result<ApplyFn>) {
+        let component = SceneComponent { name: name.to_string(), save, load };
+
+        match self.by_type.get(&TypeId::of::<T>()) {
+            Some(&index) => self.components[index] = component,
+            None => {
+                self.by_type.insert(TypeId::of::<T>(), self.components.len());
+                self.components.push(component);
+            }
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&SceneComponent> {
+        self.components.iter().find(|component| component.name == name)
+    }
+}
+
+impl Default for SceneRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn save_component<T: Component + Serialize>(world: &World) -> Result<Vec<u8>> {
+    let components: Vec<(Entity, &T)> = world.query_ref::<&T>().collect();
+    Ok(bincode::serialize(&components)?)
+}
+
+fn load_component<T: Component + DeserializeOwned>(bytes: &[u8]) -> Result<ApplyFn> {
+    let components: Vec<(Entity, T)> = bincode::deserialize(bytes)?;
+
+    Ok(Box::new(move |world: &mut World, map: &EntityMap| {
+        for (entity, component) in components {
+            world.insert(map.get(entity), component);
+        }
+    }))
+}
+
+fn load_mapped_component<T: Component + DeserializeOwned + MapEntities>(bytes: &[u8]) -> Result<ApplyFn> {
+    let components: Vec<(Entity, T)> = bincode::deserialize(bytes)?;
+
+    Ok(Box::new(move |world: &mut World, map: &EntityMap| {
+        for (entity, mut component) in components {
+            component.map_entities(map);
+            world.insert(map.get(entity), component);
+        }
+    }))
+}
+
+impl World {
+    /// Opts `T` into `save_scene`, the registry starts out with `SceneRegistry::with_defaults`
+    pub fn register_scene_component<T: Component + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        self.get_resource_or_insert_with(SceneRegistry::with_defaults).register::<T>(name);
+    }
+
+    pub fn register_scene_component_mapped<T: Component + Serialize + DeserializeOwned + MapEntities>(&mut self, name: &str) {
+        self.get_resource_or_insert_with(SceneRegistry::with_defaults).register_mapped::<T>(name);
+    }
+
+    /// Writes every entity and its registered components under `path` in the archive
+    pub fn save_scene(&self, archive: &mut BpkArchive, path: &str) -> Result<()> {
+        let defaults;
+        let registry = match self.get_resource::<SceneRegistry>() {
+            Some(registry) => registry,
+            None => {
+                defaults = SceneRegistry::with_defaults();
+                &defaults
+            }
+        };
+
+        let header = SceneHeader {
+            version: SCENE_VERSION,
+            entities: self.entities().iter().collect(),
+            components: registry.components.iter().map(|component| component.name.clone()).collect(),
+        };
+
+        archive.add_directory(path)?;
+        archive.add_item(&format!("{}/scene.meta", path), bincode::serialize(&header)?)?;
+
+        for component in &registry.components {
+            let bytes = (component.save)(self).with_context(|| format!("Failed to save scene component {}", component.name))?;
+            archive.add_item(&format!("{}/components/{}", path, component.name), bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Spawns the entities of the scene under `path` and inserts their components, entity references
+    /// are remapped to the new entities. Returns the saved to spawned entity map.
+    pub fn load_scene(&mut self, archive: &BpkArchive, path: &str) -> Result<EntityMap> {
+        let header: SceneHeader = bincode::deserialize(&archive.read_file(&format!("{}/scene.meta", path))?)?;
+        if header.version != SCENE_VERSION {
+            bail!("Unsupported scene version: {}", header.version);
+        }
+
+        let registry = self.remove_resource::<SceneRegistry>().unwrap_or_else(SceneRegistry::with_defaults);
+
+        let loaded: Result<Vec<ApplyFn>> = header.components.iter()
+            .map(|name| {
+                let component = registry.find(name).ok_or_else(|| anyhow!("Scene component {} is not registered", name))?;
+                let bytes = archive.read_file(&format!("{}/components/{}", path, name))?;
+                (component.load)(&bytes).with_context(|| format!("Failed to load scene component {}", name))
+            })
+            .collect();
+
+        self.insert_resource(registry);
+        let loaded = loaded?;
+
+        let mut map = EntityMap::default();
+        for saved in header.entities {
+            map.map.insert(saved, self.spawn());
+        }
+
+        for apply in loaded {
+            apply(self, &map);
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derive_component;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Health(u32);
+    derive_component!(Health);
+
+    // a root with two children, each with its own health
+    fn saved_archive() -> (BpkArchive, [Entity; 3]) {
+        let mut world = World::new();
+        world.register_scene_component::<Health>("Health");
+
+        let root = world.spawn();
+        let first = world.spawn();
+        let second = world.spawn();
+        world.set_parent(first, root);
+        world.set_parent(second, root);
+
+        for (index, entity) in [root, first, second].into_iter().enumerate() {
+            world.insert(entity, Health(index as u32 * 10));
+        }
+
+        let mut archive = BpkArchive::new();
+        world.save_scene(&mut archive, "scene").unwrap();
+        (archive, [root, first, second])
+    }
+
+    #[test]
+    fn round_trip_into_a_populated_world_remaps_the_hierarchy() {
+        let (archive, [root, first, second]) = saved_archive();
+
+        let mut world = World::new();
+        world.register_scene_component::<Health>("Health");
+
+        // takes the ids the saved entities had, so an unmapped reference would point here
+        let existing: Vec<Entity> = (0..3).map(|_| world.spawn()).collect();
+        world.insert(existing[0], Health(99));
+
+        let map = world.load_scene(&archive, "scene").unwrap();
+        assert_eq!(map.len(), 3);
+
+        let (root, first, second) = (map.get(root), map.get(first), map.get(second));
+        assert!(!existing.contains(&root) && !existing.contains(&first) && !existing.contains(&second));
+
+        assert_eq!(world.get_parent(first), Some(root));
+        assert_eq!(world.get_parent(second), Some(root));
+        assert_eq!(world.get_parent(root), None);
+        assert_eq!(world.get_children(root), [first, second]);
+
+        assert_eq!(world.get::<Health>(root), Some(&Health(0)));
+        assert_eq!(world.get::<Health>(second), Some(&Health(20)));
+
+        // the entities that were already there are left alone
+        assert_eq!(world.get::<Health>(existing[0]), Some(&Health(99)));
+        assert!(existing.iter().all(|&entity| world.get_parent(entity).is_none() && world.get_children(entity).is_empty()));
+    }
+
+    #[test]
+    fn unregistered_component_fails_before_spawning() {
+        let (archive, _) = saved_archive();
+
+        let mut world = World::new();
+        world.spawn();
+
+        let error = world.load_scene(&archive, "scene").unwrap_err().to_string();
+        assert!(error.contains("Health"), "{}", error);
+        assert_eq!(world.entities().iter().count(), 1);
+    }
+}
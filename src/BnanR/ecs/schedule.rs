@@ -0,0 +1,321 @@
+use anyhow::*;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::ecs::change_detection::QueryTicks;
+use crate::ecs::command::Commands;
+use crate::ecs::system::{System, SystemRun, SystemWorld};
+use crate::ecs::world::{UnsafeWorldCell, World};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Runs once, on the first `run`
+    Startup,
+    PreUpdate,
+    Update,
+    /// Copies what the renderer needs out of the world
+    RenderExtract,
+}
+
+impl Stage {
+    pub const COUNT: usize = 4;
+    pub const ALL: [Stage; Self::COUNT] = [Stage::Startup, Stage::PreUpdate, Stage::Update, Stage::RenderExtract];
+}
+
+struct StageSystems {
+    systems: Vec<System>,
+    // indices into `systems`, the systems of a batch never conflict
+    batches: Vec<Vec<usize>>,
+    dirty: bool,
+}
+
+/// Systems grouped by stage. Within a stage, systems whose component access does not conflict run in parallel,
+/// conflicting systems keep the order they were added in.
+pub struct Schedule {
+    stages: [StageSystems; Stage::COUNT],
+    startup_done: bool,
+    deterministic: bool,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self {
+            stages: std::array::from_fn(|_| StageSystems { systems: Vec::new(), batches: Vec::new(), dirty: false }),
+            startup_done: false,
+            deterministic: false,
+        }
+    }
+
+    pub fn add_system(&mut self, stage: Stage, system: System) -> &mut Self {
+        let stage = &mut self.stages[stage as usize];
+        stage.systems.push(system);
+        stage.dirty = true;
+        self
+    }
+
+    /// Runs the systems of a batch one after another in the order they were added, so entities
+    /// spawned through `Commands` get the same ids on every run, e.g. for lockstep or replays
+    pub fn set_deterministic(&mut self, deterministic: bool) -> &mut Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Runs every stage in order on the global rayon pool, startup only the first time
+    pub fn run(&mut self, world: &mut World) -> Result<()> {
+        self.run_stages(world, None)
+    }
+
+    /// Like `run` but on the given pool, e.g. `BnanDevice::thread_pool`
+    pub fn run_on(&mut self, world: &mut World, pool: &ThreadPool) -> Result<()> {
+        self.run_stages(world, Some(pool))
+    }
+
+    pub fn run_stage(&mut self, stage: Stage, world: &mut World, pool: Option<&ThreadPool>) -> Result<()> {
+        self.build_batches(stage)?;
+
+        let deterministic = self.deterministic;
+        let stage = &mut self.stages[stage as usize];
+        // batches never contain conflicting systems, so their threads share the cell
+        let world = world.as_unsafe_world_cell();
+
+        for batch in &stage.batches {
+            // hand out disjoint &mut System so each batch member can run on its own thread
+            let mut systems: Vec<&mut System> = stage.systems.iter_mut().enumerate()
+                .filter(|(index, _)| batch.contains(index))
+                .map(|(_, system)| system)
+                .collect();
+
+            let this_run = unsafe { world.change_tick() };
+
+            if let [system] = systems.as_mut_slice() {
+                Self::run_system(system, world, this_run);
+            } else if deterministic {
+                for system in systems.iter_mut() {
+                    Self::run_system(system, world, this_run);
+                }
+            } else {
+                let mut run_batch = || systems.par_iter_mut().for_each(|system| Self::run_system(system, world, this_run));
+
+                match pool {
+                    Some(pool) => pool.install(run_batch),
+                    None => run_batch(),
+                }
+            }
+
+            // commands, later batches and code outside the schedule write with a newer tick than the systems
+            // above ran at, so `Added`, `Changed` and `removed` show them those changes on their next run
+            unsafe { world.world_mut().increment_change_tick() };
+
+            // sync point, commands apply in the order the systems were added
+            for system in systems {
+                unsafe { system.commands.apply(world.world_mut()) };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// System names of each batch of the stage, in execution order
+    pub fn get_batches(&mut self, stage: Stage) -> Result<Vec<Vec<String>>> {
+        self.build_batches(stage)?;
+
+        let stage = &self.stages[stage as usize];
+        Ok(stage.batches.iter()
+            .map(|batch| batch.iter().map(|&index| stage.systems[index].name.clone()).collect())
+            .collect())
+    }
+
+    fn run_stages(&mut self, world: &mut World, pool: Option<&ThreadPool>) -> Result<()> {
+        world.update_events();
+
+        for stage in Stage::ALL {
+            if stage == Stage::Startup {
+                if self.startup_done {
+                    continue;
+                }
+                self.startup_done = true;
+            }
+
+            self.run_stage(stage, world, pool)?;
+        }
+
+        // startup systems never run again, so only the others have to see a removal before it is dropped
+        let oldest_run = self.stages.iter().skip(1)
+            .flat_map(|stage| stage.systems.iter())
+            .map(|system| system.last_run)
+            .min();
+
+        if let Some(oldest_run) = oldest_run {
+            world.prune_removed(oldest_run + 1);
+        }
+
+        Ok(())
+    }
+
+    fn run_system(system: &mut System, world: UnsafeWorldCell<'_>, this_run: u64) {
+        let ticks = QueryTicks { last_run: system.last_run, this_run };
+
+        // a parallel batch only holds systems with disjoint access, exclusive systems always run alone
+        unsafe {
+            match &mut system.run {
+                SystemRun::Parallel(run) => {
+                    let mut commands = Commands::new(&mut system.commands, world.entities());
+                    run(&mut SystemWorld::new(world, &system.name, &system.access, ticks), &mut commands);
+                }
+                SystemRun::Exclusive(run) => {
+                    // queries on the world itself compare against the system's last run for the duration
+                    let world = world.world_mut();
+                    let last_change_tick = world.set_last_change_tick(system.last_run);
+                    run(world);
+                    world.set_last_change_tick(last_change_tick);
+                }
+            }
+        }
+
+        system.last_run = this_run;
+    }
+
+    /// Places every system in the first batch after all earlier systems it conflicts with or must follow
+    fn build_batches(&mut self, stage: Stage) -> Result<()> {
+        let stage = &mut self.stages[stage as usize];
+        if !stage.dirty {
+            return Ok(());
+        }
+
+        let mut batch_of: Vec<usize> = Vec::with_capacity(stage.systems.len());
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+
+        for (index, system) in stage.systems.iter().enumerate() {
+            let mut first_batch = 0;
+
+            for name in &system.after {
+                let Some(dependency) = stage.systems[..index].iter().position(|other| &other.name == name) else {
+                    bail!("system {} runs after {} which is not an earlier system of the same stage", system.name, name);
+                };
+                first_batch = first_batch.max(batch_of[dependency] + 1);
+            }
+
+            for (other_index, other) in stage.systems[..index].iter().enumerate() {
+                if system.conflicts_with(other) {
+                    first_batch = first_batch.max(batch_of[other_index] + 1);
+                }
+            }
+
+            // every conflicting system sits before `first_batch`, exclusive systems get a batch of their own
+            let batch = match system.is_exclusive() {
+                true => batches.len(),
+                false => first_batch.min(batches.len()),
+            };
+
+            if batch == batches.len() {
+                batches.push(Vec::new());
+            }
+
+            batches[batch].push(index);
+            batch_of.push(batch);
+        }
+
+        stage.batches = batches;
+        stage.dirty = false;
+        Ok(())
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::derive_component;
+    use crate::ecs::{Added, Changed, Entity};
+
+    struct Health(u32);
+    struct Armor;
+    derive_component!(Health, Armor);
+
+    type Log = Arc<Mutex<Vec<(usize, usize)>>>;
+
+    // counts the `Added<Health>` and removed `Health` the system sees on each run
+    fn watcher(name: &str, log: &Log) -> System {
+        let log = log.clone();
+        System::new(name, move |world, _| {
+            let added = world.query_ref_filtered::<&Health, Added<Health>>().count();
+            let removed = world.removed::<Health>().count();
+            log.lock().unwrap().push((added, removed));
+        }).reads::<Health>()
+    }
+
+    #[test]
+    fn commands_are_seen_by_their_batch_and_later_batches() {
+        let mut world = World::new();
+        let mut schedule = Schedule::new();
+        let (peer_log, later_log) = (Log::default(), Log::default());
+
+        // spawns on the first run and removes on the second, never touching `Health` directly
+        let mut spawned: Option<Entity> = None;
+        schedule.add_system(Stage::Update, System::new("spawner", move |_, commands| {
+            match spawned {
+                None => {
+                    let entity = commands.spawn();
+                    commands.insert(entity, Health(10));
+                    spawned = Some(entity);
+                }
+                Some(entity) => commands.remove::<Health>(entity),
+            }
+        }).writes::<Armor>());
+
+        schedule.add_system(Stage::Update, watcher("peer", &peer_log));
+        schedule.add_system(Stage::Update, watcher("later", &later_log).after("peer"));
+
+        assert_eq!(schedule.get_batches(Stage::Update).unwrap(), vec![
+            vec!["spawner".to_string(), "peer".to_string()],
+            vec!["later".to_string()],
+        ]);
+
+        for _ in 0..3 {
+            schedule.run(&mut world).unwrap();
+        }
+
+        // the peer ran before the commands applied, so it sees them one run later
+        assert_eq!(*peer_log.lock().unwrap(), vec![(0, 0), (1, 0), (0, 1)]);
+        assert_eq!(*later_log.lock().unwrap(), vec![(1, 0), (0, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn changes_are_seen_once_by_every_later_reader() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Health(1));
+
+        let mut schedule = Schedule::new();
+        let log = Log::default();
+
+        schedule.add_system(Stage::Update, System::new("heal", |world, _| {
+            for (_, mut health) in world.query::<&mut Health>() {
+                if health.0 < 3 {
+                    health.0 += 1;
+                }
+            }
+        }).writes::<Health>());
+
+        let reader_log = log.clone();
+        schedule.add_system(Stage::Update, System::new("read", move |world, _| {
+            let changed = world.query_ref_filtered::<&Health, Changed<Health>>().count();
+            reader_log.lock().unwrap().push((changed, 0));
+        }).reads::<Health>());
+
+        for _ in 0..4 {
+            schedule.run(&mut world).unwrap();
+        }
+
+        // the insert and two heals, then nothing once health is full
+        assert_eq!(*log.lock().unwrap(), vec![(1, 0), (1, 0), (0, 0), (0, 0)]);
+        assert_eq!(world.get::<Health>(entity).map(|health| health.0), Some(3));
+    }
+}
@@ -0,0 +1,332 @@
+use std::any::{Any, TypeId};
+use std::collections::HashSet;
+
+use crate::ecs::component::Component;
+use crate::ecs::entity::{Entity, EntityAllocator};
+use crate::ecs::hierarchy::{Children, Parent};
+use crate::ecs::transform::{GlobalTransform, LocalTransform};
+use crate::ecs::world::World;
+
+/// The entities and registered components of a world at one tick, see `World::snapshot`
+pub struct WorldSnapshot {
+    entities: EntityAllocator,
+    tick: u64,
+    // the tick of the snapshot this one is a diff against
+    base: Option<u64>,
+    components: Vec<(RestoreFn, Box<dyn Any + Send + Sync>)>,
+}
+
+impl WorldSnapshot {
+    /// Change tick the snapshot was taken at
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Tick of the snapshot this diff has to be restored on top of, `None` for full snapshots
+    pub fn base(&self) -> Option<u64> {
+        self.base
+    }
+
+    pub fn is_diff(&self) -> bool {
+        self.base.is_some()
+    }
+}
+
+struct ComponentSnapshot<T> {
+    // every entity with the component, a diff only carries the values changed since its base
+    owners: Vec<Entity>,
+    values: Vec<(Entity, T)>,
+}
+
+type RestoreFn = fn(&mut World, &(dyn Any + Send + Sync));
+
+struct SnapshotComponent {
+    type_id: TypeId,
+    capture: fn(&World, Option<u64>) -> Box<dyn Any + Send + Sync>,
+    restore: RestoreFn,
+}
+
+/// Components captured by `World::snapshot`, lives on the world as a resource.
+/// Components of other types are left alone on restore.
+pub struct SnapshotRegistry {
+    // in registration order, so restoring runs hooks in the same order every time
+    components: Vec<SnapshotComponent>,
+}
+
+impl SnapshotRegistry {
+    pub fn new() -> Self {
+        Self { components: Vec::new() }
+    }
+
+    /// Registry with the hierarchy and the transforms
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register::<Parent>();
+        registry.register::<Children>();
+        registry.register::<LocalTransform>();
+        registry.register::<GlobalTransform>();
+        registry
+    }
+
+    pub fn register<T: Component + Clone>(&mut self) {
+        if !self.is_registered::<T>() {
+            self.components.push(SnapshotComponent {
+                type_id: TypeId::of::<T>(),
+                capture: capture_component::<T>,
+                restore: restore_component::<T>,
+            });
+        }
+    }
+
+    pub fn is_registered<T: Component>(&self) -> bool {
+        self.components.iter().any(|component| component.type_id == TypeId::of::<T>())
+    }
+}
+
+impl Default for SnapshotRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn capture_component<T: Component + Clone>(world: &World, base: Option<u64>) -> Box<dyn Any + Send + Sync> {
+    let mut owners = Vec::new();
+    let mut values = Vec::new();
+
+    for (entity, component) in world.query_ref::<&T>() {
+        owners.push(entity);
+
+        let changed = match base {
+            Some(base) => world.get_ticks::<T>(entity).is_some_and(|ticks| ticks.is_changed(base)),
+            None => true,
+        };
+
+        if changed {
+            values.push((entity, component.clone()));
+        }
+    }
+
+    Box::new(ComponentSnapshot { owners, values })
+}
+
+fn restore_component<T: Component + Clone>(world: &mut World, snapshot: &(dyn Any + Send + Sync)) {
+    let snapshot = snapshot.downcast_ref::<ComponentSnapshot<T>>().unwrap();
+    let owners: HashSet<Entity> = snapshot.owners.iter().cloned().collect();
+
+    let stale: Vec<Entity> = world.query_ref::<&T>()
+        .map(|(entity, _)| entity)
+        .filter(|entity| !owners.contains(entity))
+        .collect();
+
+    for entity in stale {
+        world.remove::<T>(entity);
+    }
+
+    for (entity, component) in &snapshot.values {
+        world.insert(*entity, component.clone());
+    }
+}
+
+impl World {
+    /// Opts `T` into `snapshot`, the registry starts out with `SnapshotRegistry::with_defaults`
+    pub fn register_snapshot_component<T: Component + Clone>(&mut self) {
+        self.get_resource_or_insert_with(SnapshotRegistry::with_defaults).register::<T>();
+    }
+
+    /// Clones the entity allocator and every registered component. Starts a new change tick so
+    /// `snapshot_diff` against this snapshot sees everything written afterwards.
+    pub fn snapshot(&mut self) -> WorldSnapshot {
+        self.capture(None)
+    }
+
+    /// Like `snapshot` but only clones the components changed since `base` was taken, it restores
+    /// correctly on top of the state `base` holds. Keep a full snapshot every few ticks and diffs in between.
+    pub fn snapshot_diff(&mut self, base: &WorldSnapshot) -> WorldSnapshot {
+        self.capture(Some(base.tick))
+    }
+
+    /// Puts the entities and registered components back the way they were. Restoring goes through
+    /// `despawn`, `insert` and `remove`, so hooks run and the restored components count as changed.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        self.restore_entities(&snapshot.entities);
+
+        for (restore, component) in &snapshot.components {
+            restore(self, component.as_ref());
+        }
+    }
+
+    fn capture(&mut self, base: Option<u64>) -> WorldSnapshot {
+        self.flush_entities();
+
+        let defaults;
+        let registry = match self.get_resource::<SnapshotRegistry>() {
+            Some(registry) => registry,
+            None => {
+                defaults = SnapshotRegistry::with_defaults();
+                &defaults
+            }
+        };
+
+        let snapshot = WorldSnapshot {
+            entities: self.entities().clone(),
+            tick: self.change_tick(),
+            base,
+            components: registry.components.iter()
+                .map(|component| (component.restore, (component.capture)(self, base)))
+                .collect(),
+        };
+
+        self.increment_change_tick();
+        snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{derive_component, derive_table_component};
+    use crate::ecs::{Schedule, Stage, System};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Health(u32);
+    #[derive(Clone, Debug, PartialEq)]
+    struct Name(&'static str);
+    #[derive(Clone, Debug, PartialEq)]
+    struct SpawnedBy(u32);
+    struct Left;
+    struct Right;
+    derive_component!(Name, SpawnedBy, Left, Right);
+    derive_table_component!(Health);
+
+    fn registered_world() -> World {
+        let mut world = World::new();
+        world.register_snapshot_component::<Health>();
+        world.register_snapshot_component::<Name>();
+        world.register_snapshot_component::<SpawnedBy>();
+        world
+    }
+
+    // live entities with their registered components, sorted by index
+    fn state(world: &World) -> Vec<(Entity, Option<Health>, Option<Name>)> {
+        let mut entities: Vec<Entity> = world.entities().iter().collect();
+        entities.sort_by_key(|entity| entity.index());
+
+        entities.into_iter()
+            .map(|entity| (entity, world.get::<Health>(entity).cloned(), world.get::<Name>(entity).cloned()))
+            .collect()
+    }
+
+    #[test]
+    fn full_snapshot_restores_entities_and_components() {
+        let mut world = registered_world();
+
+        let player = world.spawn();
+        world.insert(player, Health(10));
+        world.insert(player, Name("player"));
+
+        let enemy = world.spawn();
+        world.insert(enemy, Health(3));
+
+        let parent = world.spawn();
+        world.set_parent(enemy, parent);
+
+        let snapshot = world.snapshot();
+        let expected = state(&world);
+
+        world.get_mut::<Health>(player).unwrap().0 = 1;
+        world.remove::<Name>(player);
+        world.despawn(enemy);
+        let extra = world.spawn();
+        world.insert(extra, Health(99));
+
+        world.restore(&snapshot);
+
+        assert_eq!(state(&world), expected);
+        assert!(!world.is_alive(extra));
+        assert_eq!(world.get_parent(enemy), Some(parent));
+        assert_eq!(world.get_children(parent), [enemy]);
+    }
+
+    #[test]
+    fn diff_restores_on_top_of_its_base() {
+        let mut world = registered_world();
+
+        let first = world.spawn();
+        world.insert(first, Health(10));
+        let second = world.spawn();
+        world.insert(second, Health(20));
+        world.insert(second, Name("second"));
+
+        let base = world.snapshot();
+
+        world.get_mut::<Health>(first).unwrap().0 = 11;
+        world.remove::<Name>(second);
+        let third = world.spawn();
+        world.insert(third, Name("third"));
+
+        let diff = world.snapshot_diff(&base);
+        let expected = state(&world);
+        assert_eq!(diff.base(), Some(base.tick()));
+
+        world.despawn(first);
+        world.get_mut::<Health>(second).unwrap().0 = 0;
+
+        world.restore(&base);
+        world.restore(&diff);
+
+        assert_eq!(state(&world), expected);
+    }
+
+    // two systems in one batch, each spawning a few entities through commands
+    fn spawner_schedule() -> Schedule {
+        let mut schedule = Schedule::new();
+        schedule.set_deterministic(true);
+
+        schedule.add_system(Stage::Update, System::new("left", |_, commands| {
+            for _ in 0..4 {
+                let entity = commands.spawn();
+                commands.insert(entity, SpawnedBy(0));
+            }
+        }).writes::<Left>());
+
+        schedule.add_system(Stage::Update, System::new("right", |_, commands| {
+            for _ in 0..4 {
+                let entity = commands.spawn();
+                commands.insert(entity, SpawnedBy(1));
+            }
+        }).writes::<Right>());
+
+        schedule
+    }
+
+    fn spawned(world: &World) -> Vec<(Entity, u32)> {
+        let mut spawned: Vec<(Entity, u32)> = world.query_ref::<&SpawnedBy>()
+            .map(|(entity, spawned_by)| (entity, spawned_by.0))
+            .collect();
+
+        spawned.sort_by_key(|(entity, _)| entity.index());
+        spawned
+    }
+
+    #[test]
+    fn deterministic_runs_from_one_snapshot_spawn_the_same_ids() {
+        let mut world = registered_world();
+
+        // free some indices so the runs reuse them
+        let entities: Vec<Entity> = (0..6).map(|_| world.spawn()).collect();
+        world.despawn(entities[1]);
+        world.despawn(entities[4]);
+
+        let snapshot = world.snapshot();
+
+        spawner_schedule().run(&mut world).unwrap();
+        let first_run = spawned(&world);
+
+        world.restore(&snapshot);
+        assert!(spawned(&world).is_empty());
+
+        spawner_schedule().run(&mut world).unwrap();
+        assert_eq!(spawned(&world), first_run);
+        assert_eq!(first_run.len(), 8);
+    }
+}
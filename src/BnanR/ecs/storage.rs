@@ -1,47 +1,95 @@
 use std::any::Any;
+use std::cell::UnsafeCell;
 
+use crate::ecs::change_detection::ComponentTicks;
 use crate::ecs::component::Component;
 use crate::stl::sparse_set::SparseSet;
 
-pub trait AnyStorage: Any {
+/// `UnsafeCell` shared by the threads of a schedule. Writes through `get` are only made where the world is
+/// borrowed mutably or the declared access of a system makes them exclusive.
+pub struct DataCell<T: ?Sized>(UnsafeCell<T>);
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for DataCell<T> {}
+
+impl<T> DataCell<T> {
+    pub fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+impl<T: ?Sized> DataCell<T> {
+    pub fn get(&self) -> *mut T {
+        self.0.get()
+    }
+
+    /// # Safety
+    /// Nothing may write through `get` while the reference is alive.
+    pub unsafe fn get_ref(&self) -> &T {
+        unsafe { &*self.0.get() }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+}
+
+pub trait AnyStorage: Any + Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
-    fn remove(&mut self, index: usize);
+    /// Whether there was a component to remove
+    fn remove(&mut self, index: usize) -> bool;
+    fn contains(&self, index: usize) -> bool;
 }
 
 pub struct ComponentStorage<T: Component> {
     data: SparseSet<T>,
+    // inserted and removed together with `data` so both share the same dense order
+    ticks: SparseSet<ComponentTicks>,
 }
 
 impl<T: Component> ComponentStorage<T> {
     pub fn new() -> Self {
         Self {
             data: SparseSet::new(),
+            ticks: SparseSet::new(),
         }
     }
 
-    pub fn insert(&mut self, index: usize, component: T) -> Option<T> {
+    /// Replacing an existing component counts as a change, not an addition
+    pub fn insert(&mut self, index: usize, component: T, tick: u64) -> Option<T> {
+        match self.ticks.get_mut(index) {
+            Some(ticks) => ticks.changed = tick,
+            None => { self.ticks.insert(index, ComponentTicks::new(tick)); }
+        }
+
         self.data.insert(index, component)
     }
 
     pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.ticks.remove(index);
         self.data.remove(index)
     }
 
-    pub fn get(&self, index: usize) -> Option<&T> {
-        if self.data.contains(index) {
-            Some(&self.data[index])
-        } else {
-            None
+    pub fn get_ticks(&self, index: usize) -> Option<&ComponentTicks> {
+        self.ticks.get(index)
+    }
+
+    pub fn set_changed(&mut self, index: usize, tick: u64) {
+        if let Some(ticks) = self.ticks.get_mut(index) {
+            ticks.changed = tick;
         }
     }
 
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.data.get(index)
+    }
+
     pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
-        if self.data.contains(index) {
-            Some(&mut self.data[index])
-        } else {
-            None
-        }
+        self.data.get_mut(index)
     }
 
     pub fn contains(&self, index: usize) -> bool {
@@ -51,6 +99,51 @@ impl<T: Component> ComponentStorage<T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.data.iter()
     }
+
+    /// Entity indices paired with their components, in dense order
+    pub fn iter_with_index(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.data.iter_with_index()
+    }
+
+    /// Reorders the components, and their ticks with them, e.g. to draw sorted by depth
+    pub fn sort_by(&mut self, compare: impl FnMut(&T, &T) -> std::cmp::Ordering) {
+        self.data.sort_by(compare);
+        self.ticks.sort_as(&self.data);
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Entity indices in dense order
+    pub fn indices(&self) -> &[usize] {
+        self.data.indices()
+    }
+
+    pub fn dense_index(&self, index: usize) -> Option<usize> {
+        self.data.dense_index(index)
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.data.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.data.as_mut_ptr()
+    }
+
+    pub fn ticks_as_ptr(&self) -> *const ComponentTicks {
+        self.ticks.as_ptr()
+    }
+
+    /// Ticks in the same dense order as `as_mut_ptr`
+    pub fn ticks_as_mut_ptr(&mut self) -> *mut ComponentTicks {
+        self.ticks.as_mut_ptr()
+    }
 }
 
 impl<T: Component> Default for ComponentStorage<T> {
@@ -68,7 +161,11 @@ impl<T: Component + 'static> AnyStorage for ComponentStorage<T> {
         self
     }
 
-    fn remove(&mut self, index: usize) {
-        self.data.remove(index);
+    fn remove(&mut self, index: usize) -> bool {
+        ComponentStorage::remove(self, index).is_some()
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        ComponentStorage::contains(self, index)
     }
 }
@@ -0,0 +1,290 @@
+use std::any::{type_name, TypeId};
+
+use crate::ecs::change_detection::{Mut, QueryTicks};
+use crate::ecs::command::{CommandQueue, Commands};
+use crate::ecs::component::Component;
+use crate::ecs::entity::{Entity, EntityAllocator};
+use crate::ecs::event::{EventReader, Events};
+use crate::ecs::query::{self, ComponentAccess, QueryData, QueryFilter, QueryIter, ReadOnlyQueryData};
+use crate::ecs::resource::Resource;
+use crate::ecs::world::{UnsafeWorldCell, World};
+
+/// Commands are applied once the batch the system ran in has finished
+pub type SystemFn = Box<dyn FnMut(&mut SystemWorld, &mut Commands) + Send>;
+pub type ExclusiveSystemFn = Box<dyn FnMut(&mut World) + Send>;
+
+pub enum SystemRun {
+    Parallel(SystemFn),
+    Exclusive(ExclusiveSystemFn),
+}
+
+/// A function run by a `Schedule`. Parallel systems only touch the components they declare and may run
+/// alongside other systems, exclusive systems get the whole `World` and run alone.
+pub struct System {
+    pub name: String,
+    pub access: ComponentAccess,
+    pub after: Vec<String>,
+    pub run: SystemRun,
+    /// World change tick of the last run, `Added`, `Changed` and `removed` report what happened after it
+    pub last_run: u64,
+    pub commands: CommandQueue,
+}
+
+impl System {
+    pub fn new(name: &str, run: impl FnMut(&mut SystemWorld, &mut Commands) + Send + 'static) -> Self {
+        Self {
+            name: name.to_string(),
+            access: ComponentAccess::default(),
+            after: Vec::new(),
+            run: SystemRun::Parallel(Box::new(run)),
+            last_run: 0,
+            commands: CommandQueue::new(),
+        }
+    }
+
+    /// A system that may spawn, despawn and touch any component
+    pub fn exclusive(name: &str, run: impl FnMut(&mut World) + Send + 'static) -> Self {
+        Self {
+            name: name.to_string(),
+            access: ComponentAccess::default(),
+            after: Vec::new(),
+            run: SystemRun::Exclusive(Box::new(run)),
+            last_run: 0,
+            commands: CommandQueue::new(),
+        }
+    }
+
+    pub fn reads<T: Component>(mut self) -> Self {
+        self.access.add_read::<T>();
+        self
+    }
+
+    pub fn writes<T: Component>(mut self) -> Self {
+        self.access.add_write::<T>();
+        self
+    }
+
+    /// Declares what `query_filtered::<Q, F>` touches, including the components whose ticks `F` reads
+    pub fn queries<Q: QueryData, F: QueryFilter>(mut self) -> Self {
+        Q::access(&mut self.access);
+        F::access(&mut self.access);
+        self
+    }
+
+    pub fn reads_resource<R: Resource>(mut self) -> Self {
+        self.access.add_resource_read::<R>();
+        self
+    }
+
+    pub fn writes_resource<R: Resource>(mut self) -> Self {
+        self.access.add_resource_write::<R>();
+        self
+    }
+
+    /// Readers of the same event channel run in parallel
+    pub fn reads_events<E: Resource>(self) -> Self {
+        self.reads_resource::<Events<E>>()
+    }
+
+    /// Senders of an event channel run one after another, use `Commands::send_event` to avoid that
+    pub fn sends_events<E: Resource>(self) -> Self {
+        self.writes_resource::<Events<E>>()
+    }
+
+    /// Runs after the named system of the same stage even if they do not conflict
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(name.to_string());
+        self
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        matches!(self.run, SystemRun::Exclusive(_))
+    }
+
+    /// Whether the two systems may not run at the same time
+    pub fn conflicts_with(&self, other: &System) -> bool {
+        self.is_exclusive() || other.is_exclusive() || self.access.conflicts_with(&other.access)
+    }
+}
+
+/// The view of the `World` a parallel system gets. Every query is checked against the declared access.
+pub struct SystemWorld<'w> {
+    world: UnsafeWorldCell<'w>,
+    name: &'w str,
+    access: &'w ComponentAccess,
+    ticks: QueryTicks,
+}
+
+impl<'w> SystemWorld<'w> {
+    /// Every system sharing `world` must have non conflicting access
+    pub(crate) unsafe fn new(world: UnsafeWorldCell<'w>, name: &'w str, access: &'w ComponentAccess, ticks: QueryTicks) -> Self {
+        Self { world, name, access, ticks }
+    }
+
+    pub fn query<Q: QueryData>(&mut self) -> QueryIter<'_, Q> {
+        self.check_access::<Q, ()>();
+        unsafe { QueryIter::new(self.world, self.ticks) }
+    }
+
+    pub fn query_filtered<Q: QueryData, F: QueryFilter>(&mut self) -> QueryIter<'_, Q, F> {
+        self.check_access::<Q, F>();
+        unsafe { QueryIter::new(self.world, self.ticks) }
+    }
+
+    /// Read-only queries can be held alongside each other
+    pub fn query_ref<Q: ReadOnlyQueryData>(&self) -> QueryIter<'_, Q> {
+        self.check_access::<Q, ()>();
+        unsafe { QueryIter::new(self.world, self.ticks) }
+    }
+
+    pub fn query_ref_filtered<Q: ReadOnlyQueryData, F: QueryFilter>(&self) -> QueryIter<'_, Q, F> {
+        self.check_access::<Q, F>();
+        unsafe { QueryIter::new(self.world, self.ticks) }
+    }
+
+    pub fn query_one<Q: QueryData>(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        self.check_access::<Q, ()>();
+
+        match self.entities().is_alive(entity) {
+            true => unsafe { query::fetch_one::<Q>(self.world, self.ticks, entity) },
+            false => None,
+        }
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.check_access::<&T, ()>();
+
+        match self.entities().is_alive(entity) {
+            true => unsafe { query::fetch_one::<&T>(self.world, self.ticks, entity) },
+            false => None,
+        }
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<Mut<'_, T>> {
+        self.query_one::<&mut T>(entity)
+    }
+
+    /// Entities that lost their `T` since this system last ran
+    pub fn removed<T: Component>(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.check_access::<&T, ()>();
+        unsafe { self.world.removed_since::<T>(self.ticks.last_run) }
+    }
+
+    pub fn get_ticks(&self) -> QueryTicks {
+        self.ticks
+    }
+
+    pub fn resource<R: Resource>(&self) -> Option<&R> {
+        self.check_resource_access::<R>(false);
+        unsafe { self.world.get_resource::<R>() }
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.check_resource_access::<R>(true);
+        unsafe { self.world.get_resource_mut::<R>() }
+    }
+
+    /// Panics if `E` was never added with `World::add_event`
+    pub fn send_event<E: Resource>(&mut self, event: E) {
+        match self.resource_mut::<Events<E>>() {
+            Some(events) => events.send(event),
+            None => panic!("system {} sends {} which was never added to the world", self.name, type_name::<E>()),
+        }
+    }
+
+    /// Events sent since `reader` last read, nothing if `E` was never added
+    pub fn read_events<'a, E: Resource>(&'a self, reader: &mut EventReader<E>) -> impl Iterator<Item = &'a E> + use<'a, E> {
+        self.resource::<Events<E>>()
+            .map(|events| reader.read(events))
+            .into_iter()
+            .flatten()
+    }
+
+    pub fn entities(&self) -> &EntityAllocator {
+        unsafe { self.world.entities() }
+    }
+
+    /// `Added<T>` and `Changed<T>` read the ticks `Mut<T>` writes, so they need `T` declared like `&T` does
+    fn check_access<Q: QueryData, F: QueryFilter>(&self) {
+        let mut requested = ComponentAccess::default();
+        Q::access(&mut requested);
+        F::access(&mut requested);
+
+        let declared = |list: &[(TypeId, &'static str)], type_id: &TypeId| list.iter().any(|(other, _)| other == type_id);
+
+        for (type_id, name) in &requested.reads {
+            if !declared(&self.access.reads, type_id) && !declared(&self.access.writes, type_id) {
+                panic!("system {} reads {} without declaring it", self.name, name);
+            }
+        }
+
+        for (type_id, name) in &requested.writes {
+            if !declared(&self.access.writes, type_id) {
+                panic!("system {} writes {} without declaring it", self.name, name);
+            }
+        }
+    }
+
+    fn check_resource_access<R: Resource>(&self, write: bool) {
+        let type_id = TypeId::of::<R>();
+        let declared = |list: &[(TypeId, &'static str)]| list.iter().any(|(other, _)| *other == type_id);
+
+        if write && !declared(&self.access.resource_writes) {
+            panic!("system {} writes resource {} without declaring it", self.name, type_name::<R>());
+        }
+
+        if !declared(&self.access.resource_reads) && !declared(&self.access.resource_writes) {
+            panic!("system {} reads resource {} without declaring it", self.name, type_name::<R>());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::derive_component;
+    use crate::ecs::{Changed, Schedule, Stage, System, With, World};
+
+    struct Position;
+    struct Velocity(f32);
+    derive_component!(Position, Velocity);
+
+    #[test]
+    #[should_panic(expected = "reads")]
+    fn filter_reads_must_be_declared() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Position);
+        world.insert(entity, Velocity(1.0));
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, System::new("sneaky", |world, _| {
+            let _ = world.query_ref_filtered::<&Position, Changed<Velocity>>().count();
+        }).reads::<Position>());
+
+        schedule.run(&mut world).unwrap();
+    }
+
+    #[test]
+    fn filter_reads_conflict_with_writers() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, System::new("accelerate", |world, _| {
+            for (_, mut velocity) in world.query::<&mut Velocity>() {
+                velocity.0 += 1.0;
+            }
+        }).writes::<Velocity>());
+
+        schedule.add_system(Stage::Update, System::new("moved", |world, _| {
+            let _ = world.query_ref_filtered::<&Position, Changed<Velocity>>().count();
+        }).queries::<&Position, Changed<Velocity>>());
+
+        // presence filters do not touch the ticks and stay parallel
+        schedule.add_system(Stage::Update, System::new("present", |world, _| {
+            let _ = world.query_ref_filtered::<&Position, With<Velocity>>().count();
+        }).queries::<&Position, With<Velocity>>());
+
+        assert_eq!(schedule.get_batches(Stage::Update).unwrap(), vec![
+            vec!["accelerate".to_string(), "present".to_string()],
+            vec!["moved".to_string()],
+        ]);
+    }
+}
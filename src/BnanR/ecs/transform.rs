@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+
+use cgmath::*;
+use serde::*;
+
+use crate::derive_table_component;
+use crate::ecs::change_detection::Changed;
+use crate::ecs::entity::Entity;
+use crate::ecs::hierarchy::Parent;
+use crate::ecs::query::Without;
+use crate::ecs::system::System;
+use crate::ecs::world::World;
+
+/// Transform relative to the parent, or to the world for roots
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LocalTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl LocalTransform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self { translation, ..Self::identity() }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vector3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation) *
+            Matrix4::from(self.rotation) *
+            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for LocalTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Model matrix of the entity, written by `propagate_transforms`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GlobalTransform(pub Matrix4<f32>);
+
+impl GlobalTransform {
+    pub fn translation(&self) -> Vector3<f32> {
+        self.0.w.truncate()
+    }
+}
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Matrix4::identity())
+    }
+}
+
+derive_table_component!(LocalTransform, GlobalTransform);
+
+/// Recomputes `GlobalTransform` below every node whose `LocalTransform` or `Parent` changed since the last call,
+/// that has no `GlobalTransform` yet or that lost its parent. Untouched subtrees are not visited.
+/// Entities without a `LocalTransform` end propagation.
+pub fn propagate_transforms(world: &mut World) {
+    let mut dirty: HashSet<Entity> = world.removed::<Parent>().collect();
+    dirty.extend(world.query_ref_filtered::<&LocalTransform, Changed<LocalTransform>>().map(|(entity, _)| entity));
+    dirty.extend(world.query_ref_filtered::<&LocalTransform, Changed<Parent>>().map(|(entity, _)| entity));
+    dirty.extend(world.query_ref_filtered::<&LocalTransform, Without<GlobalTransform>>().map(|(entity, _)| entity));
+    dirty.retain(|&entity| world.has::<LocalTransform>(entity));
+
+    // only the topmost dirty node of a subtree starts a walk, everything below it is rewritten anyway
+    let mut stack: Vec<(Entity, Matrix4<f32>)> = dirty.iter()
+        .filter_map(|&entity| get_parent_matrix(world, entity, &dirty).map(|matrix| (entity, matrix)))
+        .collect();
+
+    while let Some((entity, parent_matrix)) = stack.pop() {
+        let Some(local) = world.get::<LocalTransform>(entity).copied() else {
+            continue;
+        };
+
+        let matrix = parent_matrix * local.to_matrix();
+        world.insert(entity, GlobalTransform(matrix));
+
+        stack.extend(world.get_children(entity).iter().map(|&child| (child, matrix)));
+    }
+}
+
+/// Global matrix the entity's own transform applies to, `None` if a dirty ancestor rewrites the entity
+/// or an ancestor without a `LocalTransform` cuts it off from its root
+fn get_parent_matrix(world: &World, entity: Entity, dirty: &HashSet<Entity>) -> Option<Matrix4<f32>> {
+    let mut ancestor = world.get_parent(entity);
+
+    while let Some(current) = ancestor {
+        if dirty.contains(&current) || !world.has::<LocalTransform>(current) {
+            return None;
+        }
+
+        ancestor = world.get_parent(current);
+    }
+
+    match world.get_parent(entity) {
+        Some(parent) => world.get::<GlobalTransform>(parent).map(|global| global.0),
+        None => Some(Matrix4::identity()),
+    }
+}
+
+/// `propagate_transforms` as an exclusive system, add it after the systems that move entities
+pub fn transform_propagation_system() -> System {
+    System::exclusive("transform_propagation", propagate_transforms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_at(world: &mut World, x: f32) -> Entity {
+        let entity = world.spawn();
+        world.insert(entity, LocalTransform::from_translation(Vector3::new(x, 0.0, 0.0)));
+        entity
+    }
+
+    fn global_x(world: &World, entity: Entity) -> Option<f32> {
+        world.get::<GlobalTransform>(entity).map(|global| global.translation().x)
+    }
+
+    fn rewritten(world: &World, entity: Entity) -> bool {
+        world.get_ticks::<GlobalTransform>(entity).is_some_and(|ticks| ticks.is_changed(world.last_change_tick()))
+    }
+
+    #[test]
+    fn only_changed_subtrees_are_rewritten() {
+        let mut world = World::new();
+        let (a, b, c, d) = (spawn_at(&mut world, 1.0), spawn_at(&mut world, 2.0), spawn_at(&mut world, 10.0), spawn_at(&mut world, 20.0));
+        world.set_parent(b, a);
+        world.set_parent(d, c);
+
+        propagate_transforms(&mut world);
+        assert_eq!([a, b, c, d].map(|entity| global_x(&world, entity)), [Some(1.0), Some(3.0), Some(10.0), Some(30.0)]);
+
+        world.clear_trackers();
+        world.get_mut::<LocalTransform>(c).unwrap().translation.x = 100.0;
+        propagate_transforms(&mut world);
+
+        assert_eq!(global_x(&world, d), Some(120.0));
+        assert_eq!([a, b, c, d].map(|entity| rewritten(&world, entity)), [false, false, true, true]);
+
+        world.clear_trackers();
+        propagate_transforms(&mut world);
+        assert!([a, b, c, d].iter().all(|&entity| !rewritten(&world, entity)));
+    }
+
+    #[test]
+    fn reparenting_moves_the_subtree() {
+        let mut world = World::new();
+        let (a, b, c) = (spawn_at(&mut world, 1.0), spawn_at(&mut world, 2.0), spawn_at(&mut world, 4.0));
+        world.set_parent(b, a);
+        world.set_parent(c, b);
+        propagate_transforms(&mut world);
+
+        world.clear_trackers();
+        world.remove_parent(b);
+        propagate_transforms(&mut world);
+        assert_eq!([a, b, c].map(|entity| global_x(&world, entity)), [Some(1.0), Some(2.0), Some(6.0)]);
+        assert!(!rewritten(&world, a));
+
+        world.clear_trackers();
+        world.set_parent(a, c);
+        propagate_transforms(&mut world);
+        assert_eq!([a, b, c].map(|entity| global_x(&world, entity)), [Some(7.0), Some(2.0), Some(6.0)]);
+        assert_eq!([a, b, c].map(|entity| rewritten(&world, entity)), [true, false, false]);
+    }
+
+    #[test]
+    fn missing_local_transform_ends_propagation() {
+        let mut world = World::new();
+        let (a, c) = (spawn_at(&mut world, 1.0), spawn_at(&mut world, 4.0));
+        let b = world.spawn();
+        world.set_parent(b, a);
+        world.set_parent(c, b);
+        propagate_transforms(&mut world);
+
+        assert_eq!([a, b, c].map(|entity| global_x(&world, entity)), [Some(1.0), None, None]);
+    }
+}
@@ -1,13 +1,38 @@
-use std::any::TypeId;
+use std::any::{type_name, Any, TypeId};
+use std::cell::UnsafeCell;
 use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
 
-use crate::ecs::component::Component;
+use crate::ecs::archetype::{ArchetypeId, Archetypes, EntityLocation, EMPTY_ARCHETYPE};
+use crate::ecs::change_detection::{ComponentTicks, QueryTicks};
+use crate::ecs::component::{Component, StorageType};
 use crate::ecs::entity::{Entity, EntityAllocator};
-use crate::ecs::storage::{AnyStorage, ComponentStorage};
+use crate::ecs::event::Events;
+use crate::ecs::observer::{ComponentHooks, Lifecycle, ObserverId, Observers};
+use crate::ecs::query::{self, QueryData, QueryFilter, QueryIter, ReadOnlyQueryData};
+use crate::ecs::resource::Resource;
+use crate::ecs::storage::{AnyStorage, ComponentStorage, DataCell};
 
+/// Storages and resources sit in `DataCell`s so the systems of a batch reach them through an
+/// `UnsafeWorldCell` without ever borrowing the whole world
 pub struct World {
     entities: EntityAllocator,
-    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    storages: HashMap<TypeId, Box<DataCell<dyn AnyStorage>>>,
+    archetypes: Archetypes,
+    // archetype and row of every entity index, stale for dead ones
+    locations: Vec<EntityLocation>,
+    // entities that lost a component and the tick they lost it at, per component type
+    removed: HashMap<TypeId, Vec<(Entity, u64)>>,
+    // every value is a `DataCell<R>`
+    resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    // swaps the buffers of every `Events<E>` added through `add_event`
+    event_updaters: HashMap<TypeId, fn(&mut World)>,
+    observers: Observers,
+    // stamped on every insert, removal and mutable access
+    change_tick: u64,
+    // what `Added`, `Changed` and `removed` on the world itself compare against
+    last_change_tick: u64,
 }
 
 impl World {
@@ -15,11 +40,23 @@ impl World {
         Self {
             entities: EntityAllocator::new(),
             storages: HashMap::new(),
+            archetypes: Archetypes::new(),
+            locations: Vec::new(),
+            removed: HashMap::new(),
+            resources: HashMap::new(),
+            event_updaters: HashMap::new(),
+            observers: Observers::new(),
+            change_tick: 1,
+            last_change_tick: 0,
         }
     }
 
     pub fn spawn(&mut self) -> Entity {
-        self.entities.allocate()
+        self.flush_entities();
+
+        let entity = self.entities.allocate();
+        self.place_entity(entity);
+        entity
     }
 
     pub fn despawn(&mut self, entity: Entity) -> bool {
@@ -27,9 +64,35 @@ impl World {
             return false;
         }
 
-        let index = entity.index();
-        for storage in self.storages.values_mut() {
-            storage.remove(index);
+        self.detach_hierarchy(entity);
+
+        if !self.observers.is_empty() {
+            // a hook may remove other components, which then already ran their own `Remove`
+            for type_id in self.get_component_types(entity) {
+                if self.contains_type_id(entity, type_id) {
+                    self.trigger(type_id, Lifecycle::Remove, entity);
+                }
+            }
+
+            self.observers.remove_entity(entity);
+        }
+
+        let tick = self.change_tick;
+        for (type_id, storage) in self.storages.iter_mut() {
+            if storage.get_mut().remove(entity.index()) {
+                self.removed.entry(*type_id).or_default().push((entity, tick));
+            }
+        }
+
+        let location = self.get_location(entity);
+        let archetype = self.archetypes.get_mut(location.archetype);
+
+        for type_id in archetype.get_components() {
+            self.removed.entry(*type_id).or_default().push((entity, tick));
+        }
+
+        if let Some(moved) = archetype.swap_remove(location.row) {
+            self.locations[moved.index()].row = location.row;
         }
 
         self.entities.deallocate(entity)
@@ -44,8 +107,19 @@ impl World {
             return None;
         }
 
-        let storage = self.get_or_create_storage::<T>();
-        storage.insert(entity.index(), component)
+        let tick = self.change_tick;
+
+        let previous = match T::STORAGE {
+            StorageType::SparseSet => self.get_or_create_storage::<T>().insert(entity.index(), component, tick),
+            StorageType::Table => self.insert_table(entity, component, tick),
+        };
+
+        if previous.is_none() {
+            self.trigger(TypeId::of::<T>(), Lifecycle::Add, entity);
+        }
+
+        self.trigger(TypeId::of::<T>(), Lifecycle::Insert, entity);
+        previous
     }
 
     pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
@@ -53,8 +127,20 @@ impl World {
             return None;
         }
 
-        self.get_storage_mut::<T>()
-            .and_then(|storage| storage.remove(entity.index()))
+        if !self.observers.is_empty() && self.has::<T>(entity) {
+            self.trigger(TypeId::of::<T>(), Lifecycle::Remove, entity);
+        }
+
+        let component = match T::STORAGE {
+            StorageType::SparseSet => self.get_storage_mut::<T>().and_then(|storage| storage.remove(entity.index())),
+            StorageType::Table => self.remove_table::<T>(entity),
+        };
+
+        if component.is_some() {
+            self.removed.entry(TypeId::of::<T>()).or_default().push((entity, self.change_tick));
+        }
+
+        component
     }
 
     pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
@@ -62,17 +148,50 @@ impl World {
             return None;
         }
 
-        self.get_storage::<T>()
-            .and_then(|storage| storage.get(entity.index()))
+        match T::STORAGE {
+            StorageType::SparseSet => self.get_storage::<T>().and_then(|storage| storage.get(entity.index())),
+            StorageType::Table => {
+                let location = self.get_location(entity);
+                self.archetypes.get(location.archetype).get_column::<T>().and_then(|column| column.get(location.row))
+            }
+        }
     }
 
+    /// Marks the component changed, use `query_one::<&mut T>` to only mark it when written
     pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
         if !self.entities.is_alive(entity) {
             return None;
         }
 
-        self.get_storage_mut::<T>()
-            .and_then(|storage| storage.get_mut(entity.index()))
+        let tick = self.change_tick;
+
+        match T::STORAGE {
+            StorageType::SparseSet => self.get_storage_mut::<T>().and_then(|storage| {
+                storage.set_changed(entity.index(), tick);
+                storage.get_mut(entity.index())
+            }),
+            StorageType::Table => {
+                let location = self.get_location(entity);
+                let column = self.archetypes.get_mut(location.archetype).get_column_mut::<T>()?;
+
+                column.get_ticks_mut(location.row)?.changed = tick;
+                column.get_mut(location.row)
+            }
+        }
+    }
+
+    pub fn get_ticks<T: Component>(&self, entity: Entity) -> Option<ComponentTicks> {
+        if !self.entities.is_alive(entity) {
+            return None;
+        }
+
+        match T::STORAGE {
+            StorageType::SparseSet => self.get_storage::<T>().and_then(|storage| storage.get_ticks(entity.index()).copied()),
+            StorageType::Table => {
+                let location = self.get_location(entity);
+                self.archetypes.get(location.archetype).get_column::<T>().and_then(|column| column.get_ticks(location.row).copied())
+            }
+        }
     }
 
     pub fn has<T: Component>(&self, entity: Entity) -> bool {
@@ -80,37 +199,427 @@ impl World {
             return false;
         }
 
-        self.get_storage::<T>()
-            .map(|storage| storage.contains(entity.index()))
-            .unwrap_or(false)
+        match T::STORAGE {
+            StorageType::SparseSet => self.get_storage::<T>().is_some_and(|storage| storage.contains(entity.index())),
+            StorageType::Table => self.archetypes.get(self.get_location(entity).archetype).contains(TypeId::of::<T>()),
+        }
     }
 
-    fn get_or_create_storage<T: Component>(&mut self) -> &mut ComponentStorage<T> {
-        let type_id = TypeId::of::<T>();
+    /// Iterates every entity with the components in `Q`, e.g. `world.query::<(&A, &mut B, Option<&C>)>()`.
+    /// Iteration walks the matching archetypes, or the smallest sparse set among the required components.
+    pub fn query<Q: QueryData>(&mut self) -> QueryIter<'_, Q> {
+        let ticks = self.get_query_ticks();
+        unsafe { QueryIter::new(self.as_unsafe_world_cell(), ticks) }
+    }
+
+    /// Like `query` but only entities matching `F`, e.g. `world.query_filtered::<&mut A, (With<B>, Without<C>)>()`
+    pub fn query_filtered<Q: QueryData, F: QueryFilter>(&mut self) -> QueryIter<'_, Q, F> {
+        let ticks = self.get_query_ticks();
+        unsafe { QueryIter::new(self.as_unsafe_world_cell(), ticks) }
+    }
 
-        if !self.storages.contains_key(&type_id) {
-            self.storages
-                .insert(type_id, Box::new(ComponentStorage::<T>::new()));
+    /// Read-only query on a shared world
+    pub fn query_ref<Q: ReadOnlyQueryData>(&self) -> QueryIter<'_, Q> {
+        unsafe { QueryIter::new(self.as_unsafe_world_cell_readonly(), self.get_query_ticks()) }
+    }
+
+    pub fn query_ref_filtered<Q: ReadOnlyQueryData, F: QueryFilter>(&self) -> QueryIter<'_, Q, F> {
+        unsafe { QueryIter::new(self.as_unsafe_world_cell_readonly(), self.get_query_ticks()) }
+    }
+
+    /// Fetches `Q` for a single entity, `None` if it is dead or lacks a required component
+    pub fn query_one<Q: QueryData>(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        if !self.entities.is_alive(entity) {
+            return None;
         }
 
-        self.storages
-            .get_mut(&type_id)
+        let ticks = self.get_query_ticks();
+        unsafe { query::fetch_one::<Q>(self.as_unsafe_world_cell(), ticks, entity) }
+    }
+
+    /// Entities that lost their `T` since the last `clear_trackers`
+    pub fn removed<T: Component>(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.removed_since::<T>(self.last_change_tick)
+    }
+
+    /// Entities that lost their `T` after `since`, oldest first
+    pub fn removed_since<T: Component>(&self, since: u64) -> impl Iterator<Item = Entity> + '_ {
+        self.removed.get(&TypeId::of::<T>())
+            .into_iter()
+            .flat_map(move |removed| removed.iter().filter(move |(_, tick)| *tick > since).map(|(entity, _)| *entity))
+    }
+
+    /// Every component type the entity has, sparse and table
+    pub fn get_component_types(&self, entity: Entity) -> Vec<TypeId> {
+        if !self.entities.is_alive(entity) {
+            return Vec::new();
+        }
+
+        // a shared world has no `UnsafeWorldCell` writing through the cells
+        let mut types: Vec<TypeId> = self.storages.iter()
+            .filter(|(_, storage)| unsafe { storage.get_ref() }.contains(entity.index()))
+            .map(|(type_id, _)| *type_id)
+            .collect();
+
+        types.extend(self.archetypes.get(self.get_location(entity).archetype).get_components());
+        types
+    }
+
+    fn contains_type_id(&self, entity: Entity, type_id: TypeId) -> bool {
+        let in_storage = self.storages.get(&type_id)
+            .is_some_and(|storage| unsafe { storage.get_ref() }.contains(entity.index()));
+
+        in_storage || self.archetypes.get(self.get_location(entity).archetype).contains(type_id)
+    }
+
+    pub fn entities(&self) -> &EntityAllocator {
+        &self.entities
+    }
+
+    pub fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
+    /// Where the components of a live entity are stored
+    pub fn get_location(&self, entity: Entity) -> EntityLocation {
+        self.locations[entity.index()]
+    }
+
+    /// Makes entities reserved through `Commands::spawn` alive
+    pub fn flush_entities(&mut self) {
+        let first = self.entities.len();
+        self.entities.flush();
+
+        for index in first..self.entities.len() {
+            let entity = self.entities.get(index).unwrap();
+            self.place_entity(entity);
+        }
+    }
+
+    /// Switches to the entities of `entities`: live entities it does not have are despawned,
+    /// the ones it adds start out without components
+    pub(crate) fn restore_entities(&mut self, entities: &EntityAllocator) {
+        self.flush_entities();
+
+        let despawned: Vec<Entity> = self.entities.iter().filter(|&entity| !entities.is_alive(entity)).collect();
+        for entity in despawned {
+            self.despawn(entity);
+        }
+
+        let previous = std::mem::replace(&mut self.entities, entities.clone());
+
+        let spawned: Vec<Entity> = self.entities.iter().filter(|&entity| !previous.is_alive(entity)).collect();
+        for entity in spawned {
+            self.place_entity(entity);
+        }
+    }
+
+    // --- Table Storage ---
+
+    fn place_entity(&mut self, entity: Entity) {
+        let row = self.archetypes.get_mut(EMPTY_ARCHETYPE).push_entity(entity);
+        let location = EntityLocation { archetype: EMPTY_ARCHETYPE, row };
+
+        // indices past the end only show up when restoring entities, the skipped ones are dead
+        if entity.index() >= self.locations.len() {
+            self.locations.resize(entity.index() + 1, location);
+        }
+
+        self.locations[entity.index()] = location;
+    }
+
+    fn move_entity(&mut self, entity: Entity, target: ArchetypeId, taken: Option<TypeId>) {
+        let location = self.get_location(entity);
+        let (row, moved) = self.archetypes.move_entity(location.archetype, location.row, target, taken);
+
+        if let Some(moved) = moved {
+            self.locations[moved.index()].row = location.row;
+        }
+
+        self.locations[entity.index()] = EntityLocation { archetype: target, row };
+    }
+
+    fn insert_table<T: Component>(&mut self, entity: Entity, component: T, tick: u64) -> Option<T> {
+        let location = self.get_location(entity);
+
+        if let Some(column) = self.archetypes.get_mut(location.archetype).get_column_mut::<T>() {
+            column.get_ticks_mut(location.row)?.changed = tick;
+            return column.get_mut(location.row).map(|previous| std::mem::replace(previous, component));
+        }
+
+        let target = self.archetypes.get_with::<T>(location.archetype);
+        self.move_entity(entity, target, None);
+
+        self.archetypes.get_mut(target).get_column_mut::<T>()?.push(component, ComponentTicks::new(tick));
+        None
+    }
+
+    fn remove_table<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let location = self.get_location(entity);
+        let component = self.archetypes.get_mut(location.archetype).get_column_mut::<T>()?.swap_remove_value(location.row);
+
+        let target = self.archetypes.get_without::<T>(location.archetype);
+        self.move_entity(entity, target, Some(TypeId::of::<T>()));
+        Some(component)
+    }
+
+    // --- Resources ---
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(TypeId::of::<R>(), Box::new(DataCell::new(resource)))
+            .and_then(|previous| previous.downcast::<DataCell<R>>().ok())
+            .map(|previous| previous.into_inner())
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast::<DataCell<R>>().ok())
+            .map(|resource| resource.into_inner())
+    }
+
+    pub fn get_resource<R: Resource>(&self) -> Option<&R> {
+        self.resources.get(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast_ref::<DataCell<R>>())
+            .map(|resource| unsafe { resource.get_ref() })
+    }
+
+    pub fn get_resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast_mut::<DataCell<R>>())
+            .map(DataCell::get_mut)
+    }
+
+    pub fn get_resource_or_insert_with<R: Resource>(&mut self, create: impl FnOnce() -> R) -> &mut R {
+        self.resources.entry(TypeId::of::<R>())
+            .or_insert_with(|| Box::new(DataCell::new(create())))
+            .downcast_mut::<DataCell<R>>()
             .unwrap()
+            .get_mut()
+    }
+
+    pub fn has_resource<R: Resource>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    // --- Events ---
+
+    /// Adds the `Events<E>` resource and has `update_events` swap its buffers
+    pub fn add_event<E: Resource>(&mut self) {
+        self.get_resource_or_insert_with(Events::<E>::new);
+        self.event_updaters.insert(TypeId::of::<E>(), |world| {
+            if let Some(events) = world.get_resource_mut::<Events<E>>() {
+                events.update();
+            }
+        });
+    }
+
+    pub fn send_event<E: Resource>(&mut self, event: E) {
+        if !self.event_updaters.contains_key(&TypeId::of::<E>()) {
+            self.add_event::<E>();
+        }
+
+        self.get_resource_mut::<Events<E>>()
+            .unwrap_or_else(|| panic!("Events<{}> was removed from the world", type_name::<E>()))
+            .send(event);
+    }
+
+    /// Ends the frame for every event channel, `Schedule::run` calls this before its stages
+    pub fn update_events(&mut self) {
+        let updaters: Vec<fn(&mut World)> = self.event_updaters.values().cloned().collect();
+
+        for update in updaters {
+            update(self);
+        }
+    }
+
+    // --- Hooks and Observers ---
+
+    /// Hooks run with the world before any observer and may change it, except that `on_remove`
+    /// must not despawn the entity it is called for, queue that through a `CommandQueue` instead
+    pub fn get_component_hooks_mut<T: Component>(&mut self) -> &mut ComponentHooks {
+        self.observers.get_hooks_mut(TypeId::of::<T>())
+    }
+
+    /// Runs `observer` whenever any entity goes through `event` for `T`
+    pub fn add_observer<T: Component>(&mut self, event: Lifecycle, observer: impl Fn(&mut World, Entity) + Send + Sync + 'static) -> ObserverId {
+        self.observers.add(TypeId::of::<T>(), event, None, Arc::new(observer))
+    }
+
+    /// Runs `observer` when `entity` goes through `event` for `T`, dropped when the entity is despawned
+    pub fn add_entity_observer<T: Component>(&mut self, entity: Entity, event: Lifecycle, observer: impl Fn(&mut World, Entity) + Send + Sync + 'static) -> ObserverId {
+        self.observers.add(TypeId::of::<T>(), event, Some(entity), Arc::new(observer))
+    }
+
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.observers.remove(id)
+    }
+
+    fn trigger(&mut self, type_id: TypeId, event: Lifecycle, entity: Entity) {
+        if self.observers.is_empty() {
+            return;
+        }
+
+        for observer in self.observers.collect(type_id, event, entity) {
+            observer(self, entity);
+        }
+    }
+
+    // --- Change Detection ---
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    pub fn last_change_tick(&self) -> u64 {
+        self.last_change_tick
+    }
+
+    /// Returns the previous value
+    pub(crate) fn set_last_change_tick(&mut self, tick: u64) -> u64 {
+        std::mem::replace(&mut self.last_change_tick, tick)
+    }
+
+    /// Starts a new tick, changes made from now on are newer than everything before
+    pub fn increment_change_tick(&mut self) -> u64 {
+        self.change_tick += 1;
+        self.change_tick
+    }
+
+    /// Ends a frame for code querying the world directly: `Added`, `Changed` and `removed` on the world
+    /// only report what happens after this call
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.increment_change_tick();
+        self.prune_removed(self.change_tick);
+    }
+
+    /// Forgets removals stamped before `before`, called by `Schedule` once every system has seen them
+    pub fn prune_removed(&mut self, before: u64) {
+        for removed in self.removed.values_mut() {
+            removed.retain(|(_, tick)| *tick >= before);
+        }
+    }
+
+    pub fn get_query_ticks(&self) -> QueryTicks {
+        QueryTicks { last_run: self.last_change_tick, this_run: self.change_tick }
+    }
+
+    fn get_or_create_storage<T: Component>(&mut self) -> &mut ComponentStorage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(DataCell::new(ComponentStorage::<T>::new())))
+            .get_mut()
             .as_any_mut()
             .downcast_mut::<ComponentStorage<T>>()
             .unwrap()
     }
 
-    fn get_storage<T: Component>(&self) -> Option<&ComponentStorage<T>> {
+    pub(crate) fn get_storage<T: Component>(&self) -> Option<&ComponentStorage<T>> {
         self.storages
             .get(&TypeId::of::<T>())
-            .and_then(|storage| storage.as_any().downcast_ref::<ComponentStorage<T>>())
+            .and_then(|storage| unsafe { storage.get_ref() }.as_any().downcast_ref::<ComponentStorage<T>>())
     }
 
-    fn get_storage_mut<T: Component>(&mut self) -> Option<&mut ComponentStorage<T>> {
+    pub(crate) fn get_storage_mut<T: Component>(&mut self) -> Option<&mut ComponentStorage<T>> {
         self.storages
             .get_mut(&TypeId::of::<T>())
-            .and_then(|storage| storage.as_any_mut().downcast_mut::<ComponentStorage<T>>())
+            .and_then(|storage| storage.get_mut().as_any_mut().downcast_mut::<ComponentStorage<T>>())
+    }
+
+    /// Handle for code that hands parts of the world to several threads, see `UnsafeWorldCell`
+    pub fn as_unsafe_world_cell(&mut self) -> UnsafeWorldCell<'_> {
+        UnsafeWorldCell { world: self, _marker: PhantomData }
+    }
+
+    /// Only for read-only queries, nothing may be written through the cell
+    pub(crate) fn as_unsafe_world_cell_readonly(&self) -> UnsafeWorldCell<'_> {
+        UnsafeWorldCell { world: self as *const World as *mut World, _marker: PhantomData }
+    }
+}
+
+// --- Unsafe World Cell ---
+
+/// The world as the systems of a parallel batch see it. It never materializes a `&World` or `&mut World`:
+/// the entity allocator, locations, archetypes and the storage maps are only borrowed shared, and
+/// components and resources are reached through their `DataCell`s. Callers make sure that whatever they
+/// write through it is not touched by anyone else at the same time, e.g. through the declared access of systems.
+#[derive(Clone, Copy)]
+pub struct UnsafeWorldCell<'w> {
+    world: *mut World,
+    _marker: PhantomData<(&'w World, &'w UnsafeCell<World>)>,
+}
+
+// threads only touch what their declared access allows, see `Schedule`
+unsafe impl Send for UnsafeWorldCell<'_> {}
+unsafe impl Sync for UnsafeWorldCell<'_> {}
+
+impl<'w> UnsafeWorldCell<'w> {
+    /// Nothing else may use the cell, or anything borrowed through it, while the world is borrowed
+    pub(crate) unsafe fn world_mut(self) -> &'w mut World {
+        unsafe { &mut *self.world }
+    }
+
+    pub(crate) unsafe fn entities(self) -> &'w EntityAllocator {
+        unsafe { &(*self.world).entities }
+    }
+
+    pub(crate) unsafe fn archetypes(self) -> &'w Archetypes {
+        unsafe { &(*self.world).archetypes }
+    }
+
+    pub(crate) unsafe fn get_location(self, entity: Entity) -> EntityLocation {
+        unsafe { (&(*self.world).locations)[entity.index()] }
+    }
+
+    pub(crate) unsafe fn change_tick(self) -> u64 {
+        unsafe { (*self.world).change_tick }
+    }
+
+    /// Nobody may write the storage of `T` while the reference is alive
+    pub(crate) unsafe fn get_storage<T: Component>(self) -> Option<&'w ComponentStorage<T>> {
+        unsafe {
+            (*self.world).storages
+                .get(&TypeId::of::<T>())
+                .and_then(|storage| storage.get_ref().as_any().downcast_ref::<ComponentStorage<T>>())
+        }
+    }
+
+    /// Nobody else may touch the storage of `T` while the reference is alive
+    pub(crate) unsafe fn get_storage_mut<T: Component>(self) -> Option<&'w mut ComponentStorage<T>> {
+        unsafe {
+            (*self.world).storages
+                .get(&TypeId::of::<T>())
+                .and_then(|storage| (*storage.get()).as_any_mut().downcast_mut::<ComponentStorage<T>>())
+        }
+    }
+
+    /// Nobody may write `R` while the reference is alive
+    pub(crate) unsafe fn get_resource<R: Resource>(self) -> Option<&'w R> {
+        unsafe {
+            (*self.world).resources
+                .get(&TypeId::of::<R>())
+                .and_then(|resource| resource.downcast_ref::<DataCell<R>>())
+                .map(|resource| resource.get_ref())
+        }
+    }
+
+    /// Nobody else may touch `R` while the reference is alive
+    pub(crate) unsafe fn get_resource_mut<R: Resource>(self) -> Option<&'w mut R> {
+        unsafe {
+            (*self.world).resources
+                .get(&TypeId::of::<R>())
+                .and_then(|resource| resource.downcast_ref::<DataCell<R>>())
+                .map(|resource| &mut *resource.get())
+        }
+    }
+
+    /// Removals are only recorded while the world is borrowed mutably
+    pub(crate) unsafe fn removed_since<T: Component>(self, since: u64) -> impl Iterator<Item = Entity> + 'w {
+        unsafe {
+            (*self.world).removed.get(&TypeId::of::<T>())
+                .into_iter()
+                .flat_map(move |removed| removed.iter().filter(move |(_, tick)| *tick > since).map(|(entity, _)| *entity))
+        }
     }
 }
 
@@ -1,10 +1,15 @@
-use std::fs::File;
-use std::io::{Read, Write, Seek, SeekFrom};
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use flate2::write::DeflateEncoder;
 use flate2::read::DeflateDecoder;
 use flate2::Compression;
+use memmap2::Mmap;
 
 use anyhow::*;
 use ash::*;
@@ -14,7 +19,25 @@ use bincode;
 use crate::core::bnan_mesh::{BnanMeshletDAG, BnanMeshletRawData};
 
 const BPK_MAGIC: u32 = 0x004B5042; // "BPK\0" in little endian
-const BPK_VERSION: u32 = 1;
+// the header also points at the path index, the entry table only lists paths and is parsed on first browse
+const BPK_VERSION: u32 = 4;
+// the header points at the entry table, which sits after the payloads so `append` can write a new one
+const BPK_VERSION_3: u32 = 3;
+// the entry table follows the header directly
+const BPK_VERSION_2: u32 = 2;
+// no codec or checksum in the entry table, deflated entries are the ones with a `.meta` sidecar
+const BPK_VERSION_1: u32 = 1;
+// Magic + Version + TableOffset + IndexOffset
+const BPK_HEADER_SIZE: u64 = 4 + 4 + 8 + 8;
+// Id + Offset + Size + Codec + UncompressedSize + Checksum + PathLength, the path follows
+const BPK_INDEX_RECORD_SIZE: usize = 8 + 8 + 8 + 1 + 8 + 16 + 4;
+const BPK_COPY_CHUNK: u64 = 1 << 20;
+const BPK_CHECKSUM_CHUNK: usize = 1 << 16;
+// payloads start on this boundary so mapped entries can be read as vectors or copied straight to the GPU
+const BPK_ALIGNMENT: u64 = 16;
+
+/// Positions, vertices and triangles of one meshlet
+pub type BpkMeshletData<'a> = (Cow<'a, [u8]>, Cow<'a, [u8]>, Cow<'a, [u8]>);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BpkImageHeader {
@@ -34,6 +57,130 @@ pub struct BpkBufferHeader {
     data_checksum: [u8; 16],
 }
 
+/// How the bytes of an entry are stored, new codecs take the next free tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BpkCodec {
+    None,
+    Deflate,
+}
+
+impl BpkCodec {
+    pub fn to_raw(self) -> u8 {
+        match self {
+            BpkCodec::None => 0,
+            BpkCodec::Deflate => 1,
+        }
+    }
+
+    pub fn from_raw(raw: u8) -> Result<Self> {
+        match raw {
+            0 => Ok(BpkCodec::None),
+            1 => Ok(BpkCodec::Deflate),
+            _ => bail!("Unknown BPK codec: {}", raw),
+        }
+    }
+
+    pub fn encode(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            BpkCodec::None => Ok(data),
+            BpkCodec::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    /// Decodes `stored` as it is read, so large entries never have to be held at once
+    pub fn decoder<'a>(self, stored: impl Read + 'a) -> Box<dyn Read + 'a> {
+        match self {
+            BpkCodec::None => Box::new(stored),
+            BpkCodec::Deflate => Box::new(DeflateDecoder::new(stored)),
+        }
+    }
+
+    /// `uncompressed_size` only sizes the output up front, 0 if unknown
+    pub fn decode(self, stored: Vec<u8>, uncompressed_size: u64) -> Result<Vec<u8>> {
+        match self {
+            BpkCodec::None => Ok(stored),
+            BpkCodec::Deflate => {
+                let mut decoder = DeflateDecoder::new(stored.as_slice());
+                let mut uncompressed = Vec::with_capacity(uncompressed_size as usize);
+                decoder.read_to_end(&mut uncompressed)?;
+                Ok(uncompressed)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BpkFileInfo {
+    pub codec: BpkCodec,
+    pub uncompressed_size: u64,
+    /// md5 of the uncompressed bytes, missing for entries of v1 archives until they are saved again
+    pub checksum: Option<[u8; 16]>,
+}
+
+impl BpkFileInfo {
+    pub fn new(codec: BpkCodec, uncompressed: &[u8]) -> Self {
+        Self {
+            codec,
+            uncompressed_size: uncompressed.len() as u64,
+            checksum: Some(md5::compute(uncompressed).0),
+        }
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Stable id of an entry path, the 64-bit FNV-1a hash of its segments joined by `/`. Ids are the same on
+/// every platform and run, so they can be computed at compile time and kept instead of path strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BpkAssetId(pub u64);
+
+impl BpkAssetId {
+    pub const fn from_path(path: &str) -> Self {
+        Self(FNV_OFFSET_BASIS).join(path)
+    }
+
+    /// Id of `path` below this one without building the string, `from_path("a").join("b/c")` is `from_path("a/b/c")`
+    pub const fn join(self, path: &str) -> Self {
+        let bytes = path.as_bytes();
+        let mut hash = self.0;
+
+        // empty segments are skipped the same way the tree skips them, so `a//b/` and `a/b` share an id
+        let mut started = hash != FNV_OFFSET_BASIS;
+        let mut separator = started;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            if bytes[i] == b'/' {
+                separator = started;
+            } else {
+                if separator {
+                    hash = (hash ^ b'/' as u64).wrapping_mul(FNV_PRIME);
+                    separator = false;
+                }
+
+                hash = (hash ^ bytes[i] as u64).wrapping_mul(FNV_PRIME);
+                started = true;
+            }
+
+            i += 1;
+        }
+
+        Self(hash)
+    }
+}
+
+impl fmt::Display for BpkAssetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Bytes of an entry as stored, i.e. after encoding with the entry's codec
 #[derive(Debug, Clone)]
 pub enum BpkEntryData {
     OnDisk { offset: u64, size: u64 },
@@ -44,6 +191,7 @@ pub enum BpkEntryData {
 pub enum BpkNode {
     File {
         data: BpkEntryData,
+        info: BpkFileInfo,
     },
 
     Directory {
@@ -57,78 +205,164 @@ pub struct BpkEntry {
     pub node: BpkNode,
 }
 
+// one row of the entry table, `None` for directories
+struct BpkTableEntry {
+    path: String,
+    file: Option<(u64, u64, BpkFileInfo)>,
+}
+
+// what the path index holds for a file
+#[derive(Debug, Clone)]
+enum BpkIndexEntry {
+    // always `OnDisk`, the path tells a lookup apart from another path with the same id
+    Stored { data: BpkEntryData, info: BpkFileInfo, path: Box<str> },
+    // added or handed out through `get_node_mut` since the archive was opened, looked up in the tree
+    Added(String),
+}
+
+impl BpkIndexEntry {
+    fn path(&self) -> &str {
+        match self {
+            BpkIndexEntry::Stored { path, .. } => path,
+            BpkIndexEntry::Added(path) => path,
+        }
+    }
+}
+
 pub struct BpkArchive {
     file: Option<File>,
-    pub root: BpkNode,
+    // where the archive was opened from and its version there, `append` writes back to it
+    path: Option<PathBuf>,
+    version: u32,
+    // set by `open_mapped`, on disk entries are then read from the mapping
+    mmap: Option<Mmap>,
+    // every file by asset id, kept in step with the tree
+    index: HashMap<BpkAssetId, BpkIndexEntry>,
+    // v4 archives leave the entry table between these offsets unparsed until the tree is first needed
+    tree: OnceLock<BpkNode>,
+    table_range: Option<(u64, u64)>,
 }
 
+// loaders share one archive between threads, every read goes through `&self`
+const _: () = {
+    const fn assert_sync<T: Sync>() {}
+    assert_sync::<BpkArchive>();
+};
+
 impl BpkArchive {
     pub fn new() -> Self {
         Self {
             file: None,
-            root: BpkNode::Directory { children: Vec::new() },
+            path: None,
+            version: BPK_VERSION,
+            mmap: None,
+            index: HashMap::new(),
+            tree: OnceLock::from(BpkNode::Directory { children: Vec::new() }),
+            table_range: None,
         }
     }
 
+    /// Like `open` but maps the archive into memory, `read_slice` then borrows uncompressed entries
+    /// without copying. The file must not be modified while it is mapped.
+    pub fn open_mapped<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut archive = Self::open(path)?;
+        let mmap = unsafe { Mmap::map(archive.file.as_ref().unwrap())? };
+
+        archive.mmap = Some(mmap);
+        Ok(archive)
+    }
+
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut file = File::open(path)?;
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(&file);
         
         let mut magic_buf = [0u8; 4];
-        file.read_exact(&mut magic_buf)?;
+        reader.read_exact(&mut magic_buf)?;
         if u32::from_le_bytes(magic_buf) != BPK_MAGIC {
             bail!("Invalid BPK file magic");
         }
 
         let mut version_buf = [0u8; 4];
-        file.read_exact(&mut version_buf)?;
+        reader.read_exact(&mut version_buf)?;
         let version = u32::from_le_bytes(version_buf);
-        if version != BPK_VERSION {
+        if version != BPK_VERSION && version != BPK_VERSION_3 && version != BPK_VERSION_2 && version != BPK_VERSION_1 {
              bail!("Unsupported BPK version: {}", version);
         }
 
-        let mut count_buf = [0u8; 8];
-        file.read_exact(&mut count_buf)?;
-        let count = u64::from_le_bytes(count_buf);
+        let mut archive = Self {
+            file: None,
+            path: Some(path.to_path_buf()),
+            version,
+            mmap: None,
+            index: HashMap::new(),
+            tree: OnceLock::new(),
+            table_range: None,
+        };
 
-        let mut root = BpkNode::Directory { children: Vec::new() };
+        if version == BPK_VERSION {
+            let mut table_offset_buf = [0u8; 8];
+            reader.read_exact(&mut table_offset_buf)?;
 
-        for _ in 0..count {
-            // Read Path String
-            let mut path_len_buf = [0u8; 4];
-            file.read_exact(&mut path_len_buf)?;
-            let path_len = u32::from_le_bytes(path_len_buf) as usize;
+            let mut index_offset_buf = [0u8; 8];
+            reader.read_exact(&mut index_offset_buf)?;
+            let index_offset = u64::from_le_bytes(index_offset_buf);
 
-            let mut path_buf = vec![0u8; path_len];
-            file.read_exact(&mut path_buf)?;
-            let full_path = String::from_utf8(path_buf).context("Invalid UTF-8 in entry path")?;
+            // only the index is read up front, `root` parses the entry table once something browses it
+            reader.seek(SeekFrom::Start(index_offset))?;
+            archive.index = read_index(&mut reader)?;
+            archive.table_range = Some((u64::from_le_bytes(table_offset_buf), index_offset));
 
-            let mut type_buf = [0u8; 1];
-            file.read_exact(&mut type_buf)?;
-            let is_dir = type_buf[0] == 1;
+            drop(reader);
+            archive.file = Some(file);
+            return Ok(archive);
+        }
 
-            if is_dir {
-                Self::insert_node(&mut root, &full_path, BpkNode::Directory { children: Vec::new() })?;
-            } else {
-                let mut offset_buf = [0u8; 8];
-                file.read_exact(&mut offset_buf)?;
-                let offset = u64::from_le_bytes(offset_buf);
+        if version == BPK_VERSION_3 {
+            let mut table_offset_buf = [0u8; 8];
+            reader.read_exact(&mut table_offset_buf)?;
+            reader.seek(SeekFrom::Start(u64::from_le_bytes(table_offset_buf)))?;
+        }
 
-                let mut size_buf = [0u8; 8];
-                file.read_exact(&mut size_buf)?;
-                let size = u64::from_le_bytes(size_buf);
+        let (root, v1_files) = read_table(&mut reader, version, &archive.index)?;
 
-                let node = BpkNode::File {
-                    data: BpkEntryData::OnDisk { offset, size },
-                };
-                
-                Self::insert_node(&mut root, &full_path, node)?;
+        drop(reader);
+        archive.file = Some(file);
+        archive.tree = OnceLock::from(root);
+
+        // v1 only deflated what add_image and add_buffer wrote, and those always have a sidecar
+        for path in &v1_files {
+            if !v1_files.contains(&format!("{}.meta", path)) {
+                continue;
+            }
+
+            if let Some(BpkNode::File { info, .. }) = archive.node_mut(path)? {
+                info.codec = BpkCodec::Deflate;
+                info.uncompressed_size = 0;
             }
         }
-        
-        Ok(Self {
-            file: Some(file),
-            root,
-        })
+
+        archive.index = archive.index_tree()?;
+        Ok(archive)
+    }
+
+    /// Every entry as a tree, for browsing. A v4 archive parses its entry table on the first call.
+    pub fn root(&self) -> Result<&BpkNode> {
+        if let Some(root) = self.tree.get() {
+            return Ok(root);
+        }
+
+        let (table_offset, index_offset) = self.table_range.ok_or_else(|| anyhow!("Archive has no entry table"))?;
+        let table = self.read_stored(&BpkEntryData::OnDisk { offset: table_offset, size: index_offset - table_offset })?;
+        let (root, _) = read_table(&mut table.as_slice(), self.version, &self.index)?;
+
+        // another thread may have parsed the same table meanwhile, either copy will do
+        Ok(self.tree.get_or_init(|| root))
+    }
+
+    fn root_mut(&mut self) -> Result<&mut BpkNode> {
+        self.root()?;
+        Ok(self.tree.get_mut().unwrap())
     }
 
     fn insert_node(root: &mut BpkNode, path: &str, new_node: BpkNode) -> Result<()> {
@@ -176,132 +410,321 @@ impl BpkArchive {
         Ok(())
     }
 
+    /// Writes the whole archive to a temporary file next to `path` and renames it over `path`, so a crash
+    /// never leaves a half written archive behind. On disk entries are streamed over, not buffered.
+    /// Saving over the file the archive was opened from reopens it, every entry has moved.
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        
-        struct FlatEntry<'a> {
-            path: String,
-            node: &'a BpkNode,
+        let path = path.as_ref();
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        if let Err(error) = self.write_archive(&temp_path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(error);
         }
-        
-        let mut flat_entries = Vec::new();
-        
-        fn collect_recursive<'a>(node: &'a BpkNode, parent_path: String, out: &mut Vec<FlatEntry<'a>>) {
-             if let BpkNode::Directory { children } = node {
-                 for child in children {
-                     let mut current_path = parent_path.clone();
-                     if !current_path.is_empty() {
-                         current_path.push('/');
-                     }
-                     current_path.push_str(&child.name);
-                     
-                     out.push(FlatEntry { path: current_path.clone(), node: &child.node });
-                     collect_recursive(&child.node, current_path, out);
-                 }
-             }
+
+        if !self.is_own_file(path) {
+            std::fs::rename(&temp_path, path)?;
+            return Ok(());
         }
-        
-        collect_recursive(&self.root, String::new(), &mut flat_entries);
 
-        struct PendingWrite {
-             data: Vec<u8>,
+        // Windows refuses to rename over a file that is still open or mapped
+        let own_path = self.path.clone().unwrap();
+        let mapped = self.mmap.is_some();
+        self.mmap = None;
+        self.file = None;
+
+        if let Err(error) = std::fs::rename(&temp_path, path) {
+            let _ = std::fs::remove_file(&temp_path);
+
+            // the old file is untouched, so the offsets still describe it
+            self.file = Some(File::open(&own_path)?);
+            if mapped {
+                self.mmap = Some(unsafe { Mmap::map(self.file.as_ref().unwrap())? });
+            }
+
+            return Err(error.into());
         }
 
-        let mut pending_writes = Vec::with_capacity(flat_entries.len());
-        
-        for entry in &flat_entries {
-             if let BpkNode::File { data, .. } = entry.node {
-                 match data {
-                     BpkEntryData::InMemory(data) => {
-                        pending_writes.push(Some(PendingWrite { data: data.clone() }));
-                     },
-
-                     BpkEntryData::OnDisk { offset, size } => {
-                         if let Some(src_file) = &mut self.file {
-                             src_file.seek(SeekFrom::Start(*offset))?;
-                             let mut buffer = vec![0u8; *size as usize];
-                             src_file.read_exact(&mut buffer)?;
-                             pending_writes.push(Some(PendingWrite { data: buffer }));
-                         } else {
-                             bail!("Missing source file for OnDisk entry: {}", entry.path);
-                         }
-                     }
-                 }
-             } else {
-                 pending_writes.push(None);
-             }
+        *self = match mapped {
+            true => Self::open_mapped(&own_path)?,
+            false => Self::open(&own_path)?,
+        };
+
+        Ok(())
+    }
+
+    fn is_own_file(&self, path: &Path) -> bool {
+        let Some(own_path) = &self.path else {
+            return false;
+        };
+
+        match (std::fs::canonicalize(own_path), std::fs::canonicalize(path)) {
+            (Result::Ok(own_path), Result::Ok(path)) => own_path == path,
+            _ => false,
         }
+    }
+
+    /// Whether `append` can update the file the archive was opened from, older versions have to be saved
+    pub fn can_append(&self) -> bool {
+        self.path.is_some() && self.version == BPK_VERSION
+    }
 
-        let mut header_size = 4 + 4 + 8; // Magic + Version + Count
-        for entry in &flat_entries {
-            // PathLen(4) + Path(len) + Type(1)
-            header_size += 4 + entry.path.len() + 1;
-            if let BpkNode::File { .. } = entry.node {
-                // Offset(8) + Size(8)
-                header_size += 8 + 8;
+    /// Writes the entries added since `open` after the end of the archive file, then a new entry table,
+    /// then points the header at it. The old table stays valid until that last write, so a crash loses
+    /// the update but not the archive. Replaced and removed entries keep their space until `compact`.
+    pub fn append(&mut self) -> Result<()> {
+        let path = self.path.clone().ok_or_else(|| anyhow!("Archive was not opened from a file, save it instead"))?;
+        if self.version != BPK_VERSION {
+            bail!("BPK version {} archives can not be appended to, save them first", self.version);
+        }
+
+        let mut writer = BufWriter::new(OpenOptions::new().write(true).open(&path)?);
+        let mut offset = writer.seek(SeekFrom::End(0))?;
+        let mut table = Vec::new();
+
+        for (entry_path, node) in self.flatten()? {
+            let file = match node {
+                BpkNode::Directory { .. } => None,
+                BpkNode::File { data: BpkEntryData::OnDisk { offset, size }, info } => Some((*offset, *size, *info)),
+                BpkNode::File { data: BpkEntryData::InMemory(data), info } => {
+                    offset = write_padding(&mut writer, offset)?;
+                    writer.write_all(data)?;
+
+                    let location = (offset, data.len() as u64, *info);
+                    offset += data.len() as u64;
+                    Some(location)
+                }
+            };
+
+            table.push(BpkTableEntry { path: entry_path, file });
+        }
+
+        let table_offset = write_padding(&mut writer, offset)?;
+        let index_offset = table_offset + write_table(&mut writer, &table)?;
+        write_index(&mut writer, &table)?;
+
+        // payloads, table and index have to be durable before the header points at them
+        let mut file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+
+        // one write well inside the first sector, the header never points at an old table and a new index
+        file.seek(SeekFrom::Start(8))?;
+        file.write_all(&header_offsets(table_offset, index_offset))?;
+        file.sync_all()?;
+
+        for entry in &table {
+            let Some((offset, size, _)) = entry.file else {
+                continue;
+            };
+
+            if let Some(BpkNode::File { data, .. }) = self.node_mut(&entry.path)? && let BpkEntryData::InMemory(_) = data {
+                *data = BpkEntryData::OnDisk { offset, size };
             }
         }
 
-        let file = File::create(path)?;
-        let mut writer = std::io::BufWriter::new(file);
+        self.index = self.index_tree()?;
+
+        if self.mmap.is_some() {
+            self.mmap = Some(unsafe { Mmap::map(self.file.as_ref().unwrap())? });
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites the archive file without the space `append` left behind, then reopens it
+    pub fn compact(&mut self) -> Result<()> {
+        let path = self.path.clone().ok_or_else(|| anyhow!("Archive was not opened from a file"))?;
+        self.save(&path)
+    }
+
+    fn write_archive(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
 
         writer.write_all(&BPK_MAGIC.to_le_bytes())?;
         writer.write_all(&BPK_VERSION.to_le_bytes())?;
-        writer.write_all(&(flat_entries.len() as u64).to_le_bytes())?;
+        writer.write_all(&header_offsets(0, 0))?; // table and index offsets, written once the payloads are
+
+        let mut offset = BPK_HEADER_SIZE;
+        let mut table = Vec::new();
+
+        for (entry_path, node) in self.flatten()? {
+            let file = match node {
+                BpkNode::Directory { .. } => None,
+                BpkNode::File { data, info } => {
+                    offset = write_padding(&mut writer, offset)?;
+                    let size = self.copy_stored(data, &mut writer).with_context(|| format!("Failed to write {}", entry_path))?;
+
+                    // entries of v1 archives get their checksum when first saved
+                    let info = match info.checksum {
+                        Some(_) => *info,
+                        None => self.checksum_stored(data, info.codec).with_context(|| format!("Failed to checksum {}", entry_path))?,
+                    };
+
+                    let location = (offset, size, info);
+                    offset += size;
+                    Some(location)
+                }
+            };
 
-        let mut current_offset = header_size as u64;
+            table.push(BpkTableEntry { path: entry_path, file });
+        }
 
-        for (i, entry) in flat_entries.iter().enumerate() {
-            writer.write_all(&(entry.path.len() as u32).to_le_bytes())?;
-            writer.write_all(entry.path.as_bytes())?;
-            
-            match entry.node {
-                BpkNode::Directory { .. } => {
-                    writer.write_all(&[1u8])?; // Type 1 = Directory
-                },
-                BpkNode::File { .. } => {
-                    writer.write_all(&[0u8])?; // Type 0 = File
-
-                    let p_write = pending_writes[i].as_ref().unwrap();
-                    let size = p_write.data.len() as u64;
-                    
-                    writer.write_all(&current_offset.to_le_bytes())?;
-                    writer.write_all(&size.to_le_bytes())?;
-
-                    current_offset += size;
-                }
+        let table_offset = write_padding(&mut writer, offset)?;
+        let index_offset = table_offset + write_table(&mut writer, &table)?;
+        write_index(&mut writer, &table)?;
+
+        writer.seek(SeekFrom::Start(8))?;
+        writer.write_all(&header_offsets(table_offset, index_offset))?;
+
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Info of an entry with its checksum, decoded and hashed in chunks
+    fn checksum_stored(&self, data: &BpkEntryData, codec: BpkCodec) -> Result<BpkFileInfo> {
+        let mut decoded = codec.decoder(BpkStoredReader { archive: self, data, position: 0 });
+        let mut context = md5::Context::new();
+        let mut buffer = vec![0u8; BPK_CHECKSUM_CHUNK];
+        let mut uncompressed_size = 0;
+
+        loop {
+            let read = decoded.read(&mut buffer)?;
+            if read == 0 {
+                break;
             }
+
+            context.consume(&buffer[..read]);
+            uncompressed_size += read as u64;
         }
 
-        for p_write in pending_writes {
-            if let Some(pw) = p_write {
-                writer.write_all(&pw.data)?;
+        Ok(BpkFileInfo { codec, uncompressed_size, checksum: Some(context.finalize().0) })
+    }
+
+    /// Every entry with its full path, parents before their children
+    fn flatten(&self) -> Result<Vec<(String, &BpkNode)>> {
+        let mut flat_entries = Vec::new();
+        collect_entries(self.root()?, "", &mut flat_entries);
+        Ok(flat_entries)
+    }
+
+    /// Path index of every file in the tree, fails if two paths share an id
+    fn index_tree(&self) -> Result<HashMap<BpkAssetId, BpkIndexEntry>> {
+        let mut index = HashMap::new();
+
+        for (path, node) in self.flatten()? {
+            let BpkNode::File { data, info } = node else {
+                continue;
+            };
+
+            let entry = match data {
+                BpkEntryData::OnDisk { .. } => BpkIndexEntry::Stored { data: data.clone(), info: *info, path: path.as_str().into() },
+                BpkEntryData::InMemory(_) => BpkIndexEntry::Added(path.clone()),
+            };
+
+            if index.insert(BpkAssetId::from_path(&path), entry).is_some() {
+                bail!("{} has the same asset id as another entry", path);
             }
         }
-        
-        writer.flush()?;
+
+        Ok(index)
+    }
+
+    /// Asset ids of the file at `path` or of every file below it, with their paths
+    fn files_under(&self, path: &str) -> Result<Vec<(BpkAssetId, String)>> {
+        let Some(node) = self.get_node(path)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut entries = vec![(path.to_string(), node)];
+        collect_entries(node, path, &mut entries);
+
+        Ok(entries.into_iter()
+            .filter(|(_, node)| matches!(node, BpkNode::File { .. }))
+            .map(|(path, _)| (BpkAssetId::from_path(&path), path))
+            .collect())
+    }
+
+    // drops the files the tree is about to lose at `path` from the index
+    fn unindex(&mut self, path: &str) -> Result<()> {
+        for (id, _) in self.files_under(path)? {
+            self.index.remove(&id);
+        }
+
         Ok(())
     }
 
+    /// Writes the stored bytes of an entry, on disk entries in chunks. Returns the size written.
+    fn copy_stored(&self, data: &BpkEntryData, writer: &mut impl Write) -> Result<u64> {
+        match data {
+            BpkEntryData::InMemory(data) => {
+                writer.write_all(data)?;
+                Ok(data.len() as u64)
+            }
+
+            BpkEntryData::OnDisk { offset, size } if self.mmap.is_some() => {
+                writer.write_all(self.mapped_range(*offset, *size)?)?;
+                Ok(*size)
+            }
+
+            BpkEntryData::OnDisk { offset, size } => {
+                let file = self.file.as_ref().ok_or_else(|| anyhow!("No source file available"))?;
+                let mut buffer = vec![0u8; BPK_COPY_CHUNK.min(*size) as usize];
+                let mut copied = 0;
+
+                while copied < *size {
+                    let chunk = (*size - copied).min(BPK_COPY_CHUNK) as usize;
+                    read_exact_at(file, &mut buffer[..chunk], offset + copied)?;
+                    writer.write_all(&buffer[..chunk])?;
+                    copied += chunk as u64;
+                }
+
+                Ok(*size)
+            }
+        }
+    }
+
     pub fn add_directory(&mut self, path: &str) -> Result<()> {
-        Self::insert_node(&mut self.root, path, BpkNode::Directory { children: Vec::new() })
+        self.unindex(path)?;
+        Self::insert_node(self.root_mut()?, path, BpkNode::Directory { children: Vec::new() })
     }
 
     pub fn add_item(&mut self, path: &str, data: Vec<u8>) -> Result<()> {
+        self.add_item_with_codec(path, data, BpkCodec::None)
+    }
+
+    /// Encodes `data` with `codec`, `read_file` hands it back decoded
+    pub fn add_item_with_codec(&mut self, path: &str, data: Vec<u8>, codec: BpkCodec) -> Result<()> {
+        let id = BpkAssetId::from_path(path);
+        if self.find_file(id, None).is_some() && self.find_file(id, Some(path)).is_none() {
+            bail!("{} has the same asset id as another entry", path);
+        }
+
+        let info = BpkFileInfo::new(codec, &data);
         let node = BpkNode::File {
-            data: BpkEntryData::InMemory(data),
+            data: BpkEntryData::InMemory(codec.encode(data)?),
+            info,
         };
         
-        Self::insert_node(&mut self.root, path, node)
+        self.unindex(path)?;
+        Self::insert_node(self.root_mut()?, path, node)?;
+        self.index.insert(id, BpkIndexEntry::Added(path.to_string()));
+        Ok(())
     }
 
     pub fn remove_item(&mut self, path: &str) -> Result<()> {
+         self.unindex(path)?;
+
          let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
          if parts.is_empty() { return Ok(()); }
          
          let parent_parts = &parts[0..parts.len()-1];
          let leaf_name = parts.last().unwrap();
          
-         let mut current_node = &mut self.root;
+         let mut current_node = self.root_mut()?;
          for part in parent_parts {
              if let BpkNode::Directory { children } = current_node {
                  match children.binary_search_by(|e| e.name.as_str().cmp(part)) {
@@ -322,78 +745,166 @@ impl BpkArchive {
          Ok(())
     }
 
-    pub fn get_node(&self, path: &str) -> Option<&BpkNode> {
-         let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-         if parts.is_empty() { return Some(&self.root); }
-         
-         let mut current_node = &self.root;
-         for part in parts {
+    /// Walks the tree, see `root`. Reading files goes through the path index instead.
+    pub fn get_node(&self, path: &str) -> Result<Option<&BpkNode>> {
+         let mut current_node = self.root()?;
+         for part in path.split('/').filter(|s| !s.is_empty()) {
              if let BpkNode::Directory { children } = current_node {
                  match children.binary_search_by(|e| e.name.as_str().cmp(part)) {
                      Result::Ok(idx) => current_node = &children[idx].node,
-                     Err(_) => return None,
+                     Err(_) => return Ok(None),
                  }
              } else {
-                 return None;
+                 return Ok(None);
              }
          }
-         Some(current_node)
+         Ok(Some(current_node))
     }
 
-    pub fn get_node_mut(&mut self, path: &str) -> Option<&mut BpkNode> {
-        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-        if parts.is_empty() { return Some(&mut self.root); }
+    /// The files below the node are looked up through the tree from then on, as they may change behind the index
+    pub fn get_node_mut(&mut self, path: &str) -> Result<Option<&mut BpkNode>> {
+        for (id, file_path) in self.files_under(path)? {
+            self.index.insert(id, BpkIndexEntry::Added(file_path));
+        }
 
-        let mut current_node = &mut self.root;
-        for part in parts {
+        self.node_mut(path)
+    }
+
+    fn node_mut(&mut self, path: &str) -> Result<Option<&mut BpkNode>> {
+        let mut current_node = self.root_mut()?;
+        for part in path.split('/').filter(|s| !s.is_empty()) {
             if let BpkNode::Directory { children } = current_node {
                 match children.binary_search_by(|e| e.name.as_str().cmp(part)) {
                     Result::Ok(idx) => current_node = &mut children[idx].node,
-                    Err(_) => return None,
+                    Err(_) => return Ok(None),
                 }
             } else {
-                return None;
+                return Ok(None);
             }
         }
-        Some(current_node)
+        Ok(Some(current_node))
     }
     
+    /// Decoded bytes of the entry, verified against its checksum. Files are found with one probe of the
+    /// path index, and reads are positional, so any number of threads can read from a shared archive at once.
     pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
-        let node_ptr = self.get_node(path).ok_or_else(|| anyhow!("Path not found: {}", path))?;
+        let (data_desc, info) = self.get_file(path)?;
+        self.decode_file(path, data_desc, info)
+    }
+
+    /// `read_file` for every path, results in the order of `paths`. On disk entries are read in file
+    /// order to keep the reads sequential.
+    pub fn read_many(&self, paths: &[&str]) -> Result<Vec<Vec<u8>>> {
+        let mut files = paths.iter()
+            .enumerate()
+            .map(|(index, path)| self.get_file(path).map(|(data, info)| (index, *path, data, info)))
+            .collect::<Result<Vec<_>>>()?;
+
+        files.sort_by_key(|(_, _, data, _)| match data {
+            BpkEntryData::OnDisk { offset, .. } => *offset,
+            BpkEntryData::InMemory(_) => 0,
+        });
+
+        let mut results = vec![Vec::new(); paths.len()];
+        for (index, path, data, info) in files {
+            results[index] = self.decode_file(path, data, info)?;
+        }
+
+        Ok(results)
+    }
+
+    /// `read_file` by asset id
+    pub fn read_asset(&self, id: BpkAssetId) -> Result<Vec<u8>> {
+        let (data_desc, info) = self.find_file(id, None).ok_or_else(|| anyhow!("Asset not found: {}", id))?;
+        self.decode_file(&id.to_string(), data_desc, info)
+    }
+
+    /// `read_slice` by asset id
+    pub fn read_asset_slice(&self, id: BpkAssetId) -> Result<Cow<'_, [u8]>> {
+        let (data_desc, info) = self.find_file(id, None).ok_or_else(|| anyhow!("Asset not found: {}", id))?;
+        self.slice_file(&id.to_string(), data_desc, info)
+    }
+
+    pub fn contains_asset(&self, id: BpkAssetId) -> bool {
+        self.find_file(id, None).is_some()
+    }
+
+    /// One probe of the path index. With `path` the hit has to be that path and not another one with
+    /// the same id, lookups by id alone rely on `add_item` and `save` refusing ids that are taken.
+    fn find_file(&self, id: BpkAssetId, path: Option<&str>) -> Option<(&BpkEntryData, BpkFileInfo)> {
+        let entry = self.index.get(&id)?;
+        if path.is_some_and(|path| !same_path(path, entry.path())) {
+            return None;
+        }
+
+        match entry {
+            BpkIndexEntry::Stored { data, info, .. } => Some((data, *info)),
+            BpkIndexEntry::Added(path) => match self.get_node(path).ok()?? {
+                BpkNode::File { data, info } => Some((data, *info)),
+                BpkNode::Directory { .. } => None,
+            },
+        }
+    }
 
-        let (data_desc) = match node_ptr {
-            BpkNode::File { data } => data.clone(),
+    fn get_file(&self, path: &str) -> Result<(&BpkEntryData, BpkFileInfo)> {
+        if let Some(file) = self.find_file(BpkAssetId::from_path(path), Some(path)) {
+            return Ok(file);
+        }
+
+        // directories are not indexed, the tree tells them apart from missing paths
+        match self.get_node(path)?.ok_or_else(|| anyhow!("Path not found: {}", path))? {
+            BpkNode::File { data, info } => Ok((data, *info)),
             BpkNode::Directory { .. } => bail!("{} in a directory, not a file", path),
-        };
-        
-        match data_desc {
-             BpkEntryData::InMemory(d) => Ok(d),
-             BpkEntryData::OnDisk { offset, size } => {
-                  if let Some( file) = &self.file {
+        }
+    }
 
-                      let mut file_ref = file;
-                      file_ref.seek(SeekFrom::Start(offset))?;
+    fn decode_file(&self, path: &str, data_desc: &BpkEntryData, info: BpkFileInfo) -> Result<Vec<u8>> {
+        let stored = self.read_stored(data_desc)?;
+        let data = info.codec.decode(stored, info.uncompressed_size).with_context(|| format!("Failed to decode {}", path))?;
 
-                      let mut reader = file_ref.take(size);
-                      let mut data: Vec<u8> = Vec::with_capacity(size as usize);
-                      reader.read_to_end(&mut data)?;
+        if let Some(checksum) = info.checksum && md5::compute(&data).0 != checksum {
+            bail!("Checksum mismatch for {}", path);
+        }
 
-                      Ok(data)
+        Ok(data)
+    }
 
-                      /*
-                      let mut decoder = DeflateDecoder::new(reader);
-                      let mut uncompressed = Vec::with_capacity(compressed_size as usize * 2); // heuristic estimate
-                      decoder.read_to_end(&mut uncompressed)?;
-                      
-                      let calc_hash = md5::compute(&uncompressed).0;
-                      if calc_hash != checksum {
-                          bail!("Checksum mismatch for {}", path);
-                      }
-                      Ok(uncompressed)
-                       */
+    /// Entry bytes without copying when the archive is mapped and the entry is stored uncompressed,
+    /// otherwise the same as `read_file`. Borrowed slices are not checked against the checksum.
+    pub fn read_slice(&self, path: &str) -> Result<Cow<'_, [u8]>> {
+        let (data_desc, info) = self.get_file(path)?;
+        self.slice_file(path, data_desc, info)
+    }
 
+    fn slice_file<'a>(&'a self, path: &str, data_desc: &'a BpkEntryData, info: BpkFileInfo) -> Result<Cow<'a, [u8]>> {
+        match data_desc {
+            BpkEntryData::OnDisk { offset, size } if info.codec == BpkCodec::None && self.mmap.is_some() => {
+                Ok(Cow::Borrowed(self.mapped_range(*offset, *size)?))
+            }
+
+            BpkEntryData::InMemory(data) if info.codec == BpkCodec::None => Ok(Cow::Borrowed(data)),
+            _ => Ok(Cow::Owned(self.decode_file(path, data_desc, info)?)),
+        }
+    }
+
+    fn mapped_range(&self, offset: u64, size: u64) -> Result<&[u8]> {
+        let mmap = self.mmap.as_ref().ok_or_else(|| anyhow!("Archive is not mapped"))?;
+
+        mmap.get(offset as usize..(offset + size) as usize)
+            .ok_or_else(|| anyhow!("Entry at {} ({} bytes) lies outside the archive", offset, size))
+    }
 
+    /// Bytes of the entry as stored in the archive, still encoded
+    pub fn read_stored(&self, data: &BpkEntryData) -> Result<Vec<u8>> {
+        match data {
+             BpkEntryData::InMemory(d) => Ok(d.clone()),
+             BpkEntryData::OnDisk { offset, size } if self.mmap.is_some() => Ok(self.mapped_range(*offset, *size)?.to_vec()),
+             BpkEntryData::OnDisk { offset, size } => {
+                  if let Some(file) = &self.file {
+                      let mut data = vec![0u8; *size as usize];
+                      read_exact_at(file, &mut data, *offset)?;
 
+                      Ok(data)
                   } else {
                       bail!("No source file available");
                   }
@@ -405,10 +916,6 @@ impl BpkArchive {
 
         let checksum = md5::compute(&data).0;
 
-        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(&data)?;
-        let compressed = encoder.finish()?;
-
         let header = BpkImageHeader {
             width,
             height,
@@ -426,17 +933,13 @@ impl BpkArchive {
 
         let header_path = format!("{}.meta", path);
         self.add_item(&header_path, header)?;
-        self.add_item(path, compressed)
+        self.add_item_with_codec(path, data, BpkCodec::Deflate)
     }
 
     pub fn add_buffer(&mut self, path: &str, instance_size: u64, instance_count: u32, data: Vec<u8>) -> Result<()> {
 
         let checksum = md5::compute(&data).0;
 
-        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(&data)?;
-        let compressed = encoder.finish()?;
-
         let header = BpkBufferHeader {
             instance_size,
             instance_count,
@@ -452,7 +955,7 @@ impl BpkArchive {
 
         let header_path = format!("{}.meta", path);
         self.add_item(&header_path, header)?;
-        self.add_item(path, compressed)
+        self.add_item_with_codec(path, data, BpkCodec::Deflate)
     }
 
     pub fn load_image(&self, path: &str) -> Result<(BpkImageHeader, Vec<u8>)> {
@@ -469,13 +972,9 @@ impl BpkArchive {
         if header_cursor.read_exact(&mut header_buf).is_err() { bail!("Failed to read image header"); }
 
         let header: BpkImageHeader = bincode::deserialize(&header_buf)?;
+        let data = self.read_file(path)?;
 
-        let blob = self.read_file(path)?;
-        let mut decoder = DeflateDecoder::new(blob.as_slice());
-        let mut uncompressed = Vec::with_capacity(header.data_len as usize);
-        decoder.read_to_end(&mut uncompressed)?;
-
-        Ok((header, uncompressed))
+        Ok((header, data))
     }
 
     pub fn load_buffer(&self, path: &str) -> Result<(BpkBufferHeader, Vec<u8>)> {
@@ -492,13 +991,9 @@ impl BpkArchive {
         if cursor.read_exact(&mut header_buf).is_err() { bail!("Failed to read buffer header"); }
         
         let header: BpkBufferHeader = bincode::deserialize(&header_buf)?;
-
-        let blob = self.read_file(path)?;
-        let mut decoder = DeflateDecoder::new(blob.as_slice());
-        let mut uncompressed = Vec::with_capacity(header.data_len as usize);
-        decoder.read_to_end(&mut uncompressed)?;
+        let data = self.read_file(path)?;
         
-        Ok((header, uncompressed))
+        Ok((header, data))
     }
 
     pub fn add_meshlet(&mut self, path: &str, positions: &[u8], vertices: &[u8], triangles: &[u8]) -> Result<()> {
@@ -516,15 +1011,16 @@ impl BpkArchive {
         Ok(())
     }
 
-    pub fn load_meshlet(&self, meshlet_path: &str) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    /// Borrowed from the mapping when the archive was opened with `open_mapped`
+    pub fn load_meshlet(&self, meshlet_path: &str) -> Result<BpkMeshletData<'_>> {
         let pos_path = format!("{}/positions", meshlet_path);
-        let positions = self.read_file(&pos_path)?;
+        let positions = self.read_slice(&pos_path)?;
         
         let vert_path = format!("{}/vertices", meshlet_path);
-        let vertices = self.read_file(&vert_path)?;
+        let vertices = self.read_slice(&vert_path)?;
         
         let tri_path = format!("{}/triangles", meshlet_path);
-        let triangles = self.read_file(&tri_path)?;
+        let triangles = self.read_slice(&tri_path)?;
         
         Ok((positions, vertices, triangles))
     }
@@ -557,7 +1053,7 @@ impl BpkArchive {
         Ok(())
     }
 
-    pub fn load_meshlet_dag(&mut self, base_path: &str) -> Result<BnanMeshletDAG> {
+    pub fn load_meshlet_dag(&self, base_path: &str) -> Result<BnanMeshletDAG> {
         let meta_path = format!("{}/dag.meta", base_path);
         let dag_bytes = self.read_file(&meta_path)?;
         let dag: BnanMeshletDAG = bincode::deserialize(&dag_bytes)?;
@@ -565,3 +1061,586 @@ impl BpkArchive {
     }
 }
 
+// the stored bytes of one entry front to back, on disk entries with positional reads
+struct BpkStoredReader<'a> {
+    archive: &'a BpkArchive,
+    data: &'a BpkEntryData,
+    position: u64,
+}
+
+impl Read for BpkStoredReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let (offset, size) = match self.data {
+            BpkEntryData::InMemory(data) => {
+                let read = (&data[self.position as usize..]).read(buffer)?;
+                self.position += read as u64;
+                return std::io::Result::Ok(read);
+            }
+
+            BpkEntryData::OnDisk { offset, size } => (*offset, *size),
+        };
+
+        let read = (size - self.position).min(buffer.len() as u64) as usize;
+        let start = offset + self.position;
+
+        match (&self.archive.mmap, &self.archive.file) {
+            (Some(_), _) => buffer[..read].copy_from_slice(self.archive.mapped_range(start, read as u64).map_err(std::io::Error::other)?),
+            (None, Some(file)) => read_exact_at(file, &mut buffer[..read], start)?,
+            (None, None) => return Err(std::io::Error::other("No source file available")),
+        }
+
+        self.position += read as u64;
+        std::io::Result::Ok(read)
+    }
+}
+
+/// Pads with zeros up to the next aligned offset and returns it
+fn write_padding(writer: &mut impl Write, offset: u64) -> Result<u64> {
+    let aligned = offset.next_multiple_of(BPK_ALIGNMENT);
+    writer.write_all(&[0u8; BPK_ALIGNMENT as usize][..(aligned - offset) as usize])?;
+    Ok(aligned)
+}
+
+fn header_offsets(table_offset: u64, index_offset: u64) -> [u8; 16] {
+    let mut offsets = [0u8; 16];
+    offsets[..8].copy_from_slice(&table_offset.to_le_bytes());
+    offsets[8..].copy_from_slice(&index_offset.to_le_bytes());
+    offsets
+}
+
+/// Paths and entry types only, where the files are is in the index. Returns the size written.
+fn write_table(writer: &mut impl Write, table: &[BpkTableEntry]) -> Result<u64> {
+    writer.write_all(&(table.len() as u64).to_le_bytes())?;
+    let mut written = 8;
+
+    for entry in table {
+        writer.write_all(&(entry.path.len() as u32).to_le_bytes())?;
+        writer.write_all(entry.path.as_bytes())?;
+
+        match entry.file {
+            None => writer.write_all(&[1u8])?, // Type 1 = Directory
+            Some(_) => writer.write_all(&[0u8])?, // Type 0 = File
+        }
+
+        written += 4 + entry.path.len() as u64 + 1;
+    }
+
+    Ok(written)
+}
+
+/// Records of the files sorted by asset id, each with its path to catch lookups of other paths with the
+/// same id. Fails if two paths share an id.
+fn write_index(writer: &mut impl Write, table: &[BpkTableEntry]) -> Result<()> {
+    let mut records: Vec<(BpkAssetId, &str, u64, u64, BpkFileInfo)> = table.iter()
+        .filter_map(|entry| entry.file.map(|(offset, size, info)| (BpkAssetId::from_path(&entry.path), entry.path.as_str(), offset, size, info)))
+        .collect();
+
+    records.sort_by_key(|record| record.0);
+
+    if let Some(pair) = records.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        bail!("{} and {} have the same asset id", pair[0].1, pair[1].1);
+    }
+
+    let records_size: usize = records.iter().map(|record| BPK_INDEX_RECORD_SIZE + record.1.len()).sum();
+    writer.write_all(&(records.len() as u64).to_le_bytes())?;
+    writer.write_all(&(records_size as u64).to_le_bytes())?;
+
+    for (id, path, offset, size, info) in records {
+        writer.write_all(&id.0.to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&size.to_le_bytes())?;
+        writer.write_all(&[info.codec.to_raw()])?;
+        writer.write_all(&info.uncompressed_size.to_le_bytes())?;
+        writer.write_all(&info.checksum.unwrap_or_default())?;
+        writer.write_all(&(path.len() as u32).to_le_bytes())?;
+        writer.write_all(path.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// The whole index in one read, then parsed out of memory
+fn read_index(reader: &mut impl Read) -> Result<HashMap<BpkAssetId, BpkIndexEntry>> {
+    let mut count_buf = [0u8; 8];
+    reader.read_exact(&mut count_buf)?;
+    let count = u64::from_le_bytes(count_buf) as usize;
+
+    let mut size_buf = [0u8; 8];
+    reader.read_exact(&mut size_buf)?;
+
+    let mut records = vec![0u8; u64::from_le_bytes(size_buf) as usize];
+    reader.read_exact(&mut records)?;
+
+    let mut index = HashMap::with_capacity(count);
+    let mut rest = records.as_slice();
+
+    for _ in 0..count {
+        if rest.len() < BPK_INDEX_RECORD_SIZE {
+            bail!("BPK path index is truncated");
+        }
+
+        let (record, after_record) = rest.split_at(BPK_INDEX_RECORD_SIZE);
+        let u64_at = |start: usize| u64::from_le_bytes(record[start..start + 8].try_into().unwrap());
+
+        let path_len = u32::from_le_bytes(record[49..53].try_into().unwrap()) as usize;
+        if after_record.len() < path_len {
+            bail!("BPK path index is truncated");
+        }
+
+        let (path, after_path) = after_record.split_at(path_len);
+        rest = after_path;
+
+        let info = BpkFileInfo {
+            codec: BpkCodec::from_raw(record[24])?,
+            uncompressed_size: u64_at(25),
+            checksum: Some(record[33..49].try_into().unwrap()),
+        };
+
+        let data = BpkEntryData::OnDisk { offset: u64_at(8), size: u64_at(16) };
+        let path = std::str::from_utf8(path).context("Invalid UTF-8 in entry path")?.into();
+        index.insert(BpkAssetId(u64_at(0)), BpkIndexEntry::Stored { data, info, path });
+    }
+
+    Ok(index)
+}
+
+/// Entry table of any version as a tree, with the paths of v1 files for the codec fix up in `open`.
+/// v4 tables only list paths, their files are taken from `index`.
+fn read_table(reader: &mut impl Read, version: u32, index: &HashMap<BpkAssetId, BpkIndexEntry>) -> Result<(BpkNode, HashSet<String>)> {
+    let mut count_buf = [0u8; 8];
+    reader.read_exact(&mut count_buf)?;
+    let count = u64::from_le_bytes(count_buf);
+
+    let mut root = BpkNode::Directory { children: Vec::new() };
+    let mut v1_files = HashSet::new();
+
+    for _ in 0..count {
+        // Read Path String
+        let mut path_len_buf = [0u8; 4];
+        reader.read_exact(&mut path_len_buf)?;
+        let path_len = u32::from_le_bytes(path_len_buf) as usize;
+
+        let mut path_buf = vec![0u8; path_len];
+        reader.read_exact(&mut path_buf)?;
+        let full_path = String::from_utf8(path_buf).context("Invalid UTF-8 in entry path")?;
+
+        let mut type_buf = [0u8; 1];
+        reader.read_exact(&mut type_buf)?;
+        let is_dir = type_buf[0] == 1;
+
+        if is_dir {
+            BpkArchive::insert_node(&mut root, &full_path, BpkNode::Directory { children: Vec::new() })?;
+        } else if version == BPK_VERSION {
+            let node = match index.get(&BpkAssetId::from_path(&full_path)) {
+                Some(BpkIndexEntry::Stored { data, info, path }) if same_path(path, &full_path) => {
+                    BpkNode::File { data: data.clone(), info: *info }
+                }
+                _ => bail!("{} is missing from the path index", full_path),
+            };
+
+            BpkArchive::insert_node(&mut root, &full_path, node)?;
+        } else {
+            let mut offset_buf = [0u8; 8];
+            reader.read_exact(&mut offset_buf)?;
+            let offset = u64::from_le_bytes(offset_buf);
+
+            let mut size_buf = [0u8; 8];
+            reader.read_exact(&mut size_buf)?;
+            let size = u64::from_le_bytes(size_buf);
+
+            let info = if version == BPK_VERSION_1 {
+                v1_files.insert(full_path.clone());
+                BpkFileInfo { codec: BpkCodec::None, uncompressed_size: size, checksum: None }
+            } else {
+                let mut codec_buf = [0u8; 1];
+                reader.read_exact(&mut codec_buf)?;
+
+                let mut uncompressed_size_buf = [0u8; 8];
+                reader.read_exact(&mut uncompressed_size_buf)?;
+
+                let mut checksum = [0u8; 16];
+                reader.read_exact(&mut checksum)?;
+
+                BpkFileInfo {
+                    codec: BpkCodec::from_raw(codec_buf[0])?,
+                    uncompressed_size: u64::from_le_bytes(uncompressed_size_buf),
+                    checksum: Some(checksum),
+                }
+            };
+
+            let node = BpkNode::File {
+                data: BpkEntryData::OnDisk { offset, size },
+                info,
+            };
+
+            BpkArchive::insert_node(&mut root, &full_path, node)?;
+        }
+    }
+
+    Ok((root, v1_files))
+}
+
+// segment by segment, the way the tree and asset ids see paths
+fn same_path(a: &str, b: &str) -> bool {
+    a.split('/').filter(|s| !s.is_empty()).eq(b.split('/').filter(|s| !s.is_empty()))
+}
+
+fn collect_entries<'a>(node: &'a BpkNode, parent_path: &str, out: &mut Vec<(String, &'a BpkNode)>) {
+    if let BpkNode::Directory { children } = node {
+        for child in children {
+            let current_path = match parent_path.is_empty() {
+                true => child.name.clone(),
+                false => format!("{}/{}", parent_path, child.name),
+            };
+
+            out.push((current_path.clone(), &child.node));
+            collect_entries(&child.node, &current_path, out);
+        }
+    }
+}
+
+// pread, the shared file cursor is never touched
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    while !buffer.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buffer, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILES: [(&str, &[u8]); 4] = [
+        ("textures/wall", b"wall pixels"),
+        ("textures/wall.meta", b"wall header"),
+        ("meshes/cube/positions", &[1, 2, 3, 4, 5, 6, 7, 8]),
+        ("readme", b""),
+    ];
+
+    // a fresh path in the temp directory, removed again on drop
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("bpk_test_{}_{}.bpk", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// What the writers of versions 1 to 3 produced. v1 deflates entries with a `.meta` sidecar.
+    fn write_legacy(path: &Path, version: u32) {
+        let payloads: Vec<(&str, Vec<u8>, BpkFileInfo)> = FILES.iter().map(|(path, data)| {
+            let codec = match version == BPK_VERSION_1 && path.ends_with("wall") {
+                true => BpkCodec::Deflate,
+                false => BpkCodec::None,
+            };
+            (*path, codec.encode(data.to_vec()).unwrap(), BpkFileInfo::new(codec, data))
+        }).collect();
+
+        let mut table = Vec::new();
+        let write_entry = |table: &mut Vec<u8>, path: &str, file: Option<(u64, &[u8], BpkFileInfo)>| {
+            table.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            table.extend_from_slice(path.as_bytes());
+
+            let Some((offset, data, info)) = file else {
+                table.push(1);
+                return;
+            };
+
+            table.push(0);
+            table.extend_from_slice(&offset.to_le_bytes());
+            table.extend_from_slice(&(data.len() as u64).to_le_bytes());
+
+            if version != BPK_VERSION_1 {
+                table.push(info.codec.to_raw());
+                table.extend_from_slice(&info.uncompressed_size.to_le_bytes());
+                table.extend_from_slice(&info.checksum.unwrap());
+            }
+        };
+
+        // v1 and v2 keep the table right after the header, v3 after the payloads
+        let table_entry_size = |path: &str| 4 + path.len() as u64 + 1 + 16 + if version == BPK_VERSION_1 { 0 } else { 25 };
+        let header_size = if version == BPK_VERSION_3 { 16 } else { 8 };
+        let table_size = 8 + 3 * (4 + 1) + "textures".len() as u64 + "meshes".len() as u64 + "meshes/cube".len() as u64
+            + FILES.iter().map(|(path, _)| table_entry_size(path)).sum::<u64>();
+
+        let mut offset = match version {
+            BPK_VERSION_3 => header_size,
+            _ => header_size + table_size,
+        };
+
+        table.extend_from_slice(&7u64.to_le_bytes());
+        for directory in ["meshes", "meshes/cube", "textures"] {
+            write_entry(&mut table, directory, None);
+        }
+
+        let mut payload_bytes = Vec::new();
+        for (path, data, info) in &payloads {
+            write_entry(&mut table, path, Some((offset, data, *info)));
+            payload_bytes.extend_from_slice(data);
+            offset += data.len() as u64;
+        }
+
+        assert_eq!(table.len() as u64, table_size);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&BPK_MAGIC.to_le_bytes());
+        bytes.extend_from_slice(&version.to_le_bytes());
+
+        if version == BPK_VERSION_3 {
+            bytes.extend_from_slice(&(header_size + payload_bytes.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&payload_bytes);
+            bytes.extend_from_slice(&table);
+        } else {
+            bytes.extend_from_slice(&table);
+            bytes.extend_from_slice(&payload_bytes);
+        }
+
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    fn assert_files(archive: &BpkArchive) {
+        for (path, data) in FILES {
+            assert_eq!(archive.read_file(path).unwrap(), data, "{}", path);
+            assert_eq!(archive.read_asset(BpkAssetId::from_path(path)).unwrap(), data, "{}", path);
+        }
+    }
+
+    #[test]
+    fn every_version_round_trips_through_v4() {
+        for version in [BPK_VERSION_1, BPK_VERSION_2, BPK_VERSION_3] {
+            let legacy = TempPath::new(&format!("legacy_{}", version));
+            let saved = TempPath::new(&format!("saved_{}", version));
+            write_legacy(&legacy.0, version);
+
+            let mut archive = BpkArchive::open(&legacy.0).unwrap();
+            assert!(!archive.can_append());
+            assert_files(&archive);
+            assert!(matches!(archive.get_node("meshes/cube").unwrap(), Some(BpkNode::Directory { .. })));
+
+            archive.save(&saved.0).unwrap();
+
+            for reopened in [BpkArchive::open(&saved.0).unwrap(), BpkArchive::open_mapped(&saved.0).unwrap()] {
+                assert!(reopened.can_append());
+                assert_files(&reopened);
+
+                // v1 entries get their checksum on the first save
+                let Some(BpkNode::File { info, .. }) = reopened.get_node("textures/wall").unwrap() else {
+                    panic!("textures/wall is not a file");
+                };
+                assert!(info.checksum.is_some());
+                assert_eq!(info.uncompressed_size, b"wall pixels".len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn append_then_reopen() {
+        let path = TempPath::new("append");
+
+        let mut archive = BpkArchive::new();
+        archive.add_item("a", vec![1; 100]).unwrap();
+        archive.add_item_with_codec("dir/b", vec![2; 5000], BpkCodec::Deflate).unwrap();
+        archive.save(&path.0).unwrap();
+
+        let mut archive = BpkArchive::open_mapped(&path.0).unwrap();
+        let saved_size = std::fs::metadata(&path.0).unwrap().len();
+
+        archive.add_item("a", vec![3; 100]).unwrap();
+        archive.add_item("dir/c", vec![4; 10]).unwrap();
+        archive.remove_item("dir/b").unwrap();
+        archive.append().unwrap();
+
+        // the appended entries now come from the file, also through the remapped view
+        assert!(matches!(archive.read_slice("dir/c").unwrap(), Cow::Borrowed(_)));
+        assert!(std::fs::metadata(&path.0).unwrap().len() > saved_size);
+
+        let reopened = BpkArchive::open(&path.0).unwrap();
+        assert_eq!(reopened.read_file("a").unwrap(), vec![3; 100]);
+        assert_eq!(reopened.read_file("dir/c").unwrap(), vec![4; 10]);
+        assert!(reopened.read_file("dir/b").is_err());
+        assert!(!reopened.contains_asset(BpkAssetId::from_path("dir/b")));
+
+        archive.compact().unwrap();
+        assert!(std::fs::metadata(&path.0).unwrap().len() < saved_size + 100);
+        assert_eq!(archive.read_file("a").unwrap(), vec![3; 100]);
+        assert!(!Path::new(&format!("{}.tmp", path.0.display())).exists());
+    }
+
+    #[test]
+    fn append_without_header_update_keeps_old_archive() {
+        let path = TempPath::new("crash");
+
+        let mut archive = BpkArchive::new();
+        archive.add_item("kept", vec![1; 10]).unwrap();
+        archive.save(&path.0).unwrap();
+        let before = std::fs::read(&path.0).unwrap();
+
+        let mut archive = BpkArchive::open(&path.0).unwrap();
+        archive.add_item("lost", vec![2; 10]).unwrap();
+        archive.append().unwrap();
+
+        // a crash before the header write leaves the old offsets in place
+        let mut after = std::fs::read(&path.0).unwrap();
+        after[8..BPK_HEADER_SIZE as usize].copy_from_slice(&before[8..BPK_HEADER_SIZE as usize]);
+        std::fs::write(&path.0, after).unwrap();
+
+        let reopened = BpkArchive::open(&path.0).unwrap();
+        assert_eq!(reopened.read_file("kept").unwrap(), vec![1; 10]);
+        assert!(reopened.read_file("lost").is_err());
+    }
+
+    #[test]
+    fn save_over_own_file_then_append() {
+        let path = TempPath::new("own");
+
+        let mut archive = BpkArchive::new();
+        archive.add_item("first", vec![1; 50]).unwrap();
+        archive.save(&path.0).unwrap();
+
+        for mapped in [false, true] {
+            let mut archive = match mapped {
+                true => BpkArchive::open_mapped(&path.0).unwrap(),
+                false => BpkArchive::open(&path.0).unwrap(),
+            };
+
+            archive.add_item("second", vec![2; 50]).unwrap();
+            archive.save(&path.0).unwrap();
+            archive.add_item("third", vec![3; 50]).unwrap();
+            archive.append().unwrap();
+
+            let reopened = BpkArchive::open(&path.0).unwrap();
+            for (name, value) in [("first", 1), ("second", 2), ("third", 3)] {
+                assert_eq!(reopened.read_file(name).unwrap(), vec![value; 50]);
+            }
+        }
+    }
+
+    #[test]
+    fn v4_reads_without_parsing_the_table() {
+        let path = TempPath::new("lazy");
+
+        let mut archive = BpkArchive::new();
+        for (path, data) in FILES {
+            archive.add_item(path, data.to_vec()).unwrap();
+        }
+        archive.add_directory("empty").unwrap();
+        archive.save(&path.0).unwrap();
+
+        let archive = BpkArchive::open(&path.0).unwrap();
+        assert_files(&archive);
+        assert!(archive.tree.get().is_none());
+
+        assert!(matches!(archive.get_node("empty").unwrap(), Some(BpkNode::Directory { .. })));
+        assert!(archive.tree.get().is_some());
+        assert!(archive.read_file("meshes").unwrap_err().to_string().contains("directory"));
+        assert!(archive.read_file("missing").unwrap_err().to_string().contains("not found"));
+    }
+
+    #[test]
+    fn lookups_check_the_path_behind_an_id() {
+        let mut archive = BpkArchive::new();
+        archive.add_item("real", vec![1]).unwrap();
+
+        // pretend "ghost" hashes like "real"
+        let entry = archive.index[&BpkAssetId::from_path("real")].clone();
+        archive.index.insert(BpkAssetId::from_path("ghost"), entry);
+
+        assert!(archive.read_file("ghost").is_err());
+        assert!(archive.add_item("ghost", vec![2]).is_err());
+        assert_eq!(archive.read_file("real").unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn corrupted_payloads_fail_their_checksum() {
+        let path = TempPath::new("corrupt");
+
+        let mut archive = BpkArchive::new();
+        archive.add_item("data", vec![7; 64]).unwrap();
+        archive.save(&path.0).unwrap();
+
+        let Some(BpkNode::File { data: BpkEntryData::OnDisk { offset, .. }, .. }) = BpkArchive::open(&path.0).unwrap().get_node("data").unwrap().cloned() else {
+            panic!("data is not stored on disk");
+        };
+
+        let mut bytes = std::fs::read(&path.0).unwrap();
+        bytes[offset as usize] ^= 0xff;
+        std::fs::write(&path.0, bytes).unwrap();
+
+        let archive = BpkArchive::open(&path.0).unwrap();
+        assert!(archive.read_file("data").unwrap_err().to_string().contains("Checksum"));
+    }
+
+    #[test]
+    fn read_many_from_several_threads_matches_read_file() {
+        let path = TempPath::new("threads");
+
+        let mut archive = BpkArchive::new();
+        let paths: Vec<String> = (0..64).map(|index| format!("files/{}", index)).collect();
+        for (index, path) in paths.iter().enumerate() {
+            let codec = if index % 2 == 0 { BpkCodec::Deflate } else { BpkCodec::None };
+            archive.add_item_with_codec(path, vec![index as u8; 100 + index * 37], codec).unwrap();
+        }
+        archive.save(&path.0).unwrap();
+
+        for archive in [BpkArchive::open(&path.0).unwrap(), BpkArchive::open_mapped(&path.0).unwrap()] {
+            let expected: Vec<Vec<u8>> = paths.iter().map(|path| archive.read_file(path).unwrap()).collect();
+
+            std::thread::scope(|scope| {
+                let threads: Vec<_> = (0..4).map(|thread| {
+                    let (archive, paths) = (&archive, &paths);
+
+                    // every thread asks for its own interleaved, reversed subset
+                    scope.spawn(move || {
+                        let indices: Vec<usize> = (0..paths.len()).rev().filter(|index| index % 4 != thread).collect();
+                        let wanted: Vec<&str> = indices.iter().map(|&index| paths[index].as_str()).collect();
+                        (indices, archive.read_many(&wanted).unwrap())
+                    })
+                }).collect();
+
+                for thread in threads {
+                    let (indices, results) = thread.join().unwrap();
+                    for (index, data) in indices.into_iter().zip(results) {
+                        assert_eq!(data, expected[index], "{}", paths[index]);
+                    }
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn asset_ids_ignore_empty_segments() {
+        const WALL: BpkAssetId = BpkAssetId::from_path("textures/wall");
+
+        assert_eq!(BpkAssetId::from_path("/textures//wall/"), WALL);
+        assert_eq!(BpkAssetId::from_path("textures").join("wall"), WALL);
+        assert_eq!(BpkAssetId::from_path("").join("textures/wall"), WALL);
+        assert_ne!(BpkAssetId::from_path("textureswall"), WALL);
+
+        // FNV-1a of "a", ids must never change between releases
+        assert_eq!(BpkAssetId::from_path("a").0, 0xaf63dc4c8601ec8c);
+    }
+}
@@ -1,9 +1,14 @@
+use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::ops::{Index, IndexMut};
 
+// sparse indices per page, pages are only allocated once an index inside them is used
+const PAGE_SIZE: usize = 4096;
+const EMPTY: usize = usize::MAX;
+
 #[derive(Clone, Debug)]
 pub struct SparseSet<T> {
-    sparse: Vec<usize>,
+    sparse: Vec<Option<Box<[usize]>>>,
     dense: Vec<T>,
     dense_idx: Vec<usize>,
 }
@@ -12,13 +17,13 @@ impl<T> Index<usize> for SparseSet<T> {
     type Output = T;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.dense[self.sparse[index]]
+        self.get(index).unwrap_or_else(|| panic!("SparseSet has no element at {}", index))
     }
 }
 
 impl<T> IndexMut<usize> for SparseSet<T> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.dense[self.sparse[index]]
+        self.get_mut(index).unwrap_or_else(|| panic!("SparseSet has no element at {}", index))
     }
 }
 
@@ -32,6 +37,26 @@ impl<'a, T> Iterator for Iter<'a, T> {
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next()
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pub struct IterMut<'a, T> {
+    inner: std::slice::IterMut<'a, T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
 }
 
 impl<T> SparseSet<T> {
@@ -44,57 +69,334 @@ impl<T> SparseSet<T> {
     }
 
     pub fn contains(&self, idx: usize) -> bool {
-        self.dense_idx[self.sparse[idx]] == idx
+        self.dense_index(idx).is_some()
     }
 
-    pub fn insert(&mut self, pos: usize, elem: T) -> Option<T> {
-        if pos >= self.sparse.len() {
-            self.sparse.resize(pos + 1usize, usize::MAX);
+    /// Position of the element in the dense array
+    pub fn dense_index(&self, idx: usize) -> Option<usize> {
+        let page = self.sparse.get(idx / PAGE_SIZE)?.as_ref()?;
+
+        match page[idx % PAGE_SIZE] {
+            EMPTY => None,
+            dense_index => Some(dense_index),
         }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.dense_index(idx).map(|dense_index| &self.dense[dense_index])
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut T> {
+        self.dense_index(idx).map(|dense_index| &mut self.dense[dense_index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    /// Sparse indices in dense order
+    pub fn indices(&self) -> &[usize] {
+        &self.dense_idx
+    }
+
+    /// Elements in dense order
+    pub fn values(&self) -> &[T] {
+        &self.dense
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.dense
+    }
 
-        if self.sparse[pos] != usize::MAX {
-            let dense_index = self.sparse[pos];
-            let old_value = std::mem::replace(&mut self.dense[dense_index], elem);
-            return Some(old_value);
+    pub fn as_ptr(&self) -> *const T {
+        self.dense.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.dense.as_mut_ptr()
+    }
+
+    pub fn insert(&mut self, pos: usize, elem: T) -> Option<T> {
+        if let Some(dense_index) = self.dense_index(pos) {
+            return Some(std::mem::replace(&mut self.dense[dense_index], elem));
         }
 
-        let dense_index = self.dense.len();
+        *self.sparse_entry(pos) = self.dense.len();
 
         self.dense.push(elem);
         self.dense_idx.push(pos);
-
-        self.sparse[pos] = dense_index;
         None
     }
 
     pub fn remove(&mut self, pos: usize) -> Option<T> {
-        if pos >= self.sparse.len() {
-            return None;
-        }
+        let dense_index = self.dense_index(pos)?;
+        let last_index = self.dense.len() - 1;
 
-        let dense_index = self.sparse[pos];
+        self.swap_dense(dense_index, last_index);
+        *self.sparse_entry(pos) = EMPTY;
 
-        if dense_index == usize::MAX {
-            return None;
-        }
+        self.dense_idx.pop();
+        self.dense.pop()
+    }
 
-        let last_index = self.dense.len() - 1;
-        let last_idx = self.dense_idx[dense_index];
+    /// Removes everything, keeping the allocated pages
+    pub fn clear(&mut self) {
+        for &pos in &self.dense_idx {
+            if let Some(page) = &mut self.sparse[pos / PAGE_SIZE] {
+                page[pos % PAGE_SIZE] = EMPTY;
+            }
+        }
 
-        self.dense.swap(dense_index, last_index);
-        self.dense_idx.swap(dense_index, last_index);
+        self.dense.clear();
+        self.dense_idx.clear();
+    }
 
-        self.sparse[last_idx] = last_index;
-        self.sparse[pos] = usize::MAX;
+    /// Removes every element, yielding them with their sparse index in dense order
+    pub fn drain(&mut self) -> impl Iterator<Item = (usize, T)> + '_ {
+        for &pos in &self.dense_idx {
+            if let Some(page) = &mut self.sparse[pos / PAGE_SIZE] {
+                page[pos % PAGE_SIZE] = EMPTY;
+            }
+        }
 
-        self.dense_idx.pop();
-        self.dense.pop()
+        self.dense_idx.drain(..).zip(self.dense.drain(..))
     }
 
     pub fn iter(&self) -> Iter<'_, T> {
         Iter { inner: self.dense.iter() }
     }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut { inner: self.dense.iter_mut() }
+    }
+
+    /// Sparse indices paired with their elements, in dense order
+    pub fn iter_with_index(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.dense_idx.iter().cloned().zip(self.dense.iter())
+    }
+
+    pub fn iter_with_index_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        self.dense_idx.iter().cloned().zip(self.dense.iter_mut())
+    }
+
+    // --- Sorting ---
+
+    /// Reorders the dense array, sparse indices keep pointing at their elements
+    pub fn sort_by(&mut self, mut compare: impl FnMut(&T, &T) -> Ordering) {
+        let mut order: Vec<usize> = (0..self.dense.len()).collect();
+        order.sort_by(|&a, &b| compare(&self.dense[a], &self.dense[b]));
+
+        self.apply_order(order);
+    }
+
+    pub fn sort_by_key<K: Ord>(&mut self, mut key: impl FnMut(&T) -> K) {
+        self.sort_by(|a, b| key(a).cmp(&key(b)));
+    }
+
+    /// Sorts by sparse index
+    pub fn sort_by_index(&mut self) {
+        let mut order: Vec<usize> = (0..self.dense.len()).collect();
+        order.sort_by_key(|&position| self.dense_idx[position]);
+
+        self.apply_order(order);
+    }
+
+    /// Moves the indices also in `other` to the front, in the order `other` has them, the rest
+    /// follow in no particular order. Returns how many are shared, so `values()[..shared]` of
+    /// both sets line up element for element.
+    pub fn sort_as<U>(&mut self, other: &SparseSet<U>) -> usize {
+        let mut shared = 0;
+
+        for &pos in other.indices() {
+            if let Some(dense_index) = self.dense_index(pos) {
+                self.swap_dense(shared, dense_index);
+                shared += 1;
+            }
+        }
+
+        shared
+    }
+
+    /// `order[i]` is the current position of the element that ends up at `i`
+    fn apply_order(&mut self, mut order: Vec<usize>) {
+        for start in 0..order.len() {
+            let mut current = start;
+
+            // follow the cycle, the element from `start` travels along until its slot frees up
+            loop {
+                let next = order[current];
+                order[current] = current;
+
+                if next == start {
+                    break;
+                }
+
+                self.swap_dense(current, next);
+                current = next;
+            }
+        }
+    }
+
+    fn swap_dense(&mut self, a: usize, b: usize) {
+        self.dense.swap(a, b);
+        self.dense_idx.swap(a, b);
+
+        let (pos_a, pos_b) = (self.dense_idx[a], self.dense_idx[b]);
+        *self.sparse_entry(pos_a) = a;
+        *self.sparse_entry(pos_b) = b;
+    }
+
+    fn sparse_entry(&mut self, pos: usize) -> &mut usize {
+        let page = pos / PAGE_SIZE;
+
+        if page >= self.sparse.len() {
+            self.sparse.resize(page + 1, None);
+        }
+
+        let page = self.sparse[page].get_or_insert_with(|| vec![EMPTY; PAGE_SIZE].into_boxed_slice());
+        &mut page[pos % PAGE_SIZE]
+    }
 }
 
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
 
+    use super::*;
 
+    // xorshift, enough to shuffle operations without pulling in a crate
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+    }
+
+    // indices clustered around page boundaries, plus a few far apart pages
+    fn random_index(rng: &mut Rng) -> usize {
+        match rng.below(4) {
+            0 => PAGE_SIZE - 8 + rng.below(16) as usize,
+            1 => 3 * PAGE_SIZE - 8 + rng.below(16) as usize,
+            2 => rng.below(64) as usize * PAGE_SIZE + rng.below(PAGE_SIZE as u64) as usize,
+            _ => rng.below(64) as usize,
+        }
+    }
+
+    fn assert_matches(set: &SparseSet<u64>, model: &HashMap<usize, u64>) {
+        assert_eq!(set.len(), model.len());
+        assert_eq!(set.indices().len(), set.values().len());
+
+        for (&index, value) in model {
+            assert_eq!(set.get(index), Some(value));
+        }
+
+        for (position, &index) in set.indices().iter().enumerate() {
+            assert_eq!(set.dense_index(index), Some(position));
+            assert_eq!(model.get(&index), Some(&set.values()[position]));
+        }
+    }
+
+    #[test]
+    fn matches_hash_map_model() {
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+
+        for _ in 0..50 {
+            let mut set = SparseSet::new();
+            let mut model = HashMap::new();
+
+            for _ in 0..1000 {
+                let index = random_index(&mut rng);
+
+                match rng.below(10) {
+                    0..=3 => {
+                        let value = rng.below(1000);
+                        assert_eq!(set.insert(index, value), model.insert(index, value));
+                    }
+                    4..=5 => assert_eq!(set.remove(index), model.remove(&index)),
+                    6 => {
+                        let value = rng.below(1000);
+                        if let Some(stored) = set.get_mut(index) {
+                            *stored = value;
+                        }
+                        if let Some(stored) = model.get_mut(&index) {
+                            *stored = value;
+                        }
+                    }
+                    7 => {
+                        set.sort_by(|a, b| b.cmp(a));
+                        assert!(set.values().windows(2).all(|pair| pair[0] >= pair[1]));
+                    }
+                    8 => {
+                        let mut other = SparseSet::new();
+                        for _ in 0..rng.below(32) {
+                            other.insert(random_index(&mut rng), ());
+                        }
+
+                        let shared = set.sort_as(&other);
+                        let expected: Vec<usize> = other.indices().iter().cloned().filter(|index| model.contains_key(index)).collect();
+                        assert_eq!(shared, expected.len());
+                        assert_eq!(&set.indices()[..shared], expected.as_slice());
+                    }
+                    _ => assert_eq!(set.get(index), model.get(&index)),
+                }
+
+                assert_matches(&set, &model);
+            }
+
+            let mut drained: Vec<(usize, u64)> = set.drain().collect();
+            let mut expected: Vec<(usize, u64)> = model.into_iter().collect();
+            drained.sort();
+            expected.sort();
+
+            assert_eq!(drained, expected);
+            assert!(set.is_empty());
+            assert!(drained.iter().all(|(index, _)| !set.contains(*index)));
+        }
+    }
+
+    #[test]
+    fn page_boundaries() {
+        let mut set = SparseSet::new();
+        let indices = [0, PAGE_SIZE - 1, PAGE_SIZE, PAGE_SIZE + 1, 2 * PAGE_SIZE - 1, 2 * PAGE_SIZE, 100 * PAGE_SIZE + 7];
+
+        for (value, &index) in indices.iter().enumerate() {
+            assert_eq!(set.insert(index, value), None);
+        }
+
+        // neighbours across the boundary are not aliased
+        assert!(!set.contains(PAGE_SIZE + 2));
+        assert!(!set.contains(3 * PAGE_SIZE));
+        assert!(!set.contains(101 * PAGE_SIZE));
+
+        for (value, &index) in indices.iter().enumerate() {
+            assert_eq!(set[index], value);
+        }
+
+        set.sort_by_key(|value| std::cmp::Reverse(*value));
+        assert_eq!(set.indices()[0], 100 * PAGE_SIZE + 7);
+        assert_eq!(set.remove(PAGE_SIZE), Some(2));
+        assert_eq!(set.get(PAGE_SIZE - 1), Some(&1));
+        assert_eq!(set.get(PAGE_SIZE + 1), Some(&3));
+
+        set.clear();
+        assert!(indices.iter().all(|&index| !set.contains(index)));
+    }
+}